@@ -36,6 +36,7 @@ ARG=$(cat <<EOF
     icrc7_default_take_value = opt 500;
     icrc7_logo = opt "https://example.com/logo.png";
     icrc7_name = "Example Collection";
+    deploy_assets = opt false;
   }
 )
 EOF
@@ -0,0 +1,652 @@
+use std::time::Duration;
+use candid::Principal;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::{
+    errors::MarketError,
+    market_types::{
+        Auction, AuctionKind, AuctionStatus, Bid, BidArg, BidResult, CancelAuctionArg,
+        CreateAuctionArg, CreateAuctionResult, Payout,
+    },
+};
+use crate::{
+    ledger::{IcrcLedger, Ledger},
+    market::{collect_payment, pay_out, proceeds_payouts},
+    state::{State, STATE},
+    utils::{account_transformer, auction_account, auction_subaccount, time},
+};
+
+impl State {
+    pub fn create_auction(
+        &mut self,
+        caller: &Principal,
+        arg: CreateAuctionArg,
+    ) -> CreateAuctionResult {
+        let seller = account_transformer(Account {
+            owner: *caller,
+            subaccount: arg.from_subaccount,
+        });
+        let now = time();
+        match self.tokens.get(&arg.token_id) {
+            None => return Err(MarketError::NonExistingTokenId),
+            Some(token) => {
+                if token.token_owner != seller {
+                    return Err(MarketError::Unauthorized);
+                }
+            }
+        }
+        if self.settling_tokens.contains(&arg.token_id) {
+            return Err(MarketError::SettlementInProgress);
+        }
+        if self.is_frozen(&arg.token_id, &[&seller]) {
+            return Err(MarketError::Frozen);
+        }
+        if !self.is_transferable_by(&arg.token_id, &seller) {
+            return Err(MarketError::NonTransferable);
+        }
+        let start_at = arg.start_at.unwrap_or(now).max(now);
+        if arg.end_at <= start_at {
+            return Err(MarketError::InvalidAuction {
+                message: "End time must be after the start time".into(),
+            });
+        }
+        match arg.kind {
+            AuctionKind::English { reserve_price, .. } => {
+                if reserve_price == 0 {
+                    return Err(MarketError::InvalidPrice);
+                }
+            }
+            AuctionKind::Dutch {
+                start_price,
+                floor_price,
+                decay_interval,
+                ..
+            } => {
+                if floor_price == 0 || start_price < floor_price {
+                    return Err(MarketError::InvalidPrice);
+                }
+                if decay_interval == 0 {
+                    return Err(MarketError::InvalidAuction {
+                        message: "Decay interval must be greater than zero".into(),
+                    });
+                }
+            }
+        }
+
+        let id = self.next_auction_id;
+        self.next_auction_id += 1;
+
+        // the token stays in escrow until the auction is settled
+        self.move_token(arg.token_id, seller, auction_account(id), now);
+        self.auctions.insert(
+            id,
+            Auction {
+                id,
+                token_id: arg.token_id,
+                seller,
+                ledger: arg.ledger,
+                kind: arg.kind,
+                start_at,
+                end_at: arg.end_at,
+                highest_bid: None,
+                status: AuctionStatus::Active,
+            },
+        );
+        Ok(id)
+    }
+
+    // Validates a bid, returning the auction and the amount to be escrowed
+    pub fn prepare_bid(
+        &mut self,
+        bidder: &Account,
+        arg: &BidArg,
+        now: u64,
+    ) -> Result<(Auction, u128), MarketError> {
+        let mut auction = self
+            .auctions
+            .get(&arg.auction_id)
+            .ok_or(MarketError::AuctionNotFound)?;
+        if !auction.is_open(now) {
+            return Err(MarketError::AuctionNotActive);
+        }
+        if auction.seller == *bidder {
+            return Err(MarketError::Unauthorized);
+        }
+        if self.is_frozen(&auction.token_id, &[bidder]) {
+            return Err(MarketError::Frozen);
+        }
+        let min_bid = auction.min_bid(now).ok_or(MarketError::AuctionNotActive)?;
+        if arg.amount < min_bid {
+            return Err(MarketError::BidTooLow { min_bid });
+        }
+        match auction.kind {
+            AuctionKind::English { .. } => Ok((auction, arg.amount)),
+            AuctionKind::Dutch { .. } => {
+                // the first taker wins at the current price
+                auction.status = AuctionStatus::Settling;
+                self.auctions.insert(auction.id, auction.clone());
+                Ok((auction, min_bid))
+            }
+        }
+    }
+
+    pub fn abort_bid(&mut self, auction_id: u128) {
+        if let Some(mut auction) = self.auctions.get(&auction_id) {
+            if let AuctionKind::Dutch { .. } = auction.kind {
+                auction.status = AuctionStatus::Active;
+                self.auctions.insert(auction_id, auction);
+            }
+        }
+    }
+
+    // Records an escrowed bid, returning the outbid bid to be refunded
+    pub fn accept_bid(&mut self, auction_id: u128, bid: Bid) -> Result<Option<Bid>, MarketError> {
+        let mut auction = self
+            .auctions
+            .get(&auction_id)
+            .ok_or(MarketError::AuctionNotFound)?;
+        match auction.kind {
+            AuctionKind::English {
+                extension_window, ..
+            } => {
+                if !auction.is_open(bid.at) {
+                    return Err(MarketError::AuctionNotActive);
+                }
+                let min_bid = auction.min_bid(bid.at).unwrap_or(u128::MAX);
+                if bid.amount < min_bid {
+                    return Err(MarketError::BidTooLow { min_bid });
+                }
+                if auction.end_at - bid.at < extension_window {
+                    auction.end_at = bid.at + extension_window;
+                }
+            }
+            AuctionKind::Dutch { .. } => {
+                if auction.status != AuctionStatus::Settling || auction.highest_bid.is_some() {
+                    return Err(MarketError::AuctionNotActive);
+                }
+                auction.end_at = bid.at;
+            }
+        }
+        let outbid = auction.highest_bid.replace(bid);
+        self.auctions.insert(auction_id, auction);
+        Ok(outbid)
+    }
+
+    pub fn begin_auction_settlement(
+        &mut self,
+        auction_id: u128,
+        now: u64,
+    ) -> Result<Auction, MarketError> {
+        let mut auction = self
+            .auctions
+            .get(&auction_id)
+            .ok_or(MarketError::AuctionNotFound)?;
+        let ready = match auction.status {
+            AuctionStatus::Active => now >= auction.end_at,
+            // a Dutch auction is settled right after its winning bid
+            AuctionStatus::Settling => auction.highest_bid.is_some(),
+            _ => false,
+        };
+        if !ready {
+            return Err(MarketError::AuctionNotEnded);
+        }
+        // the token stays in escrow while frozen, or while the collection is paused
+        if self.paused {
+            return Err(MarketError::Paused);
+        }
+        let mut accounts = vec![&auction.seller];
+        accounts.extend(auction.highest_bid.as_ref().map(|bid| &bid.to));
+        if self.is_frozen(&auction.token_id, &accounts) {
+            return Err(MarketError::Frozen);
+        }
+        auction.status = AuctionStatus::Settling;
+        self.auctions.insert(auction_id, auction.clone());
+        Ok(auction)
+    }
+
+    // Hands the token to the winner, or back to the seller, returning the payouts due
+    pub fn finish_auction_settlement(&mut self, auction_id: u128, now: u64) -> Vec<Payout> {
+        let mut auction = match self.auctions.get(&auction_id) {
+            Some(auction) => auction,
+            None => return vec![],
+        };
+        let escrow = auction_account(auction_id);
+        let payouts = match auction.highest_bid {
+            Some(ref bid) => {
+                self.move_token(auction.token_id, escrow, bid.to, now);
+                auction.status = AuctionStatus::Settled;
+                let shares = self.sale_shares(auction.token_id);
+                proceeds_payouts(
+                    auction.ledger,
+                    auction_subaccount(auction_id),
+                    bid.amount,
+                    &shares,
+                    self.proceeds_recipient(auction.seller),
+                )
+            }
+            None => {
+                self.move_token(auction.token_id, escrow, auction.seller, now);
+                auction.status = AuctionStatus::Unsold;
+                vec![]
+            }
+        };
+        self.auctions.insert(auction_id, auction);
+        payouts
+    }
+
+    // Hands the token back to the seller of an auction nobody bid on yet
+    pub fn cancel_auction(
+        &mut self,
+        caller: &Principal,
+        arg: &CancelAuctionArg,
+        now: u64,
+    ) -> Result<(), MarketError> {
+        let seller = account_transformer(Account {
+            owner: *caller,
+            subaccount: arg.from_subaccount,
+        });
+        let mut auction = self
+            .auctions
+            .get(&arg.auction_id)
+            .ok_or(MarketError::AuctionNotFound)?;
+        if auction.seller != seller {
+            return Err(MarketError::Unauthorized);
+        }
+        if auction.status != AuctionStatus::Active {
+            return Err(MarketError::AuctionNotActive);
+        }
+        if auction.highest_bid.is_some() {
+            return Err(MarketError::InvalidAuction {
+                message: "An auction with bids cannot be cancelled".into(),
+            });
+        }
+        self.move_token(auction.token_id, auction_account(auction.id), seller, now);
+        auction.status = AuctionStatus::Unsold;
+        self.auctions.insert(auction.id, auction);
+        Ok(())
+    }
+
+    // The auctions a settlement timer has to be armed for
+    pub fn pending_auction_settlements(&self) -> Vec<(u128, u64)> {
+        self.auctions
+            .iter()
+            .filter(|(_, auction)| auction.status == AuctionStatus::Active)
+            .map(|(id, auction)| (id, auction.end_at))
+            .collect()
+    }
+
+    pub fn get_auction(&self, auction_id: u128) -> Option<Auction> {
+        self.auctions.get(&auction_id)
+    }
+
+    pub fn get_auctions(&self, prev: Option<u128>, take: Option<u128>) -> Vec<Auction> {
+        let take = self.get_current_take(take);
+        let start = prev.map_or(0, |prev| prev + 1);
+        self.auctions
+            .range(start..)
+            .map(|(_, auction)| auction)
+            .filter(|auction| auction.status == AuctionStatus::Active)
+            .take(take as usize)
+            .collect()
+    }
+
+    pub fn auction_min_bid(&self, auction_id: u128) -> Option<u128> {
+        let now = time();
+        self.auctions
+            .get(&auction_id)
+            .filter(|auction| auction.is_open(now))
+            .and_then(|auction| auction.min_bid(now))
+    }
+}
+
+pub async fn bid(caller: Principal, arg: BidArg) -> BidResult {
+    let end_at = STATE.with_borrow(|s| s.auctions.get(&arg.auction_id).map(|a| a.end_at));
+    let auction_id = bid_with(IcrcLedger, caller, arg).await?;
+    // a bid close to the end pushes it back, the settlement then waiting for the new end
+    let auction = STATE.with_borrow(|s| s.auctions.get(&auction_id));
+    if let Some(auction) = auction {
+        if auction.status == AuctionStatus::Active && Some(auction.end_at) != end_at {
+            schedule_auction_settlement(auction_id, auction.end_at);
+        }
+    }
+    Ok(auction_id)
+}
+
+// The bidding flow, paying through whichever ledger `ledger_of` gives for the auction's one
+pub(crate) async fn bid_with<L: Ledger>(
+    ledger_of: impl Fn(Principal) -> L,
+    caller: Principal,
+    arg: BidArg,
+) -> BidResult {
+    let bidder = account_transformer(Account {
+        owner: caller,
+        subaccount: arg.from_subaccount,
+    });
+    let to = account_transformer(arg.to.unwrap_or(bidder));
+    let (auction, amount) = STATE.with_borrow_mut(|s| s.prepare_bid(&bidder, &arg, time()))?;
+    let ledger = ledger_of(auction.ledger);
+
+    let fee = match ledger.fee().await {
+        Ok(fee) => fee,
+        Err(message) => {
+            STATE.with_borrow_mut(|s| s.abort_bid(auction.id));
+            return Err(MarketError::PaymentFailed { message });
+        }
+    };
+
+    // 1st: escrow the bid in the auction subaccount
+    if let Err(message) =
+        collect_payment(&ledger, bidder, auction_account(auction.id), amount, fee).await
+    {
+        STATE.with_borrow_mut(|s| s.abort_bid(auction.id));
+        return Err(MarketError::PaymentFailed { message });
+    }
+
+    // 2nd: record it, refunding whoever does not hold the highest bid anymore
+    let bid = Bid {
+        bidder,
+        to,
+        amount,
+        at: time(),
+    };
+    let res = STATE.with_borrow_mut(|s| s.accept_bid(auction.id, bid.clone()));
+    let refund = match res {
+        Ok(outbid) => outbid,
+        Err(e) => {
+            refund_bids(&ledger, auction.id, vec![bid], fee).await;
+            return Err(e);
+        }
+    };
+    if let Some(outbid) = refund {
+        refund_bids(&ledger, auction.id, vec![outbid], fee).await;
+    }
+
+    if let AuctionKind::Dutch { .. } = auction.kind {
+        settle_auction_with(ledger_of, auction.id).await?;
+    }
+    Ok(auction.id)
+}
+
+pub async fn settle_auction(auction_id: u128) -> Result<(), MarketError> {
+    settle_auction_with(IcrcLedger, auction_id).await
+}
+
+pub(crate) async fn settle_auction_with<L: Ledger>(
+    ledger_of: impl Fn(Principal) -> L,
+    auction_id: u128,
+) -> Result<(), MarketError> {
+    let auction = STATE.with_borrow_mut(|s| s.begin_auction_settlement(auction_id, time()))?;
+    let payouts = STATE.with_borrow_mut(|s| s.finish_auction_settlement(auction_id, time()));
+    if payouts.is_empty() {
+        return Ok(());
+    }
+
+    let ledger = ledger_of(auction.ledger);
+    let failed = match ledger.fee().await {
+        Ok(fee) => pay_out(&ledger, payouts, fee).await,
+        Err(_) => payouts,
+    };
+    STATE.with_borrow_mut(|s| s.record_failed_payouts(failed));
+    Ok(())
+}
+
+async fn refund_bids<L: Ledger>(ledger: &L, auction_id: u128, bids: Vec<Bid>, fee: u128) {
+    let ledger_id = STATE.with_borrow(|s| s.auctions.get(&auction_id).map(|a| a.ledger));
+    let Some(ledger_id) = ledger_id else {
+        return;
+    };
+    let refunds = bids
+        .into_iter()
+        .map(|bid| Payout {
+            ledger: ledger_id,
+            from_subaccount: Some(auction_subaccount(auction_id)),
+            to: bid.bidder,
+            amount: bid.amount,
+        })
+        .collect();
+    let failed = pay_out(ledger, refunds, fee).await;
+    STATE.with_borrow_mut(|s| s.record_failed_payouts(failed));
+}
+
+// Settles the auction once it ends. Timers firing before an extended end are no-ops.
+pub fn schedule_auction_settlement(auction_id: u128, end_at: u64) {
+    let delay = Duration::from_nanos(end_at.saturating_sub(time()));
+    ic_cdk_timers::set_timer(delay, move || {
+        ic_cdk::spawn(async move {
+            let _ = settle_auction(auction_id).await;
+        });
+    });
+}
+
+// Timers do not survive upgrades, so they are re-armed for every active auction
+pub fn schedule_auction_timers() {
+    let auctions = STATE.with_borrow(|s| s.pending_auction_settlements());
+    for (auction_id, end_at) in auctions {
+        schedule_auction_settlement(auction_id, end_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use icrc7_types::compliance_types::{FreezeArg, FreezeTarget};
+    use crate::{
+        market::tests::{account, block_on, LocalLedger},
+        state::Icrc7Token,
+        utils::{canister_id, default_account, set_time},
+    };
+
+    fn user(n: u8) -> Account {
+        default_account(&account(n).owner)
+    }
+
+    // Puts token 1 of user 1 up for auction until 1_000
+    fn auctioned_token(ledger: &LocalLedger, kind: AuctionKind) -> u128 {
+        STATE.with_borrow_mut(|s| {
+            let token = Icrc7Token {
+                token_id: 1,
+                token_owner: user(1),
+                token_meta: Default::default(),
+                edition: None,
+                token_user: None,
+            };
+            s.tokens.insert(1, token);
+            let arg = CreateAuctionArg {
+                from_subaccount: None,
+                token_id: 1,
+                ledger: ledger.canister,
+                kind,
+                start_at: None,
+                end_at: 1_000,
+            };
+            s.create_auction(&user(1).owner, arg).unwrap()
+        })
+    }
+
+    fn place_bid(ledger: &LocalLedger, n: u8, auction_id: u128, amount: u128) -> BidResult {
+        let arg = BidArg {
+            from_subaccount: None,
+            auction_id,
+            amount,
+            to: None,
+        };
+        block_on(bid_with(|_| ledger, user(n).owner, arg))
+    }
+
+    fn owner_of(token_id: u128) -> Account {
+        STATE.with_borrow(|s| s.tokens.get(&token_id).unwrap().token_owner)
+    }
+
+    #[test]
+    fn english_auctions_refund_outbid_bidders_and_pay_the_seller() {
+        let ledger = LocalLedger::new(canister_id(), 10);
+        let kind = AuctionKind::English {
+            reserve_price: 1_000,
+            min_increment: 100,
+            extension_window: 10,
+        };
+        let id = auctioned_token(&ledger, kind);
+        assert_eq!(owner_of(1), auction_account(id));
+        ledger.mint(user(2), 2_210);
+        ledger.mint(user(3), 1_110);
+
+        let res = place_bid(&ledger, 2, id, 900);
+        assert!(matches!(res, Err(MarketError::BidTooLow { min_bid: 1_000 })));
+        assert_eq!(place_bid(&ledger, 2, id, 1_000).ok(), Some(id));
+        let res = place_bid(&ledger, 3, id, 1_050);
+        assert!(matches!(res, Err(MarketError::BidTooLow { min_bid: 1_100 })));
+        assert_eq!(place_bid(&ledger, 3, id, 1_100).ok(), Some(id));
+        assert_eq!(ledger.balance(&user(2)), 2_190);
+        assert_eq!(ledger.balance(&auction_account(id)), 1_100);
+
+        // a late bid pushes the end back
+        set_time(995);
+        assert_eq!(place_bid(&ledger, 2, id, 1_200).ok(), Some(id));
+        assert_eq!(STATE.with_borrow(|s| s.auctions.get(&id).unwrap().end_at), 1_005);
+        assert_eq!(ledger.balance(&user(3)), 1_090);
+        let res = block_on(settle_auction_with(|_| &ledger, id));
+        assert!(matches!(res, Err(MarketError::AuctionNotEnded)));
+
+        set_time(1_005);
+        assert!(block_on(settle_auction_with(|_| &ledger, id)).is_ok());
+        assert_eq!(owner_of(1), user(2));
+        assert_eq!(ledger.balance(&user(1)), 1_190);
+        assert_eq!(ledger.balance(&auction_account(id)), 0);
+        let status = STATE.with_borrow(|s| s.auctions.get(&id).unwrap().status);
+        assert_eq!(status, AuctionStatus::Settled);
+        assert!(block_on(settle_auction_with(|_| &ledger, id)).is_err());
+    }
+
+    #[test]
+    fn dutch_auctions_reopen_when_the_payment_fails() {
+        let ledger = LocalLedger::new(canister_id(), 10);
+        let kind = AuctionKind::Dutch {
+            start_price: 1_000,
+            floor_price: 500,
+            decay_amount: 100,
+            decay_interval: 10,
+        };
+        let id = auctioned_token(&ledger, kind);
+        set_time(20);
+        let res = place_bid(&ledger, 2, id, 700);
+        assert!(matches!(res, Err(MarketError::BidTooLow { min_bid: 800 })));
+
+        // the auction is reserved for the taker while the payment is collected
+        let arg = BidArg {
+            from_subaccount: None,
+            auction_id: id,
+            amount: 800,
+            to: None,
+        };
+        STATE.with_borrow_mut(|s| s.prepare_bid(&user(2), &arg, 20)).unwrap();
+        let status = STATE.with_borrow(|s| s.auctions.get(&id).unwrap().status);
+        assert_eq!(status, AuctionStatus::Settling);
+        assert!(matches!(place_bid(&ledger, 3, id, 800), Err(MarketError::AuctionNotActive)));
+        STATE.with_borrow_mut(|s| s.abort_bid(id));
+        let status = STATE.with_borrow(|s| s.auctions.get(&id).unwrap().status);
+        assert_eq!(status, AuctionStatus::Active);
+
+        let res = place_bid(&ledger, 2, id, 800);
+        assert!(matches!(res, Err(MarketError::PaymentFailed { .. })));
+        let status = STATE.with_borrow(|s| s.auctions.get(&id).unwrap().status);
+        assert_eq!(status, AuctionStatus::Active);
+
+        // the first taker pays the current price, whatever it offered
+        ledger.mint(user(2), 1_010);
+        assert_eq!(place_bid(&ledger, 2, id, 1_000).ok(), Some(id));
+        assert_eq!(owner_of(1), user(2));
+        assert_eq!(ledger.balance(&user(2)), 200);
+        assert_eq!(ledger.balance(&user(1)), 790);
+    }
+
+    #[test]
+    fn frozen_or_paused_auctions_keep_the_token_in_escrow() {
+        let ledger = LocalLedger::new(canister_id(), 10);
+        let kind = AuctionKind::English {
+            reserve_price: 1_000,
+            min_increment: 100,
+            extension_window: 0,
+        };
+        let id = auctioned_token(&ledger, kind);
+        ledger.mint(user(2), 2_020);
+        assert!(place_bid(&ledger, 2, id, 1_000).is_ok());
+
+        // the token is reported stolen while in escrow
+        let freeze = || FreezeArg {
+            target: FreezeTarget::Token(1),
+            reason: "stolen".into(),
+        };
+        STATE.with_borrow_mut(|s| s.freeze(user(4), freeze(), 0)).unwrap();
+        assert!(matches!(place_bid(&ledger, 2, id, 1_100), Err(MarketError::Frozen)));
+        set_time(1_000);
+        let settle = || block_on(settle_auction_with(|_| &ledger, id));
+        assert!(matches!(settle(), Err(MarketError::Frozen)));
+        assert_eq!(owner_of(1), auction_account(id));
+        assert_eq!(ledger.balance(&user(1)), 0);
+        let status = STATE.with_borrow(|s| s.auctions.get(&id).unwrap().status);
+        assert_eq!(status, AuctionStatus::Active);
+
+        STATE.with_borrow_mut(|s| {
+            s.unfreeze(user(4), freeze(), 0).unwrap();
+            s.paused = true;
+        });
+        assert!(matches!(settle(), Err(MarketError::Paused)));
+        assert_eq!(owner_of(1), auction_account(id));
+        STATE.with_borrow_mut(|s| s.paused = false);
+        assert!(settle().is_ok());
+        assert_eq!(owner_of(1), user(2));
+    }
+
+    #[test]
+    fn auctions_without_bids_are_cancelled_or_unsold() {
+        let ledger = LocalLedger::new(canister_id(), 10);
+        let kind = AuctionKind::English {
+            reserve_price: 1_000,
+            min_increment: 100,
+            extension_window: 0,
+        };
+        let first = auctioned_token(&ledger, kind.clone());
+        let cancel = |n: u8, auction_id| {
+            let arg = CancelAuctionArg {
+                from_subaccount: None,
+                auction_id,
+            };
+            STATE.with_borrow_mut(|s| s.cancel_auction(&user(n).owner, &arg, time()))
+        };
+        assert!(matches!(cancel(2, first), Err(MarketError::Unauthorized)));
+        assert!(cancel(1, first).is_ok());
+        assert_eq!(owner_of(1), user(1));
+        assert!(matches!(cancel(1, first), Err(MarketError::AuctionNotActive)));
+
+        let second = auctioned_token(&ledger, kind.clone());
+        let third = STATE.with_borrow_mut(|s| {
+            let mut token = s.tokens.get(&1).unwrap();
+            token.token_id = 2;
+            token.token_owner = user(1);
+            s.tokens.insert(2, token);
+            let arg = CreateAuctionArg {
+                from_subaccount: None,
+                token_id: 2,
+                ledger: ledger.canister,
+                kind,
+                start_at: None,
+                end_at: 2_000,
+            };
+            s.create_auction(&user(1).owner, arg).unwrap()
+        });
+        ledger.mint(user(2), 1_010);
+        assert!(place_bid(&ledger, 2, third, 1_000).is_ok());
+        assert!(matches!(cancel(1, third), Err(MarketError::InvalidAuction { .. })));
+
+        // timers are armed again after an upgrade for the auctions still running
+        let pending = STATE.with_borrow(|s| s.pending_auction_settlements());
+        assert_eq!(pending, vec![(second, 1_000), (third, 2_000)]);
+
+        set_time(1_000);
+        assert!(block_on(settle_auction_with(|_| &ledger, second)).is_ok());
+        assert_eq!(owner_of(1), user(1));
+        let status = STATE.with_borrow(|s| s.auctions.get(&second).unwrap().status);
+        assert_eq!(status, AuctionStatus::Unsold);
+        let pending = STATE.with_borrow(|s| s.pending_auction_settlements());
+        assert_eq!(pending, vec![(third, 2_000)]);
+    }
+}
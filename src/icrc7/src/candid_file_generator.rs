@@ -1,9 +1,18 @@
 use candid::export_service;
 use candid::{Nat, Principal};
 use ic_cdk_macros::query;
-use icrc_ledger_types::{icrc1::account::Account, icrc3::blocks::DataCertificate};
+use serde_bytes::ByteBuf;
+use icrc_ledger_types::{
+    icrc::generic_value::Value, icrc1::account::Account, icrc3::blocks::DataCertificate,
+};
 use crate::cycles::WalletReceiveResult;
-use icrc7_types::{icrc3_types::*, icrc7_types::*, icrc37_types::*};
+use icrc7_types::{
+    composable_types::*, compliance_types::*, errors::{MarketError, MediaError, MultisigError},
+    history_types::*, holder_types::*, http_types::*, icrc3_types::*, icrc7_types::*,
+    icrc37_types::*, market_types::*, media_types::*, migration_types::*, multisig_types::*,
+    rbac_types::*, rental_types::*, replay_types::*, revenue_types::*, schema_types::*,
+    series_types::*, token_account_types::*, trait_types::*,
+};
 
 #[query(name = "__get_candid_interface_tmp_hack")]
 fn export_candid() -> String {
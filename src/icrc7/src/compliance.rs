@@ -0,0 +1,194 @@
+use std::ops::Bound;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::{
+    compliance_types::{ComplianceResult, FreezeArg, FreezeRecord, FreezeTarget},
+    icrc37_types::UserAccount,
+    icrc7_types::TransactionType,
+};
+use crate::{state::State, utils::account_transformer};
+
+impl State {
+    pub fn pause(&mut self, by: Account, reason: String, at: u64) -> ComplianceResult {
+        self.check_reason(&reason)?;
+        if self.paused {
+            return Err("The collection is already paused".into());
+        }
+        self.paused = true;
+        Ok(self.log_transaction(TransactionType::Pause { by }, at, Some(reason.into_bytes())))
+    }
+
+    pub fn unpause(&mut self, by: Account, reason: String, at: u64) -> ComplianceResult {
+        self.check_reason(&reason)?;
+        if !self.paused {
+            return Err("The collection is not paused".into());
+        }
+        self.paused = false;
+        self.export_prepared = false;
+        Ok(self.log_transaction(TransactionType::Unpause { by }, at, Some(reason.into_bytes())))
+    }
+
+    pub fn freeze(&mut self, by: Account, arg: FreezeArg, at: u64) -> ComplianceResult {
+        self.check_reason(&arg.reason)?;
+        let record = FreezeRecord {
+            reason: arg.reason.clone(),
+            by,
+            at,
+        };
+        let txn_type = match arg.target {
+            FreezeTarget::Token(tid) => {
+                if !self.tokens.contains_key(&tid) {
+                    return Err("Non existing token id".into());
+                }
+                if self.frozen_tokens.insert(tid, record).is_some() {
+                    return Err("The token is already frozen".into());
+                }
+                self.listings.remove(&tid);
+                TransactionType::FreezeToken { tid, by }
+            }
+            FreezeTarget::Account(account) => {
+                let account = account_transformer(account);
+                if self
+                    .frozen_accounts
+                    .insert(UserAccount::new(account), record)
+                    .is_some()
+                {
+                    return Err("The account is already frozen".into());
+                }
+                TransactionType::FreezeAccount { account, by }
+            }
+        };
+        Ok(self.log_transaction(txn_type, at, Some(arg.reason.into_bytes())))
+    }
+
+    pub fn unfreeze(&mut self, by: Account, arg: FreezeArg, at: u64) -> ComplianceResult {
+        self.check_reason(&arg.reason)?;
+        let txn_type = match arg.target {
+            FreezeTarget::Token(tid) => {
+                if self.frozen_tokens.remove(&tid).is_none() {
+                    return Err("The token is not frozen".into());
+                }
+                TransactionType::UnfreezeToken { tid, by }
+            }
+            FreezeTarget::Account(account) => {
+                let account = account_transformer(account);
+                if self
+                    .frozen_accounts
+                    .remove(&UserAccount::new(account))
+                    .is_none()
+                {
+                    return Err("The account is not frozen".into());
+                }
+                TransactionType::UnfreezeAccount { account, by }
+            }
+        };
+        Ok(self.log_transaction(txn_type, at, Some(arg.reason.into_bytes())))
+    }
+
+    // The reason is logged as the block memo, so it is bounded like any other memo
+    fn check_reason(&self, reason: &str) -> Result<(), String> {
+        let max_memo_size = self.icrc7_max_memo_size.unwrap_or(State::DEFAULT_MAX_MEMO_SIZE);
+        if reason.len() as u32 > max_memo_size {
+            return Err("Exceeds Max Memo Length".into());
+        }
+        Ok(())
+    }
+
+    // Whether the token, or any of the accounts involved, is frozen
+    pub fn is_frozen(&self, token_id: &u128, accounts: &[&Account]) -> bool {
+        self.frozen_tokens.contains_key(token_id) || self.is_any_account_frozen(accounts)
+    }
+
+    pub fn is_any_account_frozen(&self, accounts: &[&Account]) -> bool {
+        accounts
+            .iter()
+            .any(|account| self.frozen_accounts.contains_key(&UserAccount::new(**account)))
+    }
+
+    pub fn get_freezes(&self, targets: Vec<FreezeTarget>) -> Vec<Option<FreezeRecord>> {
+        targets
+            .into_iter()
+            .map(|target| match target {
+                FreezeTarget::Token(tid) => self.frozen_tokens.get(&tid),
+                FreezeTarget::Account(account) => {
+                    self.frozen_accounts.get(&UserAccount::new(account_transformer(account)))
+                }
+            })
+            .collect()
+    }
+
+    pub fn get_frozen_tokens(
+        &self,
+        prev: Option<u128>,
+        take: Option<u128>,
+    ) -> Vec<(u128, FreezeRecord)> {
+        let take = self.get_current_take(take);
+        let start = prev.map_or(0, |prev| prev + 1);
+        self.frozen_tokens
+            .range(start..)
+            .take(take as usize)
+            .collect()
+    }
+
+    pub fn get_frozen_accounts(
+        &self,
+        prev: Option<Account>,
+        take: Option<u128>,
+    ) -> Vec<(Account, FreezeRecord)> {
+        let take = self.get_current_take(take);
+        let start = prev.map_or(Bound::Unbounded, |prev| {
+            Bound::Excluded(UserAccount::new(account_transformer(prev)))
+        });
+        self.frozen_accounts
+            .range((start, Bound::Unbounded))
+            .take(take as usize)
+            .map(|(account, record)| (account.into(), record))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use icrc7_types::{errors::TransferError, icrc7_types::TransferArg};
+    use crate::{market::tests::account, state::Icrc7Token};
+
+    #[test]
+    fn frozen_accounts_cannot_transfer_from_their_default_subaccount() {
+        let mut state = State::default();
+        let owner = account(1);
+        let token = Icrc7Token {
+            token_id: 1,
+            token_owner: account_transformer(owner),
+            token_meta: Default::default(),
+            edition: None,
+            token_user: None,
+        };
+        state.tokens.insert(1, token);
+        let freeze = |target| FreezeArg {
+            target,
+            reason: "Reported stolen".into(),
+        };
+        // Frozen without a subaccount, it is the default subaccount that gets frozen
+        state.freeze(account(9), freeze(FreezeTarget::Account(owner)), 0).unwrap();
+        let normalized = FreezeTarget::Account(account_transformer(owner));
+        assert!(state.get_freezes(vec![normalized])[0].is_some());
+
+        let arg = TransferArg {
+            from_subaccount: None,
+            to: account(2),
+            token_id: 1,
+            memo: None,
+            created_at_time: None,
+        };
+        let res = state.icrc7_transfer(&owner.owner, vec![arg]);
+        assert!(matches!(res[0], Some(Err(TransferError::Frozen))));
+
+        let reason = "x".repeat(State::DEFAULT_MAX_MEMO_SIZE as usize + 1);
+        let arg = FreezeArg {
+            target: FreezeTarget::Token(1),
+            reason,
+        };
+        assert!(state.freeze(account(9), arg, 0).is_err());
+        assert!(state.get_freezes(vec![FreezeTarget::Token(1)])[0].is_none());
+    }
+}
@@ -0,0 +1,283 @@
+use candid::Principal;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::{
+    composable_types::{EquipArg, EquipResult, UnequipArg},
+    errors::EquipError,
+    icrc7_types::TransactionType,
+};
+use crate::{
+    state::{Icrc7Token, State},
+    utils::{account_transformer, token_subaccount},
+};
+
+impl State {
+    // Equips a token to another one owned by the same root owner: the token moves to the
+    // parent's account and from then on goes wherever the parent goes.
+    pub fn equip(
+        &mut self,
+        caller: &Principal,
+        arg: EquipArg,
+        canister: Principal,
+        now: u64,
+    ) -> EquipResult {
+        let caller = account_transformer(Account {
+            owner: *caller,
+            subaccount: arg.from_subaccount,
+        });
+        let mut token = self
+            .tokens
+            .get(&arg.token_id)
+            .ok_or(EquipError::NonExistingTokenId)?;
+        let parent = self
+            .tokens
+            .get(&arg.parent_id)
+            .ok_or(EquipError::NonExistingTokenId)?;
+        if self.root_owner_of(&token) != caller || self.root_owner_of(&parent) != caller {
+            return Err(EquipError::Unauthorized);
+        }
+        if arg.token_id == arg.parent_id || self.ancestors_of(arg.parent_id).contains(&arg.token_id)
+        {
+            return Err(EquipError::CyclicEquip);
+        }
+        if self.parents.get(&arg.token_id) == Some(&arg.parent_id) {
+            return Err(EquipError::GenericError {
+                error_code: 2,
+                message: "Already Equipped".into(),
+            });
+        }
+        if self.is_frozen(&arg.token_id, &[&caller]) || self.is_frozen(&arg.parent_id, &[]) {
+            return Err(EquipError::Frozen);
+        }
+        if !self.is_transferable_by(&arg.token_id, &caller) {
+            return Err(EquipError::NonTransferable);
+        }
+        if self.settling_tokens.contains(&arg.token_id) {
+            return Err(EquipError::SettlementInProgress);
+        }
+        self.check_equip_memo(&arg.memo)?;
+
+        let from = token.token_owner;
+        let to = Account {
+            owner: canister,
+            subaccount: Some(token_subaccount(arg.parent_id)),
+        };
+        token.transfer(to);
+        self.tokens.insert(arg.token_id, token);
+        self.token_approvals_clean(&arg.token_id);
+        self.token_moved(&arg.token_id);
+        self.parents.insert(arg.token_id, arg.parent_id);
+        Ok(self.log_transaction(
+            TransactionType::Equip {
+                tid: arg.token_id,
+                from,
+                to,
+                by: caller,
+            },
+            now,
+            arg.memo,
+        ))
+    }
+
+    // Takes an equipped token out of its parent, back to the root owner
+    pub fn unequip(&mut self, caller: &Principal, arg: UnequipArg, now: u64) -> EquipResult {
+        let caller = account_transformer(Account {
+            owner: *caller,
+            subaccount: arg.from_subaccount,
+        });
+        let mut token = self
+            .tokens
+            .get(&arg.token_id)
+            .ok_or(EquipError::NonExistingTokenId)?;
+        if !self.parents.contains_key(&arg.token_id) {
+            return Err(EquipError::NotEquipped);
+        }
+        if self.root_owner_of(&token) != caller {
+            return Err(EquipError::Unauthorized);
+        }
+        if self.is_frozen(&arg.token_id, &[&caller]) {
+            return Err(EquipError::Frozen);
+        }
+        self.check_equip_memo(&arg.memo)?;
+
+        let from = token.token_owner;
+        token.transfer(caller);
+        self.tokens.insert(arg.token_id, token);
+        self.parents.remove(&arg.token_id);
+        Ok(self.log_transaction(
+            TransactionType::Unequip {
+                tid: arg.token_id,
+                from,
+                to: caller,
+            },
+            now,
+            arg.memo,
+        ))
+    }
+
+    fn check_equip_memo(&self, memo: &Option<Vec<u8>>) -> Result<(), EquipError> {
+        let max_memo_size = self.icrc7_max_memo_size.unwrap_or(State::DEFAULT_MAX_MEMO_SIZE);
+        if memo.as_ref().is_some_and(|memo| memo.len() as u32 > max_memo_size) {
+            return Err(EquipError::GenericError {
+                error_code: 1,
+                message: "Exceeds Max Memo Length".into(),
+            });
+        }
+        Ok(())
+    }
+
+    // The owner of the outermost parent, the one in control of a token and all it holds
+    pub fn root_owner_of(&self, token: &Icrc7Token) -> Account {
+        let root_id = self.ancestors_of(token.token_id).pop();
+        root_id
+            .and_then(|root_id| self.tokens.get(&root_id))
+            .map_or(token.token_owner, |root| root.token_owner)
+    }
+
+    // The parents of a token, innermost first
+    pub fn ancestors_of(&self, token_id: u128) -> Vec<u128> {
+        let mut ancestors = vec![];
+        let mut current = token_id;
+        while let Some(parent_id) = self.parents.get(&current) {
+            ancestors.push(*parent_id);
+            current = *parent_id;
+        }
+        ancestors
+    }
+
+    pub fn children_of(&self, parent_id: u128) -> Vec<u128> {
+        self.parents
+            .iter()
+            .filter(|(_, parent)| **parent == parent_id)
+            .map(|(child, _)| *child)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use icrc7_types::{
+        errors::{TransferError, TransferFromError},
+        icrc37_types::TransferFromArg,
+        icrc7_types::TransferArg,
+    };
+    use crate::market::tests::account;
+
+    #[test]
+    fn equipped_tokens_follow_their_parent() {
+        let canister = Principal::from_slice(&[9]);
+        let owner = account_transformer(account(1));
+        let mut state = State::default();
+        for token_id in 1..=3 {
+            state.tokens.insert(
+                token_id,
+                Icrc7Token {
+                    token_id,
+                    token_owner: owner,
+                    token_meta: Default::default(),
+                    edition: None,
+                    token_user: None,
+                },
+            );
+        }
+        let equip = |token_id, parent_id| EquipArg {
+            from_subaccount: None,
+            token_id,
+            parent_id,
+            memo: None,
+        };
+
+        // 3 into 2 into 1
+        state.parents.insert(3, 2);
+        let mut child = state.tokens.get(&3).unwrap();
+        child.transfer(Account {
+            owner: canister,
+            subaccount: Some(token_subaccount(2)),
+        });
+        state.tokens.insert(3, child);
+        state.parents.insert(2, 1);
+        let mut child = state.tokens.get(&2).unwrap();
+        child.transfer(Account {
+            owner: canister,
+            subaccount: Some(token_subaccount(1)),
+        });
+        state.tokens.insert(2, child);
+
+        assert_eq!(
+            state.equip(&account(1).owner, equip(1, 3), canister, 1),
+            Err(EquipError::CyclicEquip)
+        );
+        assert_eq!(
+            state.equip(&account(1).owner, equip(1, 1), canister, 1),
+            Err(EquipError::CyclicEquip)
+        );
+        assert_eq!(state.ancestors_of(3), vec![2, 1]);
+        assert_eq!(state.children_of(1), vec![2]);
+        assert_eq!(state.icrc7_owner_of(&[1, 2, 3]), vec![Some(owner); 3]);
+        assert_eq!(state.icrc7_balance_of(&[owner]), vec![3]);
+
+        // moving the root carries everything it holds
+        let mut root = state.tokens.get(&1).unwrap();
+        root.transfer(account_transformer(account(2)));
+        state.tokens.insert(1, root);
+        assert_eq!(state.icrc7_balance_of(&[owner]), vec![0]);
+        assert_eq!(state.icrc7_owner_of(&[3]), vec![Some(account_transformer(account(2)))]);
+        let unequip = UnequipArg {
+            from_subaccount: None,
+            token_id: 3,
+            memo: None,
+        };
+        assert_eq!(
+            state.unequip(&account(1).owner, unequip, 2),
+            Err(EquipError::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn tokens_are_only_equipped_through_equip() {
+        let canister = Principal::from_slice(&[9]);
+        let owner = account_transformer(account(1));
+        let mut state = State {
+            icrc7_max_update_batch_size: Some(10),
+            ..Default::default()
+        };
+        for token_id in 1..=2 {
+            state.tokens.insert(
+                token_id,
+                Icrc7Token {
+                    token_id,
+                    token_owner: owner,
+                    token_meta: Default::default(),
+                    edition: None,
+                    token_user: None,
+                },
+            );
+        }
+        // a plain transfer to a token account would leave the token without a parent
+        let to = Account {
+            owner: canister,
+            subaccount: Some(token_subaccount(2)),
+        };
+        let transfer = TransferArg {
+            from_subaccount: None,
+            to,
+            token_id: 1,
+            memo: None,
+            created_at_time: None,
+        };
+        let res = state.icrc7_transfer(&account(1).owner, vec![transfer]);
+        assert!(matches!(res[0], Some(Err(TransferError::InvalidRecipient))));
+        let transfer_from = TransferFromArg {
+            spender_subaccount: None,
+            from: owner,
+            to,
+            token_id: 1,
+            memo: None,
+            created_at_time: None,
+        };
+        let res = state.transfer_from(&account(2).owner, vec![transfer_from]);
+        assert!(matches!(res[0], Some(Err(TransferFromError::InvalidRecipient))));
+        assert_eq!(state.tokens.get(&1).unwrap().token_owner, owner);
+        assert!(!state.parents.contains_key(&1));
+    }
+}
@@ -0,0 +1,200 @@
+use icrc_ledger_types::{icrc::generic_value::Value, icrc1::account::Account};
+use icrc7_types::icrc7_types::{
+    Icrc7TokenMetadata, TransactionType, UpdateCollectionConfigArg, UpdateCollectionConfigResult,
+};
+use crate::state::State;
+
+impl State {
+    // Applies a partial config update, logged as a 7update_coll block holding the new values.
+    // The whole update is validated before anything changes.
+    pub fn update_collection_config(
+        &mut self,
+        by: Account,
+        arg: UpdateCollectionConfigArg,
+        at: u64,
+    ) -> UpdateCollectionConfigResult {
+        self.validate_collection_config(&arg)?;
+        let memo = arg.memo.clone();
+        let changes = self.apply_collection_config(arg);
+        if changes.is_empty() {
+            return Err("Nothing to update".into());
+        }
+        Ok(self.log_transaction(TransactionType::UpdateCollection { by, changes }, at, memo))
+    }
+
+    pub fn validate_collection_config(
+        &self,
+        arg: &UpdateCollectionConfigArg,
+    ) -> Result<(), String> {
+        let changes_metadata = arg.icrc7_name.is_some()
+            || arg.icrc7_description.is_some()
+            || arg.icrc7_logo.is_some();
+        if changes_metadata && self.is_metadata_frozen() {
+            return Err("The collection metadata is frozen".into());
+        }
+        if let Some(ref name) = arg.icrc7_name {
+            if name.trim().is_empty() {
+                return Err("The name cannot be empty".into());
+            }
+        }
+        if arg.icrc7_max_query_batch_size == Some(0) || arg.icrc7_max_update_batch_size == Some(0) {
+            return Err("Batch sizes must be greater than zero".into());
+        }
+        let max_take = arg
+            .icrc7_max_take_value
+            .or(self.icrc7_max_take_value)
+            .unwrap_or(State::DEFAULT_MAX_TAKE_VALUE);
+        let default_take = arg
+            .icrc7_default_take_value
+            .or(self.icrc7_default_take_value)
+            .unwrap_or(State::DEFAULT_TAKE_VALUE);
+        if max_take == 0 || default_take == 0 {
+            return Err("Take values must be greater than zero".into());
+        }
+        if default_take > max_take {
+            return Err("The default take value cannot be above the max take value".into());
+        }
+        if arg.tx_window == Some(0) {
+            return Err("The transaction window must be greater than zero".into());
+        }
+        if let Some(ref approval_init) = arg.approval_init {
+            let mut ledger_info = self.approval_ledger_info.clone();
+            ledger_info.patch(approval_init.clone());
+            if ledger_info.settle_to_approvals > ledger_info.max_approvals {
+                return Err("settle_to_approvals cannot be above max_approvals".into());
+            }
+        }
+        if let Some(ref archive_init) = arg.archive_init {
+            if archive_init.settle_to_records > archive_init.max_active_records {
+                return Err("settle_to_records cannot be above max_active_records".into());
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the values that were set, keyed like the collection metadata
+    fn apply_collection_config(&mut self, arg: UpdateCollectionConfigArg) -> Icrc7TokenMetadata {
+        let mut changes = Icrc7TokenMetadata::new();
+        let nat = |value: u128| Value::Nat(value.into());
+
+        if let Some(name) = arg.icrc7_name {
+            changes.insert("icrc7:name".into(), Value::text(&name));
+            self.icrc7_name = name;
+        }
+        if let Some(description) = arg.icrc7_description {
+            changes.insert("icrc7:description".into(), Value::text(&description));
+            self.icrc7_description = Some(description).filter(|text| !text.is_empty());
+        }
+        if let Some(logo) = arg.icrc7_logo {
+            changes.insert("icrc7:logo".into(), Value::text(&logo));
+            self.icrc7_logo = Some(logo).filter(|text| !text.is_empty());
+        }
+        if let Some(size) = arg.icrc7_max_query_batch_size {
+            changes.insert("icrc7:max_query_batch_size".into(), nat(size.into()));
+            self.icrc7_max_query_batch_size = Some(size);
+        }
+        if let Some(size) = arg.icrc7_max_update_batch_size {
+            changes.insert("icrc7:max_update_batch_size".into(), nat(size.into()));
+            self.icrc7_max_update_batch_size = Some(size);
+        }
+        if let Some(take) = arg.icrc7_max_take_value {
+            changes.insert("icrc7:max_take_value".into(), nat(take));
+            self.icrc7_max_take_value = Some(take);
+        }
+        if let Some(take) = arg.icrc7_default_take_value {
+            changes.insert("icrc7:default_take_value".into(), nat(take));
+            self.icrc7_default_take_value = Some(take);
+        }
+        if let Some(size) = arg.icrc7_max_memo_size {
+            changes.insert("icrc7:max_memo_size".into(), nat(size.into()));
+            self.icrc7_max_memo_size = Some(size);
+        }
+        if let Some(atomic) = arg.icrc7_atomic_batch_transfers {
+            changes.insert("icrc7:atomic_batch_transfers".into(), Value::text(atomic));
+            self.icrc7_atomic_batch_transfers = Some(atomic);
+        }
+        if let Some(window) = arg.tx_window {
+            changes.insert("icrc7:tx_window".into(), nat(window.into()));
+            self.tx_window = Some(window);
+        }
+        if let Some(drift) = arg.permitted_drift {
+            changes.insert("icrc7:permitted_drift".into(), nat(drift.into()));
+            self.permitted_drift = Some(drift);
+        }
+        if let Some(approval_init) = arg.approval_init {
+            let fields = [
+                (
+                    "icrc37:max_approvals_per_token_or_collection",
+                    approval_init.max_approvals_per_token_or_collection,
+                ),
+                ("icrc37:max_revoke_approvals", approval_init.max_revoke_approvals),
+                ("icrc37:max_approvals", approval_init.max_approvals),
+                ("icrc37:settle_to_approvals", approval_init.settle_to_approvals),
+            ];
+            for (key, value) in fields {
+                if let Some(value) = value {
+                    changes.insert(key.into(), nat(value.into()));
+                }
+            }
+            if let Some(requires_token) = approval_init.collection_approval_requires_token {
+                changes.insert(
+                    "icrc37:collection_approval_requires_token".into(),
+                    Value::text(requires_token),
+                );
+            }
+            self.approval_ledger_info.patch(approval_init);
+        }
+        if let Some(archive_init) = arg.archive_init {
+            let setting = archive_init.to_archive_setting();
+            changes.insert("icrc3:max_active_records".into(), nat(setting.max_active_records));
+            changes.insert("icrc3:settle_to_records".into(), nat(setting.settle_to_records));
+            changes.insert(
+                "icrc3:max_records_in_archive_instance".into(),
+                nat(setting.max_records_in_archive_instance),
+            );
+            changes.insert("icrc3:max_archive_pages".into(), nat(setting.max_archive_pages));
+            changes.insert(
+                "icrc3:max_records_to_archive".into(),
+                nat(setting.max_records_to_archive),
+            );
+            changes.insert("icrc3:archive_cycles".into(), nat(setting.archive_cycles));
+            self.archive_ledger_info.setting = setting;
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_updates_are_partial_and_validated() {
+        let mut state = State {
+            icrc7_description: Some("old".into()),
+            icrc7_max_take_value: Some(50),
+            ..Default::default()
+        };
+
+        let invalid = UpdateCollectionConfigArg {
+            icrc7_name: Some("Renamed".into()),
+            icrc7_default_take_value: Some(60),
+            ..Default::default()
+        };
+        assert!(state.validate_collection_config(&invalid).is_err());
+
+        let arg = UpdateCollectionConfigArg {
+            icrc7_name: Some("Renamed".into()),
+            icrc7_description: Some("".into()),
+            icrc7_default_take_value: Some(40),
+            ..Default::default()
+        };
+        state.validate_collection_config(&arg).unwrap();
+        let changes = state.apply_collection_config(arg);
+        assert_eq!(changes.len(), 3);
+        assert_eq!(state.icrc7_name, "Renamed");
+        assert_eq!(state.icrc7_description, None);
+        assert_eq!(state.icrc7_default_take_value, Some(40));
+        assert_eq!(state.icrc7_max_take_value, Some(50));
+    }
+}
@@ -0,0 +1,183 @@
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::icrc7_types::{Finalization, FinalizeArg, FinalizeResult, TransactionType};
+use crate::state::{State, STATE};
+
+impl State {
+    // Irreversibly caps the supply at the current total and, if asked, freezes the metadata
+    pub fn finalize(&mut self, by: Account, arg: &FinalizeArg, at: u64) -> FinalizeResult {
+        if self.finalization.is_some() {
+            return Err("The collection is already finalized".into());
+        }
+        self.icrc7_supply_cap = Some(self.icrc7_total_supply);
+        let block_index = self.log_transaction(
+            TransactionType::Finalize {
+                by,
+                supply: self.icrc7_total_supply,
+                metadata_frozen: arg.freeze_metadata,
+            },
+            at,
+            arg.memo.clone(),
+        );
+        self.finalization = Some(Finalization {
+            block_index,
+            at,
+            by,
+            metadata_frozen: arg.freeze_metadata,
+            launchpad_removed: false,
+        });
+        Ok(block_index)
+    }
+
+    pub fn is_finalized(&self) -> bool {
+        self.finalization.is_some()
+    }
+
+    pub fn check_launchpad_removable(&self) -> Result<(), String> {
+        match self.finalization {
+            None => return Err("The collection is not finalized".into()),
+            Some(ref finalization) if finalization.launchpad_removed => {
+                return Err("The launchpad was already removed".into())
+            }
+            Some(_) => {}
+        }
+        if self.installer.is_none() {
+            return Err("The collection was not installed by a launchpad".into());
+        }
+        Ok(())
+    }
+
+    pub fn is_metadata_frozen(&self) -> bool {
+        self.finalization
+            .as_ref()
+            .is_some_and(|finalization| finalization.metadata_frozen)
+    }
+}
+
+// The collection can't change its own controllers, so the launchpad is asked to drop itself.
+// A failure can be retried until the launchpad is removed.
+pub async fn remove_launchpad() -> Result<(), String> {
+    let launchpad = STATE.with_borrow(|s| {
+        s.check_launchpad_removable()?;
+        Ok::<_, String>(s.installer.unwrap())
+    })?;
+    let (res,): (Result<(), String>,) = ic_cdk::call(launchpad, "release_collection", ())
+        .await
+        .map_err(|(code, msg)| format!("Rejection Code: {:?}, Message: {:?}", code, msg))?;
+    res?;
+    STATE.with_borrow_mut(|s| {
+        if let Some(ref mut finalization) = s.finalization {
+            finalization.launchpad_removed = true;
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::tests::account;
+    use icrc_ledger_types::icrc::generic_value::Value;
+    use icrc7_types::{
+        errors::MediaError,
+        icrc7_types::{UpdateCollectionConfigArg, METADATA_LAUNCHPAD_REMOVED},
+        media_types::CreateUploadArg,
+    };
+
+    #[test]
+    fn finalized_collections_reject_metadata_changes() {
+        let mut state = State {
+            icrc7_total_supply: 3,
+            finalization: Some(Finalization {
+                block_index: 7,
+                at: 1,
+                by: account(1),
+                metadata_frozen: true,
+                launchpad_removed: false,
+            }),
+            ..Default::default()
+        };
+        assert!(state.is_finalized());
+        assert!(state.is_metadata_frozen());
+        let arg = FinalizeArg {
+            freeze_metadata: false,
+            remove_launchpad: false,
+            memo: None,
+        };
+        assert!(state.finalize(account(1), &arg, 2).is_err());
+
+        let rename = UpdateCollectionConfigArg {
+            icrc7_name: Some("Renamed".into()),
+            ..Default::default()
+        };
+        assert!(state.validate_collection_config(&rename).is_err());
+        let limits = UpdateCollectionConfigArg {
+            icrc7_max_take_value: Some(64),
+            ..Default::default()
+        };
+        assert!(state.validate_collection_config(&limits).is_ok());
+    }
+
+    #[test]
+    fn finalized_collections_reject_setting_changes() {
+        let finalization = |metadata_frozen| Finalization {
+            block_index: 7,
+            at: 1,
+            by: account(1),
+            metadata_frozen,
+            launchpad_removed: false,
+        };
+        let mut state = State {
+            finalization: Some(finalization(false)),
+            ..Default::default()
+        };
+        assert!(state.set_royalties(vec![]).is_err());
+        assert!(state.set_token_royalties(1, None).is_err());
+        assert!(state.set_metadata_schema(None).is_err());
+        assert!(state.set_trait_keys(vec![]).is_err());
+        assert!(state.set_revenue_split(vec![]).is_err());
+
+        let upload = || CreateUploadArg {
+            content_type: "image/png".into(),
+            sha256: None,
+        };
+        let uploader = account(1).owner;
+        assert!(state.create_upload(uploader, upload(), 2).is_ok());
+        state.finalization = Some(finalization(true));
+        let frozen = |err: Option<MediaError>| {
+            matches!(err, Some(MediaError::GenericError { error_code: 4, .. }))
+        };
+        assert!(frozen(state.create_upload(uploader, upload(), 2).err()));
+        assert!(frozen(state.commit_upload(uploader, 0, 2).err()));
+        assert!(frozen(state.delete_media(&[0; 32]).err()));
+    }
+
+    #[test]
+    fn launchpad_removal_can_be_retried_once_finalized() {
+        let mut state = State {
+            installer: Some(account(5).owner),
+            ..Default::default()
+        };
+        assert!(state.check_launchpad_removable().is_err());
+        let arg = FinalizeArg {
+            freeze_metadata: false,
+            remove_launchpad: true,
+            memo: None,
+        };
+        state.finalize(account(1), &arg, 2).unwrap();
+        // the removal failed, finalizing again is refused but the removal can be retried
+        assert!(state.finalize(account(1), &arg, 3).is_err());
+        assert!(state.check_launchpad_removable().is_ok());
+        let removed = |state: &State| {
+            state
+                .icrc7_collection_metadata()
+                .into_iter()
+                .find(|(key, _)| key == METADATA_LAUNCHPAD_REMOVED)
+                .map(|(_, value)| value)
+        };
+        assert_eq!(removed(&state), Some(Value::text(false)));
+
+        state.finalization.as_mut().unwrap().launchpad_removed = true;
+        assert!(state.check_launchpad_removable().is_err());
+        assert_eq!(removed(&state), Some(Value::text(true)));
+    }
+}
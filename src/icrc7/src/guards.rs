@@ -1,17 +1,88 @@
 use crate::state::STATE;
 use candid::Principal;
 use ic_cdk::caller;
+use icrc7_types::rbac_types::Role;
+
+#[inline(always)]
+fn role_guard(role: Role) -> Result<(), String> {
+    if STATE.with(|s| s.borrow().multisig.is_some()) {
+        return Err("Multisig mode is enabled: submit a proposal instead".to_string());
+    }
+    operation_guard(role)
+}
+
+// For the operations that change no setting, such as uploading media or importing a
+// collection, which stay open to the role in multisig mode
+#[inline(always)]
+fn operation_guard(role: Role) -> Result<(), String> {
+    if STATE.with(|s| s.borrow().has_role(&caller(), role)) {
+        Ok(())
+    } else {
+        Err(format!("The caller does not have the {} role", role.as_str()))
+    }
+}
 
 #[inline(always)]
 pub fn owner_guard() -> Result<(), String> {
-    let owner = STATE
-        .with(|s| s.borrow().icrc7_minting_authority())
-        .ok_or_else(|| String::from("The canister not set owner"))?;
+    role_guard(Role::Owner)
+}
+
+#[inline(always)]
+pub fn admin_guard() -> Result<(), String> {
+    role_guard(Role::Admin)
+}
+
+#[inline(always)]
+pub fn minter_guard() -> Result<(), String> {
+    role_guard(Role::Minter)
+}
+
+#[inline(always)]
+pub fn metadata_editor_guard() -> Result<(), String> {
+    role_guard(Role::MetadataEditor)
+}
+
+#[inline(always)]
+pub fn pauser_guard() -> Result<(), String> {
+    role_guard(Role::Pauser)
+}
+
+#[inline(always)]
+pub fn treasurer_guard() -> Result<(), String> {
+    role_guard(Role::Treasurer)
+}
+
+#[inline(always)]
+pub fn minter_operation_guard() -> Result<(), String> {
+    operation_guard(Role::Minter)
+}
+
+#[inline(always)]
+pub fn admin_operation_guard() -> Result<(), String> {
+    operation_guard(Role::Admin)
+}
+
+#[inline(always)]
+pub fn treasurer_operation_guard() -> Result<(), String> {
+    operation_guard(Role::Treasurer)
+}
+
+#[inline(always)]
+pub fn signer_guard() -> Result<(), String> {
+    if STATE.with(|s| s.borrow().is_signer(&caller())) {
+        Ok(())
+    } else {
+        Err("The caller is not a multisig signer".to_string())
+    }
+}
 
-    if caller() == owner.owner {
+// The canister that installed the collection, usually the launchpad
+#[inline(always)]
+pub fn installer_guard() -> Result<(), String> {
+    if STATE.with(|s| s.borrow().installer) == Some(caller()) {
         Ok(())
     } else {
-        Err(String::from("The caller is not the owner of contract"))
+        Err("The caller did not install the collection".to_string())
     }
 }
 
@@ -23,3 +94,19 @@ pub fn authenticated_guard() -> Result<(), String> {
         Ok(())
     }
 }
+
+// For the endpoints that move tokens or change approvals, stopped while the collection is paused
+// or being recovered or migrated
+#[inline(always)]
+pub fn not_paused_guard() -> Result<(), String> {
+    authenticated_guard()?;
+    if STATE.with(|s| s.borrow().paused) {
+        Err("The collection is paused".to_string())
+    } else if STATE.with(|s| s.borrow().recovery.is_some()) {
+        Err("The collection is being recovered".to_string())
+    } else if STATE.with(|s| s.borrow().migration.is_some()) {
+        Err("The collection is being migrated".to_string())
+    } else {
+        Ok(())
+    }
+}
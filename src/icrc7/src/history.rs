@@ -0,0 +1,195 @@
+use candid::Principal;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::{
+    history_types::{OwnersAtResult, OwnershipSnapshotArg, OwnershipSnapshotResult, TokenOwner},
+    icrc37_types::UserAccount,
+    icrc7_types::TransactionType,
+};
+use crate::{
+    state::State,
+    utils::{burn_subaccount, token_of_subaccount},
+};
+
+// The token a block hands to a new account, and that account
+pub(crate) fn owner_change(txn_type: &TransactionType) -> Option<(u128, Account)> {
+    match txn_type {
+        TransactionType::Mint { tid, to, .. }
+        | TransactionType::Burn { tid, to, .. }
+        | TransactionType::Transfer { tid, to, .. }
+        | TransactionType::TransferFrom { tid, to, .. }
+        | TransactionType::Equip { tid, to, .. }
+        | TransactionType::Unequip { tid, to, .. } => Some((*tid, *to)),
+        _ => None,
+    }
+}
+
+impl State {
+    // Collections created before the history existed start it at their next block, with the
+    // current owners
+    pub fn migrate_ownership_history(&mut self) {
+        if self.ownership_history_start.is_some() {
+            return;
+        }
+        let start = self.txn_count;
+        let owners: Vec<(u128, Account)> = self
+            .tokens
+            .iter()
+            .map(|(token_id, token)| (token_id, token.token_owner))
+            .collect();
+        for (token_id, owner) in owners {
+            self.ownership_history
+                .insert((token_id, start), UserAccount::new(owner));
+        }
+        self.ownership_history_start = Some(start);
+    }
+
+    fn check_history_block(&self, block: u128) -> Result<(), String> {
+        let start = self.ownership_history_start.unwrap_or_default();
+        if block < start {
+            return Err(format!("The ownership history starts at block {start}"));
+        }
+        if block >= self.txn_count {
+            return Err("The block does not exist yet".into());
+        }
+        Ok(())
+    }
+
+    // The account a token was sent to by the last block up to `block`
+    fn direct_owner_at(&self, token_id: u128, block: u128) -> Option<Account> {
+        self.ownership_history
+            .range((token_id, 0)..=(token_id, block))
+            .next_back()
+            .map(|(_, owner)| owner.into())
+    }
+
+    // Who owned a token once `block` was applied, following equipped tokens up to the owner
+    // of their outermost parent as it was then. Burned and unminted tokens have no owner.
+    pub fn owner_at(&self, token_id: u128, block: u128, canister: Principal) -> Option<Account> {
+        let mut owner = self.direct_owner_at(token_id, block)?;
+        while owner.owner == canister {
+            match owner.subaccount.as_ref().and_then(token_of_subaccount) {
+                Some(parent_id) => owner = self.direct_owner_at(parent_id, block)?,
+                None => break,
+            }
+        }
+        if owner.owner == canister && owner.subaccount == Some(burn_subaccount()) {
+            return None;
+        }
+        Some(owner)
+    }
+
+    pub fn owners_at(&self, token_ids: &[u128], block: u128, canister: Principal) -> OwnersAtResult {
+        self.check_history_block(block)?;
+        Ok(token_ids
+            .iter()
+            .map(|token_id| self.owner_at(*token_id, block, canister))
+            .collect())
+    }
+
+    // Pages through the owners at a block, for airdrop and governance snapshots
+    pub fn ownership_snapshot(
+        &self,
+        arg: OwnershipSnapshotArg,
+        canister: Principal,
+    ) -> OwnershipSnapshotResult {
+        self.check_history_block(arg.block)?;
+        let take = self.get_current_take(arg.take) as usize;
+        let start = arg.prev.map_or(0, |prev| prev + 1);
+        Ok(self
+            .tokens
+            .range(start..)
+            .filter_map(|(token_id, _)| {
+                let owner = self.owner_at(token_id, arg.block, canister)?;
+                Some(TokenOwner { token_id, owner })
+            })
+            .take(take)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{market::tests::account, state::Icrc7Token, utils::token_subaccount};
+
+    #[test]
+    fn owners_are_known_at_every_block() {
+        let canister = Principal::from_slice(&[9]);
+        let mut state = State {
+            icrc7_max_take_value: Some(100),
+            ..Default::default()
+        };
+        let mut log = |state: &mut State, token_id: u128, to: Account| {
+            let block = state.txn_count;
+            state.txn_count += 1;
+            state
+                .ownership_history
+                .insert((token_id, block), UserAccount::new(to));
+        };
+        let parent_account = Account {
+            owner: canister,
+            subaccount: Some(token_subaccount(1)),
+        };
+        let burned = Account {
+            owner: canister,
+            subaccount: Some(burn_subaccount()),
+        };
+        log(&mut state, 1, account(1)); // 0: mint 1
+        log(&mut state, 2, account(1)); // 1: mint 2
+        log(&mut state, 2, parent_account); // 2: equip 2 to 1
+        log(&mut state, 1, account(2)); // 3: transfer 1
+        log(&mut state, 3, account(3)); // 4: mint 3
+        log(&mut state, 3, burned); // 5: burn 3
+        for token_id in 1..=3 {
+            state.tokens.insert(
+                token_id,
+                Icrc7Token {
+                    token_id,
+                    token_owner: account(1),
+                    token_meta: Default::default(),
+                    edition: None,
+                    token_user: None,
+                },
+            );
+        }
+
+        assert_eq!(
+            state.owners_at(&[1, 2, 3], 0, canister),
+            Ok(vec![Some(account(1)), None, None])
+        );
+        assert_eq!(
+            state.owners_at(&[1, 2], 2, canister),
+            Ok(vec![Some(account(1)), Some(account(1))])
+        );
+        assert_eq!(
+            state.owners_at(&[1, 2, 3], 4, canister),
+            Ok(vec![Some(account(2)), Some(account(2)), Some(account(3))])
+        );
+        assert_eq!(state.owners_at(&[3], 5, canister), Ok(vec![None]));
+        assert!(state.owners_at(&[1], 6, canister).is_err());
+
+        let snapshot = state
+            .ownership_snapshot(
+                OwnershipSnapshotArg {
+                    block: 5,
+                    prev: Some(1),
+                    take: None,
+                },
+                canister,
+            )
+            .unwrap();
+        assert_eq!(
+            snapshot,
+            vec![TokenOwner {
+                token_id: 2,
+                owner: account(2),
+            }]
+        );
+
+        // a collection migrating to the history only knows it from its next block
+        state.ownership_history_start = None;
+        state.migrate_ownership_history();
+        assert_eq!(state.ownership_history_start, Some(6));
+        assert!(state.owners_at(&[1], 5, canister).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, BTreeSet};
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::holder_types::{BalanceBucket, Holder, HolderStats, HoldersArg};
+use serde::{Deserialize, Serialize};
+use crate::{state::State, utils::burn_subaccount};
+
+// Balances by root owner, kept in step with every mint, transfer and burn so the holder
+// queries do not scan the tokens
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Holdings {
+    pub balances: BTreeMap<Account, u128>,
+    // (balance, account), for the holders sorted by balance
+    pub ranking: BTreeSet<(u128, Account)>,
+    pub minted: u128,
+    pub burned: u128,
+    pub transfers: u128,
+}
+
+impl Holdings {
+    fn add(&mut self, account: &Account, amount: u128) {
+        let balance = self.balances.entry(*account).or_default();
+        self.ranking.remove(&(*balance, *account));
+        *balance += amount;
+        self.ranking.insert((*balance, *account));
+    }
+
+    fn sub(&mut self, account: &Account, amount: u128) {
+        let Some(balance) = self.balances.get_mut(account) else {
+            return;
+        };
+        self.ranking.remove(&(*balance, *account));
+        *balance = balance.saturating_sub(amount);
+        if *balance == 0 {
+            self.balances.remove(account);
+        } else {
+            self.ranking.insert((*balance, *account));
+        }
+    }
+}
+
+impl State {
+    pub(crate) fn record_mint(&mut self, to: &Account) {
+        if let Some(holdings) = self.holdings.as_mut() {
+            holdings.add(to, 1);
+            holdings.minted += 1;
+        }
+    }
+
+    // A token moving between root owners carries the tokens equipped to it
+    pub(crate) fn record_transfer(&mut self, token_id: u128, from: &Account, to: &Account) {
+        let amount = 1 + self.descendants_of(token_id);
+        if let Some(holdings) = self.holdings.as_mut() {
+            holdings.sub(from, amount);
+            holdings.add(to, amount);
+            holdings.transfers += 1;
+        }
+    }
+
+    pub(crate) fn record_burn(&mut self, from: &Account) {
+        if let Some(holdings) = self.holdings.as_mut() {
+            holdings.sub(from, 1);
+            holdings.burned += 1;
+        }
+    }
+
+    fn descendants_of(&self, token_id: u128) -> u128 {
+        self.children_of(token_id)
+            .into_iter()
+            .map(|child| 1 + self.descendants_of(child))
+            .sum()
+    }
+
+    // Collections created before the statistics existed count their tokens once. Transfers
+    // made before are not known.
+    pub fn migrate_holdings(&mut self) {
+        if self.holdings.is_some() {
+            return;
+        }
+        let mut holdings = Holdings::default();
+        for (_, token) in self.tokens.iter() {
+            holdings.minted += 1;
+            if token.token_owner.subaccount == Some(burn_subaccount()) {
+                holdings.burned += 1;
+            } else {
+                holdings.add(&self.root_owner_of(&token), 1);
+            }
+        }
+        self.holdings = Some(holdings);
+    }
+
+    pub fn holder_stats(&self) -> HolderStats {
+        let holdings = self.holdings.clone().unwrap_or_default();
+        HolderStats {
+            holders: holdings.balances.len() as u64,
+            minted: holdings.minted,
+            burned: holdings.burned,
+            transfers: holdings.transfers,
+        }
+    }
+
+    pub fn holders(&self, arg: HoldersArg) -> Vec<Holder> {
+        let Some(ref holdings) = self.holdings else {
+            return vec![];
+        };
+        let take = self.get_current_take(arg.take) as usize;
+        let ranked: Vec<_> = match arg.prev {
+            Some(prev) => {
+                let Some(balance) = holdings.balances.get(&prev) else {
+                    return vec![];
+                };
+                holdings.ranking.range(..(*balance, prev)).rev().take(take).collect()
+            }
+            None => holdings.ranking.iter().rev().take(take).collect(),
+        };
+        ranked
+            .into_iter()
+            .map(|(balance, account)| Holder {
+                account: *account,
+                balance: *balance,
+            })
+            .collect()
+    }
+
+    pub fn holder_balances(&self, accounts: &[Account]) -> Vec<u128> {
+        accounts
+            .iter()
+            .map(|account| {
+                self.holdings
+                    .as_ref()
+                    .and_then(|holdings| holdings.balances.get(account))
+                    .copied()
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    // The number of holders per balance, smallest balance first
+    pub fn holder_distribution(&self) -> Vec<BalanceBucket> {
+        let mut buckets: BTreeMap<u128, u64> = BTreeMap::new();
+        for balance in self.holdings.iter().flat_map(|holdings| holdings.balances.values()) {
+            *buckets.entry(*balance).or_default() += 1;
+        }
+        buckets
+            .into_iter()
+            .map(|(balance, holders)| BalanceBucket { balance, holders })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{market::tests::account, state::Icrc7Token};
+
+    #[test]
+    fn holdings_follow_mints_transfers_and_burns() {
+        let mut state = State {
+            icrc7_max_take_value: Some(100),
+            ..Default::default()
+        };
+        for (token_id, owner) in [(1, 1), (2, 1), (3, 1), (4, 2), (5, 3)] {
+            state.tokens.insert(
+                token_id,
+                Icrc7Token {
+                    token_id,
+                    token_owner: account(owner),
+                    token_meta: Default::default(),
+                    edition: None,
+                    token_user: None,
+                },
+            );
+            state.record_mint(&account(owner));
+        }
+        // 3 is equipped to 2 and goes along with it
+        state.parents.insert(3, 2);
+        state.record_transfer(2, &account(1), &account(2));
+        state.record_burn(&account(3));
+
+        assert_eq!(
+            state.holder_stats(),
+            HolderStats {
+                holders: 2,
+                minted: 5,
+                burned: 1,
+                transfers: 1,
+            }
+        );
+        assert_eq!(state.holder_balances(&[account(1), account(2), account(3)]), vec![1, 3, 0]);
+        let holders = state.holders(HoldersArg {
+            prev: None,
+            take: None,
+        });
+        assert_eq!(
+            holders,
+            vec![
+                Holder {
+                    account: account(2),
+                    balance: 3,
+                },
+                Holder {
+                    account: account(1),
+                    balance: 1,
+                },
+            ]
+        );
+        let after_first = state.holders(HoldersArg {
+            prev: Some(account(2)),
+            take: Some(1),
+        });
+        assert_eq!(after_first, holders[1..].to_vec());
+        assert_eq!(
+            state.holder_distribution(),
+            vec![
+                BalanceBucket {
+                    balance: 1,
+                    holders: 1,
+                },
+                BalanceBucket {
+                    balance: 3,
+                    holders: 1,
+                },
+            ]
+        );
+
+        // the statistics rebuilt from the tokens agree, but for the transfers
+        let live = state.holdings.take().unwrap();
+        let mut burned = state.tokens.get(&5).unwrap();
+        burned.token_owner.subaccount = Some(burn_subaccount());
+        state.tokens.insert(5, burned);
+        for token_id in [2, 3] {
+            let mut token = state.tokens.get(&token_id).unwrap();
+            token.token_owner = account(2);
+            state.tokens.insert(token_id, token);
+        }
+        state.migrate_holdings();
+        let rebuilt = state.holdings.clone().unwrap();
+        assert_eq!(rebuilt.balances, live.balances);
+        assert_eq!(rebuilt.ranking, live.ranking);
+        assert_eq!((rebuilt.minted, rebuilt.burned, rebuilt.transfers), (5, 1, 0));
+    }
+}
@@ -0,0 +1,395 @@
+use data_encoding::BASE64;
+use ic_certified_map::{AsHashTree, Hash, HashTree, RbTree};
+use icrc_ledger_types::icrc::generic_value::Value;
+use icrc7_types::{
+    http_types::{HeaderField, HttpRequest, HttpResponse},
+    icrc7_types::TransactionType,
+};
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+use crate::{
+    media::media_ref,
+    state::{State, STATE, TREE},
+};
+
+// The label boundary nodes look the response hashes up under, by request path
+const HTTP_ASSETS: &str = "http_assets";
+pub const METADATA_IMAGE: &str = "image";
+// Bumped when the responses render differently, for the upgrade to hash them all again
+pub const RESPONSES_VERSION: u32 = 1;
+// Media is served in a single response, which must fit the 3 MiB query response limit with
+// room left for the headers and the certificate
+pub const MAX_SERVED_ASSET_SIZE: u64 = 3 * 1024 * 1024 - 64 * 1024;
+
+// A value of the certified tree: the ICRC-3 tip leaves, or the hashes of the HTTP responses
+pub enum CertifiedEntry {
+    Leaf(Hash),
+    Assets(RbTree<String, Hash>),
+}
+
+impl AsHashTree for CertifiedEntry {
+    fn root_hash(&self) -> Hash {
+        match self {
+            CertifiedEntry::Leaf(hash) => hash.root_hash(),
+            CertifiedEntry::Assets(assets) => assets.root_hash(),
+        }
+    }
+
+    fn as_hash_tree(&self) -> HashTree<'_> {
+        match self {
+            CertifiedEntry::Leaf(hash) => hash.as_hash_tree(),
+            CertifiedEntry::Assets(assets) => assets.as_hash_tree(),
+        }
+    }
+}
+
+pub struct HttpAsset {
+    pub status_code: u16,
+    pub headers: Vec<HeaderField>,
+    pub body: Vec<u8>,
+}
+
+impl State {
+    // What is served at a path: `/collection`, `/token/{id}` and `/token/{id}/image`
+    pub fn http_asset(&self, path: &str) -> Option<HttpAsset> {
+        if path == "/collection" {
+            let metadata = self.icrc7_collection_metadata().into_iter().collect();
+            return Some(json_asset(&Value::Map(metadata)));
+        }
+        let (token_id, image) = token_route(path)?;
+        let token = self.tokens.get(&token_id)?;
+        let metadata = self.token_metadata_of(&token);
+        if !image {
+            return Some(json_asset(&Value::Map(metadata)));
+        }
+        match metadata.get(METADATA_IMAGE)? {
+            Value::Blob(bytes) => Some(HttpAsset {
+                status_code: 200,
+                headers: vec![("Content-Type".into(), content_type_of(bytes).into())],
+                body: bytes.to_vec(),
+            }),
+            Value::Text(text) if media_ref(text).is_some() => {
+                let (media, content) = self.media_content(&media_ref(text)?)?;
+                Some(HttpAsset {
+                    status_code: 200,
+                    headers: vec![("Content-Type".into(), media.content_type)],
+                    body: content,
+                })
+            }
+            Value::Text(url) => Some(HttpAsset {
+                status_code: 307,
+                headers: vec![("Location".into(), url.clone())],
+                body: vec![],
+            }),
+            _ => None,
+        }
+    }
+
+    // The sha256 of what is served at a path, stored media being hashed already
+    fn http_asset_hash(&self, path: &str) -> Option<Hash> {
+        let stored_image = token_route(path)
+            .filter(|(_, image)| *image)
+            .and_then(|(token_id, _)| self.tokens.get(&token_id))
+            .and_then(|token| match self.token_metadata_of(&token).get(METADATA_IMAGE) {
+                Some(Value::Text(text)) => media_ref(text),
+                _ => None,
+            });
+        match stored_image {
+            Some(sha256) => self.media.contains_key(&sha256).then_some(sha256),
+            None => self.http_asset(path).map(|asset| sha256(&asset.body)),
+        }
+    }
+
+    // Hashes the responses for the collection and the given tokens into the certified tree
+    pub(crate) fn certify_responses(
+        &mut self,
+        tree: &mut RbTree<&'static str, CertifiedEntry>,
+        token_ids: &[u128],
+    ) {
+        let mut paths = vec!["/collection".to_string()];
+        for token_id in token_ids {
+            paths.push(format!("/token/{token_id}"));
+            paths.push(format!("/token/{token_id}/image"));
+        }
+        let hashes: Vec<(String, Option<Hash>)> = paths
+            .into_iter()
+            .map(|path| {
+                let hash = self.http_asset_hash(&path);
+                (path, hash)
+            })
+            .collect();
+        for (path, hash) in &hashes {
+            match hash {
+                Some(hash) => self.response_hashes.insert(path.clone(), *hash),
+                None => self.response_hashes.remove(path),
+            };
+        }
+        if tree.get(HTTP_ASSETS.as_bytes()).is_none() {
+            tree.insert(HTTP_ASSETS, CertifiedEntry::Assets(RbTree::new()));
+        }
+        tree.modify(HTTP_ASSETS.as_bytes(), |entry| {
+            if let CertifiedEntry::Assets(assets) = entry {
+                for (path, hash) in hashes {
+                    match hash {
+                        Some(hash) => assets.insert(path, hash),
+                        None => assets.delete(path.as_bytes()),
+                    }
+                }
+            }
+        });
+    }
+
+    // Rebuilds the certified tree from the stored response hashes, without rendering them
+    pub(crate) fn restore_responses(&self, tree: &mut RbTree<&'static str, CertifiedEntry>) {
+        let mut assets = RbTree::new();
+        for (path, hash) in self.response_hashes.iter() {
+            assets.insert(path, hash);
+        }
+        tree.insert(HTTP_ASSETS, CertifiedEntry::Assets(assets));
+    }
+}
+
+// `/token/{id}` or, flagged, `/token/{id}/image`
+fn token_route(path: &str) -> Option<(u128, bool)> {
+    let path = path.strip_prefix("/token/")?;
+    match path.strip_suffix("/image") {
+        Some(token_id) => Some((token_id.parse().ok()?, true)),
+        None => Some((path.parse().ok()?, false)),
+    }
+}
+
+// The token whose served metadata or media a block changes, burns releasing the media
+pub(crate) fn changed_token(txn_type: &TransactionType) -> Option<u128> {
+    match txn_type {
+        TransactionType::Mint { tid, .. }
+        | TransactionType::Update { tid, .. }
+        | TransactionType::Burn { tid, .. } => Some(*tid),
+        _ => None,
+    }
+}
+
+// Hashes the responses of every token again, for collections whose tokens were all imported
+pub fn certify_all() {
+    STATE.with_borrow_mut(|s| {
+        TREE.with_borrow_mut(|tree| {
+            s.certify_tip(tree);
+            let token_ids: Vec<u128> = s.tokens.iter().map(|(token_id, _)| token_id).collect();
+            s.certify_responses(tree, &token_ids);
+            s.responses_version = RESPONSES_VERSION;
+            crate::utils::set_certified_data(&tree.root_hash());
+        })
+    });
+}
+
+// The certified tree lives on the heap, so upgrades rebuild it from the stored hashes, unless
+// the responses now render differently
+pub fn restore_certification() {
+    if STATE.with_borrow(|s| s.responses_version) != RESPONSES_VERSION {
+        return certify_all();
+    }
+    STATE.with_borrow_mut(|s| {
+        TREE.with_borrow_mut(|tree| {
+            s.certify_tip(tree);
+            s.restore_responses(tree);
+            s.certify_responses(tree, &[]);
+            crate::utils::set_certified_data(&tree.root_hash());
+        })
+    });
+}
+
+// For the tip and collection metadata changes that are not logged as blocks one by one
+pub fn certify_collection() {
+    STATE.with_borrow_mut(|s| {
+        TREE.with_borrow_mut(|tree| {
+            s.certify_tip(tree);
+            s.certify_responses(tree, &[]);
+            crate::utils::set_certified_data(&tree.root_hash());
+        })
+    });
+}
+
+pub fn http_request(req: HttpRequest) -> HttpResponse {
+    if req.method != "GET" {
+        return text_response(405, "Method Not Allowed");
+    }
+    let path = req.url.split('?').next().unwrap_or_default();
+    let Some(asset) = STATE.with_borrow(|s| s.http_asset(path)) else {
+        return text_response(404, "Not Found");
+    };
+    let mut headers = asset.headers;
+    headers.push(("IC-Certificate".into(), certificate_header(path)));
+    HttpResponse {
+        status_code: asset.status_code,
+        headers,
+        body: ByteBuf::from(asset.body),
+    }
+}
+
+// The legacy response certification: a witness of `http_assets/<path>` in the certified tree
+fn certificate_header(path: &str) -> String {
+    let certificate = ic_cdk::api::data_certificate().unwrap_or_default();
+    let witness = TREE.with_borrow(|tree| {
+        let witness = tree.nested_witness(HTTP_ASSETS.as_bytes(), |entry| match entry {
+            CertifiedEntry::Assets(assets) => assets.witness(path.as_bytes()),
+            CertifiedEntry::Leaf(_) => entry.as_hash_tree(),
+        });
+        let mut bytes = vec![];
+        let mut serializer = serde_cbor::Serializer::new(&mut bytes);
+        let _ = serializer.self_describe();
+        witness.serialize(&mut serializer).unwrap();
+        bytes
+    });
+    format!(
+        "certificate=:{}:, tree=:{}:",
+        BASE64.encode(&certificate),
+        BASE64.encode(&witness)
+    )
+}
+
+fn text_response(status_code: u16, text: &str) -> HttpResponse {
+    HttpResponse {
+        status_code,
+        headers: vec![("Content-Type".into(), "text/plain".into())],
+        body: ByteBuf::from(text.as_bytes()),
+    }
+}
+
+fn json_asset(value: &Value) -> HttpAsset {
+    HttpAsset {
+        status_code: 200,
+        headers: vec![("Content-Type".into(), "application/json".into())],
+        body: value_to_json(value).into_bytes(),
+    }
+}
+
+// Numbers stay JSON numbers and blobs become hex strings
+pub fn value_to_json(value: &Value) -> String {
+    match value {
+        Value::Nat(nat) => nat.0.to_string(),
+        Value::Nat64(nat) => nat.to_string(),
+        Value::Int(int) => int.0.to_string(),
+        Value::Text(text) => json_string(text),
+        Value::Blob(bytes) => json_string(&hex::encode(bytes)),
+        Value::Array(values) => {
+            let values: Vec<String> = values.iter().map(value_to_json).collect();
+            format!("[{}]", values.join(","))
+        }
+        Value::Map(map) => {
+            let entries: Vec<String> = map
+                .iter()
+                .map(|(key, value)| format!("{}:{}", json_string(key), value_to_json(value)))
+                .collect();
+            format!("{{{}}}", entries.join(","))
+        }
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut res = String::with_capacity(text.len() + 2);
+    res.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+pub fn content_type_of(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "image/jpeg"
+    } else if bytes.starts_with(b"GIF8") {
+        "image/gif"
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        "image/webp"
+    } else if bytes.starts_with(b"<svg") || bytes.starts_with(b"<?xml") {
+        "image/svg+xml"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+pub fn sha256(bytes: &[u8]) -> Hash {
+    Sha256::digest(bytes).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{market::tests::account, state::Icrc7Token};
+    use icrc7_types::{icrc7_types::Icrc7TokenMetadata, media_types::MediaQuota};
+
+    #[test]
+    fn token_responses_are_certified() {
+        let mut state = State {
+            icrc7_name: "Flowers".into(),
+            ..Default::default()
+        };
+        state.tokens.insert(
+            1,
+            Icrc7Token {
+                token_id: 1,
+                token_owner: account(1),
+                token_meta: Icrc7TokenMetadata::from([
+                    ("icrc7:name".to_string(), Value::text("Rose \"red\"")),
+                    ("level".to_string(), Value::Nat(7u32.into())),
+                    ("image".to_string(), Value::Blob(ByteBuf::from(b"\x89PNG...".to_vec()))),
+                ]),
+                edition: None,
+                token_user: None,
+            },
+        );
+
+        let token = state.http_asset("/token/1").unwrap();
+        assert_eq!(
+            String::from_utf8(token.body.clone()).unwrap(),
+            r#"{"icrc7:name":"Rose \"red\"","image":"89504e472e2e2e","level":7}"#
+        );
+        let image = state.http_asset("/token/1/image").unwrap();
+        assert_eq!(image.headers[0].1, "image/png");
+        assert!(state.http_asset("/token/2").is_none());
+        assert!(state.http_asset("/token/one").is_none());
+
+        let mut tree = RbTree::new();
+        tree.insert("last_block_index", CertifiedEntry::Leaf([0; 32]));
+        state.certify_responses(&mut tree, &[1]);
+        let witness = tree.nested_witness(HTTP_ASSETS.as_bytes(), |entry| match entry {
+            CertifiedEntry::Assets(assets) => assets.witness(b"/token/1"),
+            CertifiedEntry::Leaf(_) => entry.as_hash_tree(),
+        });
+        assert_eq!(witness.reconstruct(), tree.root_hash());
+        let CertifiedEntry::Assets(assets) = tree.get(HTTP_ASSETS.as_bytes()).unwrap() else {
+            panic!("no response hashes");
+        };
+        assert_eq!(assets.get(b"/token/1"), Some(&sha256(&token.body)));
+        assert!(assets.get(b"/collection").is_some());
+
+        // upgrades rebuild the same tree from the stored hashes
+        let mut restored = RbTree::new();
+        restored.insert("last_block_index", CertifiedEntry::Leaf([0; 32]));
+        state.restore_responses(&mut restored);
+        assert_eq!(restored.root_hash(), tree.root_hash());
+    }
+
+    #[test]
+    fn media_fits_in_a_single_response() {
+        let mut state = State::default();
+        let quota = |max_asset_size| MediaQuota {
+            max_asset_size,
+            max_total_size: 1024 * 1024 * 1024,
+            max_uploads: 16,
+        };
+        assert!(state.set_media_quota(quota(MAX_SERVED_ASSET_SIZE + 1)).is_err());
+        assert!(state.set_media_quota(quota(MAX_SERVED_ASSET_SIZE)).is_ok());
+    }
+}
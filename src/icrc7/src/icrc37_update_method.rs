@@ -5,17 +5,17 @@ use icrc7_types::icrc37_types::{
     RevokeTokenApprovalResult, TransferFromArg, TransferFromResult,
 };
 use crate::{
-    guards::authenticated_guard,
+    guards::not_paused_guard,
     state::STATE,
 };
 
-#[update(guard = "authenticated_guard")]
+#[update(guard = "not_paused_guard")]
 pub fn icrc37_approve_tokens(args: Vec<ApproveTokenArg>) -> Vec<Option<ApproveTokenResult>> {
     let caller = ic_cdk::caller();
     STATE.with(|s| s.borrow_mut().approve(&caller, args))
 }
 
-#[update(guard = "authenticated_guard")]
+#[update(guard = "not_paused_guard")]
 pub fn icrc37_approve_collection(
     args: Vec<ApproveCollectionArg>,
 ) -> Vec<Option<ApproveCollectionResult>> {
@@ -25,7 +25,7 @@ pub fn icrc37_approve_collection(
 }
 
 // Revokes the specified approvals for a token given by `token_id` from the set of active approvals.
-#[ic_cdk::update(guard = "authenticated_guard")]
+#[ic_cdk::update(guard = "not_paused_guard")]
 pub fn icrc37_revoke_token_approvals(
     args: Vec<RevokeTokenApprovalArg>,
 ) -> Vec<Option<RevokeTokenApprovalResult>> {
@@ -35,7 +35,7 @@ pub fn icrc37_revoke_token_approvals(
 }
 
 // Revokes collection-level approvals from the set of active approvals.
-#[ic_cdk::update(guard = "authenticated_guard")]
+#[ic_cdk::update(guard = "not_paused_guard")]
 pub fn icrc37_revoke_collection_approvals(
     args: Vec<RevokeCollectionApprovalArg>,
 ) -> Vec<Option<RevokeCollectionApprovalResult>> {
@@ -46,7 +46,7 @@ pub fn icrc37_revoke_collection_approvals(
 
 // Transfers one or more tokens from the from account to the to account.
 // The transfer can be initiated by the holder of the tokens.
-#[ic_cdk::update(guard = "authenticated_guard")]
+#[ic_cdk::update(guard = "not_paused_guard")]
 pub fn icrc37_transfer_from(args: Vec<TransferFromArg>) -> Vec<Option<TransferFromResult>> {
     let caller = ic_cdk::caller();
 
@@ -4,11 +4,17 @@ use icrc_ledger_types::icrc1::account::Account;
 use icrc7_types::{
     icrc37_types::LedgerInfo, 
     icrc3_types::ArchiveLedgerInfo, 
-    icrc7_types::InitArg
+    icrc7_types::{InitArg, UpdateCollectionConfigArg},
+    rbac_types::Role,
 };
 use crate::{
+    auction::schedule_auction_timers,
+    http::{certify_collection, restore_certification},
+    migration::Migration,
+    offer::schedule_offer_timers,
+    rental::schedule_rental_timers,
     state::STATE,
-    utils::account_transformer,
+    utils::{account_transformer, default_account},
 };
 
 #[init]
@@ -26,26 +32,7 @@ pub fn init(arg: InitArg) {
 
     let mut ledger_info = LedgerInfo::default();
     if let Some(approval_init) = arg.approval_init {
-        if let Some(max_approvals_per_token_or_collection) =
-            approval_init.max_approvals_per_token_or_collection
-        {
-            ledger_info.max_approvals_per_token_or_collection =
-                max_approvals_per_token_or_collection;
-        }
-        if let Some(max_revoke_approvals) = approval_init.max_revoke_approvals {
-            ledger_info.max_revoke_approvals = max_revoke_approvals;
-        }
-        if let Some(max_approvals) = approval_init.max_approvals {
-            ledger_info.max_approvals = max_approvals;
-        }
-        if let Some(settle_to_approvals) = approval_init.settle_to_approvals {
-            ledger_info.settle_to_approvals = settle_to_approvals;
-        }
-        if let Some(collection_approval_requires_token) =
-            approval_init.collection_approval_requires_token
-        {
-            ledger_info.collection_approval_requires_token = collection_approval_requires_token;
-        }
+        ledger_info.patch(approval_init);
     }
 
     let mut archive_ledger_info = ArchiveLedgerInfo::default();
@@ -53,8 +40,16 @@ pub fn init(arg: InitArg) {
         archive_ledger_info = ArchiveLedgerInfo::new(Some(archive_init.to_archive_setting()))
     }
 
+    let owner = arg.owner.unwrap_or_else(ic_cdk::caller);
+
     STATE.with(|s| {
         let mut s = s.borrow_mut();
+        s.owner = Some(owner);
+        s.installer = Some(ic_cdk::caller());
+        s.roles
+            .entry(minting_authority.owner)
+            .or_default()
+            .insert(Role::Minter);
         s.minting_authority = Some(minting_authority);
         s.icrc7_symbol = arg.icrc7_symbol;
         s.icrc7_name = arg.icrc7_name;
@@ -71,7 +66,19 @@ pub fn init(arg: InitArg) {
         s.permitted_drift = arg.permitted_drift;
         s.approval_ledger_info = ledger_info;
         s.archive_ledger_info = archive_ledger_info;
-    })
+        s.transfer_policy = arg.transferable.unwrap_or_default();
+        s.assets_canister = arg.assets_canister;
+        if arg.migration == Some(true) {
+            s.migration = Some(Migration::default());
+        }
+        if let Some(split) = arg.revenue_split {
+            s.set_revenue_split(split).expect("invalid revenue split");
+        }
+        if let Some(royalties) = arg.royalties {
+            s.set_royalties(royalties).expect("invalid royalties");
+        }
+    });
+    certify_collection();
 }
 
 #[pre_upgrade]
@@ -92,9 +99,11 @@ fn pre_upgrade() {
     writer.write(&state_bytes).unwrap();
 }
 
-// A post-upgrade hook for deserializing the data back into the heap.
+// A post-upgrade hook for deserializing the data back into the heap. An optional config
+// patch can be passed, applied as `update_collection_config` would; an invalid one fails
+// the upgrade.
 #[post_upgrade]
-fn post_upgrade() {
+fn post_upgrade(config: Option<UpdateCollectionConfigArg>) {
     let memory = crate::memory::get_upgrades_memory();
 
     // Read the length of the state bytes.
@@ -109,4 +118,19 @@ fn post_upgrade() {
     // Deserialize and set the state.
     let state = ciborium::de::from_reader(&*state_bytes).expect("failed to decode state");
     STATE.with(|s| *s.borrow_mut() = state);
+    STATE.with(|s| s.borrow_mut().migrate_roles());
+    STATE.with(|s| s.borrow_mut().migrate_holdings());
+    STATE.with(|s| s.borrow_mut().migrate_ownership_history());
+
+    if let Some(config) = config {
+        let by = default_account(&ic_cdk::caller());
+        STATE
+            .with(|s| s.borrow_mut().update_collection_config(by, config, ic_cdk::api::time()))
+            .expect("invalid config patch");
+    }
+
+    schedule_auction_timers();
+    schedule_offer_timers();
+    schedule_rental_timers();
+    restore_certification();
 }
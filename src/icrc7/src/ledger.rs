@@ -0,0 +1,92 @@
+use candid::{Nat, Principal};
+use icrc_ledger_types::{
+    icrc1::{account::Account, transfer::TransferArg},
+    icrc2::transfer_from::TransferFromArgs,
+};
+
+// Minimal ICRC-1/ICRC-2 client used by the canister's own settlement flows.
+// Abstracted behind a trait so settlement can be exercised against a local stand-in.
+pub(crate) trait Ledger {
+    async fn fee(&self) -> Result<u128, String>;
+
+    async fn balance_of(&self, account: Account) -> Result<u128, String>;
+
+    async fn transfer(&self, arg: TransferArg) -> Result<u128, String>;
+
+    async fn transfer_from(&self, arg: TransferFromArgs) -> Result<u128, String>;
+}
+
+#[derive(Clone, Copy)]
+pub struct IcrcLedger(pub Principal);
+
+impl Ledger for IcrcLedger {
+    async fn fee(&self) -> Result<u128, String> {
+        let res: Result<(Nat,), _> = ic_cdk::call(self.0, "icrc1_fee", ()).await;
+        match res {
+            Ok((fee,)) => nat_to_u128(fee),
+            Err((code, msg)) => Err(format!("Rejection Code: {:?}, Message: {:?}", code, msg)),
+        }
+    }
+
+    async fn balance_of(&self, account: Account) -> Result<u128, String> {
+        let res: Result<(Nat,), _> = ic_cdk::call(self.0, "icrc1_balance_of", (account,)).await;
+        match res {
+            Ok((balance,)) => nat_to_u128(balance),
+            Err((code, msg)) => Err(format!("Rejection Code: {:?}, Message: {:?}", code, msg)),
+        }
+    }
+
+    async fn transfer(&self, arg: TransferArg) -> Result<u128, String> {
+        let res: Result<(Result<Nat, icrc_ledger_types::icrc1::transfer::TransferError>,), _> =
+            ic_cdk::call(self.0, "icrc1_transfer", (arg,)).await;
+        match res {
+            Ok((Ok(block_index),)) => nat_to_u128(block_index),
+            Ok((Err(e),)) => Err(e.to_string()),
+            Err((code, msg)) => Err(format!("Rejection Code: {:?}, Message: {:?}", code, msg)),
+        }
+    }
+
+    async fn transfer_from(&self, arg: TransferFromArgs) -> Result<u128, String> {
+        let res: Result<
+            (Result<Nat, icrc_ledger_types::icrc2::transfer_from::TransferFromError>,),
+            _,
+        > = ic_cdk::call(self.0, "icrc2_transfer_from", (arg,)).await;
+        match res {
+            Ok((Ok(block_index),)) => nat_to_u128(block_index),
+            Ok((Err(e),)) => Err(e.to_string()),
+            Err((code, msg)) => Err(format!("Rejection Code: {:?}, Message: {:?}", code, msg)),
+        }
+    }
+}
+
+pub fn nat_to_u128(value: Nat) -> Result<u128, String> {
+    value
+        .0
+        .try_into()
+        .map_err(|_| String::from("Amount does not fit in u128"))
+}
+
+// Sends `amount` from the canister's `from_subaccount` to `to`, the ledger fee being
+// deducted from the amount. Amounts not covering the fee are not sent.
+pub(crate) async fn send<L: Ledger>(
+    ledger: &L,
+    from_subaccount: Option<[u8; 32]>,
+    to: Account,
+    amount: u128,
+    fee: u128,
+) -> Result<Option<u128>, String> {
+    if amount <= fee {
+        return Ok(None);
+    }
+    ledger
+        .transfer(TransferArg {
+            from_subaccount,
+            to,
+            fee: Some(fee.into()),
+            created_at_time: None,
+            memo: None,
+            amount: (amount - fee).into(),
+        })
+        .await
+        .map(Some)
+}
@@ -10,6 +10,32 @@ pub mod update_method;
 pub mod icrc37_update_method;
 pub mod cycles;
 pub mod utils;
+pub mod ledger;
+pub mod market;
+pub mod auction;
+pub mod offer;
+pub mod royalty;
+pub mod revenue;
+pub mod transfer_lock;
+pub mod compliance;
+pub mod rbac;
+pub mod multisig;
+pub mod config;
+pub mod finalize;
+pub mod series;
+pub mod rental;
+pub mod token_account;
+pub mod composable;
+pub mod http;
+pub mod media;
+pub mod schema;
+pub mod traits;
+pub mod holders;
+pub mod history;
+pub mod recovery;
+pub mod migration;
+pub mod market_update_method;
+pub mod market_query_method;
 pub mod candid_file_generator;
 pub mod guards;
 pub mod archive;
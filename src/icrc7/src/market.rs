@@ -0,0 +1,676 @@
+use candid::Principal;
+use icrc_ledger_types::{
+    icrc1::account::{Account, Subaccount},
+    icrc2::transfer_from::TransferFromArgs,
+};
+use icrc7_types::{
+    errors::MarketError,
+    icrc37_types::ApprovalInfo,
+    icrc7_types::TransactionType,
+    market_types::{
+        BuyArg, BuyResult, DelistArg, DelistResult, ListTokenArg, ListTokenResult, Listing,
+        MarketFee, Payout,
+    },
+};
+use crate::{
+    ledger::{send, IcrcLedger, Ledger},
+    state::{State, STATE},
+    utils::{
+        account_transformer, market_account, market_subaccount, revenue_account, time,
+    },
+};
+
+pub const MAX_BPS: u16 = 10_000;
+
+impl State {
+    pub fn list_token(&mut self, caller: &Principal, arg: ListTokenArg) -> ListTokenResult {
+        let seller = account_transformer(Account {
+            owner: *caller,
+            subaccount: arg.from_subaccount,
+        });
+        let now = time();
+        match self.tokens.get(&arg.token_id) {
+            None => return Err(MarketError::NonExistingTokenId),
+            Some(token) => {
+                if token.token_owner != seller {
+                    return Err(MarketError::Unauthorized);
+                }
+            }
+        }
+        if self.is_frozen(&arg.token_id, &[&seller]) {
+            return Err(MarketError::Frozen);
+        }
+        if !self.is_transferable_by(&arg.token_id, &seller) {
+            return Err(MarketError::NonTransferable);
+        }
+        if arg.price == 0 {
+            return Err(MarketError::InvalidPrice);
+        }
+        if let Some(expires_at) = arg.expires_at {
+            if expires_at <= now {
+                return Err(MarketError::ListingExpired);
+            }
+        }
+        if self.settling_tokens.contains(&arg.token_id) {
+            return Err(MarketError::SettlementInProgress);
+        }
+
+        // the listing is backed by an ICRC-37 approval to the canister's market account
+        let txn_id = self.approve_token_internal(
+            seller,
+            arg.token_id,
+            ApprovalInfo::new(arg.from_subaccount, market_account(), None, arg.expires_at, Some(now)),
+            now,
+        );
+        self.listings.insert(
+            arg.token_id,
+            Listing {
+                token_id: arg.token_id,
+                seller,
+                price: arg.price,
+                ledger: arg.ledger,
+                expires_at: arg.expires_at,
+                created_at: now,
+            },
+        );
+        Ok(txn_id)
+    }
+
+    pub fn delist(&mut self, caller: &Principal, arg: DelistArg) -> DelistResult {
+        let seller = account_transformer(Account {
+            owner: *caller,
+            subaccount: arg.from_subaccount,
+        });
+        let listing = self
+            .listings
+            .get(&arg.token_id)
+            .ok_or(MarketError::NotListed)?;
+        if listing.seller != seller {
+            return Err(MarketError::Unauthorized);
+        }
+        if self.settling_tokens.contains(&arg.token_id) {
+            return Err(MarketError::SettlementInProgress);
+        }
+        self.listings.remove(&arg.token_id);
+        Ok(self.revoke_token_internal(seller, arg.token_id, market_account(), time()))
+    }
+
+    pub fn get_listing(&self, token_id: u128) -> Option<Listing> {
+        let now = time();
+        self.listings
+            .get(&token_id)
+            .filter(|listing| self.is_listing_valid(listing, now))
+    }
+
+    pub fn get_listings(&self, prev: Option<u128>, take: Option<u128>) -> Vec<Listing> {
+        let take = self.get_current_take(take);
+        let now = time();
+        let start = prev.map_or(0, |prev| prev + 1);
+        self.listings
+            .range(start..)
+            .map(|(_, listing)| listing)
+            .filter(|listing| self.is_listing_valid(listing, now))
+            .take(take as usize)
+            .collect()
+    }
+
+    pub fn set_market_fee(&mut self, fee: Option<MarketFee>) -> Result<(), String> {
+        if let Some(ref fee) = fee {
+            if fee.fee_bps > MAX_BPS {
+                return Err("Fee exceeds 10000 basis points".into());
+            }
+        }
+        self.market_fee = fee;
+        Ok(())
+    }
+
+    fn is_listing_valid(&self, listing: &Listing, now: u64) -> bool {
+        if listing.is_expired(now) {
+            return false;
+        }
+        match self.tokens.get(&listing.token_id) {
+            None => false,
+            Some(token) => {
+                token.token_owner == listing.seller
+                    && self.is_approved_by_token(
+                        &listing.token_id,
+                        &listing.seller,
+                        &market_account(),
+                        now,
+                    )
+            }
+        }
+    }
+
+    // Validates a purchase and reserves the token until the sale is completed or aborted
+    pub fn prepare_sale(
+        &mut self,
+        buyer: &Account,
+        arg: &BuyArg,
+        now: u64,
+    ) -> Result<Listing, MarketError> {
+        if self.settling_tokens.contains(&arg.token_id) {
+            return Err(MarketError::SettlementInProgress);
+        }
+        let listing = self
+            .listings
+            .get(&arg.token_id)
+            .ok_or(MarketError::NotListed)?;
+        if listing.is_expired(now) {
+            return Err(MarketError::ListingExpired);
+        }
+        if !self.is_listing_valid(&listing, now) {
+            return Err(MarketError::NotListed);
+        }
+        if listing.price != arg.price {
+            return Err(MarketError::PriceMismatch {
+                price: listing.price,
+            });
+        }
+        if listing.seller == *buyer {
+            return Err(MarketError::Unauthorized);
+        }
+        if self.is_frozen(&arg.token_id, &[&listing.seller, buyer]) {
+            return Err(MarketError::Frozen);
+        }
+        self.settling_tokens.insert(arg.token_id);
+        Ok(listing)
+    }
+
+    pub fn abort_sale(&mut self, token_id: &u128) {
+        self.settling_tokens.remove(token_id);
+    }
+
+    // Moves the token to the buyer once the payment is escrowed
+    pub fn complete_sale(
+        &mut self,
+        listing: &Listing,
+        to: &Account,
+        now: u64,
+    ) -> Result<u128, MarketError> {
+        self.settling_tokens.remove(&listing.token_id);
+        match self.listings.get(&listing.token_id) {
+            Some(current) if current.seller == listing.seller && current.price == listing.price => {}
+            _ => return Err(MarketError::NotListed),
+        }
+        if !self.is_listing_valid(listing, now) {
+            return Err(MarketError::NotListed);
+        }
+
+        let mut token = self.tokens.get(&listing.token_id).unwrap();
+        token.transfer(*to);
+        self.token_approvals_clean(&listing.token_id);
+        self.tokens.insert(listing.token_id, token);
+        self.token_moved(&listing.token_id);
+        self.record_transfer(listing.token_id, &listing.seller, to);
+        Ok(self.log_transaction(
+            TransactionType::TransferFrom {
+                tid: listing.token_id,
+                from: listing.seller,
+                to: *to,
+                spender: market_account(),
+            },
+            now,
+            None,
+        ))
+    }
+
+    // Basis points taken from a sale before the seller is paid
+    // The market fee comes first, then the token royalties, the seller getting what is left
+    pub fn sale_shares(&self, token_id: u128) -> Vec<(Account, u16)> {
+        let mut shares = vec![];
+        if let Some(ref fee) = self.market_fee {
+            if fee.fee_bps > 0 {
+                shares.push((fee.fee_account, fee.fee_bps));
+            }
+        }
+        shares.extend(self.royalty_shares(token_id));
+        shares
+    }
+
+    pub fn record_failed_payouts(&mut self, payouts: Vec<Payout>) {
+        self.failed_payouts.extend(payouts);
+    }
+}
+
+pub async fn buy(caller: Principal, arg: BuyArg) -> BuyResult {
+    buy_with(IcrcLedger, caller, arg).await
+}
+
+// The purchase flow, paying through whichever ledger `ledger_of` gives for the listing's one
+pub(crate) async fn buy_with<L: Ledger>(
+    ledger_of: impl Fn(Principal) -> L,
+    caller: Principal,
+    arg: BuyArg,
+) -> BuyResult {
+    let buyer = account_transformer(Account {
+        owner: caller,
+        subaccount: arg.from_subaccount,
+    });
+    let to = account_transformer(arg.to.unwrap_or(buyer));
+    let listing = STATE.with_borrow_mut(|s| s.prepare_sale(&buyer, &arg, time()))?;
+    settle_sale(&ledger_of(listing.ledger), listing, buyer, to).await
+}
+
+// Settles a sale prepared by `prepare_sale`
+pub(crate) async fn settle_sale<L: Ledger>(
+    ledger: &L,
+    listing: Listing,
+    buyer: Account,
+    to: Account,
+) -> BuyResult {
+    let fee = match ledger.fee().await {
+        Ok(fee) => fee,
+        Err(message) => {
+            STATE.with_borrow_mut(|s| s.abort_sale(&listing.token_id));
+            return Err(MarketError::PaymentFailed { message });
+        }
+    };
+
+    // 1st: escrow the payment in the market account
+    if let Err(message) =
+        collect_payment(ledger, buyer, market_account(), listing.price, fee).await
+    {
+        STATE.with_borrow_mut(|s| s.abort_sale(&listing.token_id));
+        return Err(MarketError::PaymentFailed { message });
+    }
+
+    // 2nd: move the token, refunding the buyer if the listing changed meanwhile
+    let res = STATE.with_borrow_mut(|s| s.complete_sale(&listing, &to, time()));
+    let txn_id = match res {
+        Ok(txn_id) => txn_id,
+        Err(e) => {
+            let refund = vec![Payout {
+                ledger: listing.ledger,
+                from_subaccount: Some(market_subaccount()),
+                to: buyer,
+                amount: listing.price,
+            }];
+            let failed = pay_out(ledger, refund, fee).await;
+            STATE.with_borrow_mut(|s| s.record_failed_payouts(failed));
+            return Err(e);
+        }
+    };
+
+    // 3rd: pay the seller and the fee recipients
+    let (shares, seller) = STATE.with_borrow(|s| {
+        (s.sale_shares(listing.token_id), s.proceeds_recipient(listing.seller))
+    });
+    let payouts = proceeds_payouts(
+        listing.ledger,
+        market_subaccount(),
+        listing.price,
+        &shares,
+        seller,
+    );
+    let failed = pay_out(ledger, payouts, fee).await;
+    STATE.with_borrow_mut(|s| s.record_failed_payouts(failed));
+
+    Ok(txn_id)
+}
+
+pub async fn retry_failed_payouts() -> usize {
+    retry_failed_payouts_with(IcrcLedger).await
+}
+
+pub(crate) async fn retry_failed_payouts_with<L: Ledger>(
+    ledger_of: impl Fn(Principal) -> L,
+) -> usize {
+    let payouts = STATE.with_borrow_mut(|s| std::mem::take(&mut s.failed_payouts));
+    let mut failed = vec![];
+    for payout in payouts {
+        let ledger = ledger_of(payout.ledger);
+        match ledger.fee().await {
+            Ok(fee) => failed.extend(pay_out(&ledger, vec![payout], fee).await),
+            Err(_) => failed.push(payout),
+        }
+    }
+    let remaining = failed.len();
+    STATE.with_borrow_mut(|s| s.record_failed_payouts(failed));
+    remaining
+}
+
+pub(crate) async fn collect_payment<L: Ledger>(
+    ledger: &L,
+    from: Account,
+    to: Account,
+    amount: u128,
+    fee: u128,
+) -> Result<u128, String> {
+    ledger
+        .transfer_from(TransferFromArgs {
+            spender_subaccount: None,
+            from,
+            to,
+            amount: amount.into(),
+            fee: Some(fee.into()),
+            memo: None,
+            created_at_time: None,
+        })
+        .await
+}
+
+// Sends every payout from its escrow subaccount, returning the ones that could not be delivered
+pub(crate) async fn pay_out<L: Ledger>(ledger: &L, payouts: Vec<Payout>, fee: u128) -> Vec<Payout> {
+    let mut failed = vec![];
+    for payout in payouts {
+        match send(ledger, payout.from_subaccount, payout.to, payout.amount, fee).await {
+            // primary-sale proceeds become claimable by the stakeholders once delivered
+            Ok(Some(_)) if payout.to == revenue_account() => {
+                STATE.with_borrow_mut(|s| {
+                    s.credit_revenue(payout.ledger, payout.amount - fee, time())
+                });
+            }
+            Ok(_) => {}
+            Err(_) => failed.push(payout),
+        }
+    }
+    failed
+}
+
+pub(crate) fn proceeds_payouts(
+    ledger: Principal,
+    from_subaccount: Subaccount,
+    price: u128,
+    shares: &[(Account, u16)],
+    seller: Account,
+) -> Vec<Payout> {
+    split_proceeds(price, shares, seller)
+        .into_iter()
+        .map(|(to, amount)| Payout {
+            ledger,
+            from_subaccount: Some(from_subaccount),
+            to,
+            amount,
+        })
+        .collect()
+}
+
+// Splits `price` among the `shares` (in basis points), the remainder going to the seller
+pub fn split_proceeds(price: u128, shares: &[(Account, u16)], seller: Account) -> Vec<(Account, u128)> {
+    let mut res = vec![];
+    let mut remaining = price;
+    for (account, bps) in shares {
+        let amount = bps_of(price, *bps).min(remaining);
+        if amount == 0 {
+            continue;
+        }
+        remaining -= amount;
+        res.push((*account, amount));
+    }
+    if remaining > 0 {
+        res.push((seller, remaining));
+    }
+    res
+}
+
+pub fn bps_of(amount: u128, bps: u16) -> u128 {
+    let bps = bps as u128;
+    amount / MAX_BPS as u128 * bps + amount % MAX_BPS as u128 * bps / MAX_BPS as u128
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use candid::Principal;
+    use icrc_ledger_types::icrc1::transfer::TransferArg;
+    use crate::{
+        state::Icrc7Token,
+        utils::{canister_id, default_account},
+    };
+    use std::{cell::RefCell, collections::BTreeMap, future::Future, pin::pin, task::Context};
+
+    // In-memory ICRC-1/ICRC-2 ledger stand-in
+    pub struct LocalLedger {
+        pub fee: u128,
+        pub canister: Principal,
+        pub balances: RefCell<BTreeMap<Account, u128>>,
+        pub rejected: RefCell<Vec<Account>>,
+    }
+
+    impl LocalLedger {
+        pub fn new(canister: Principal, fee: u128) -> Self {
+            Self {
+                fee,
+                canister,
+                balances: RefCell::new(BTreeMap::new()),
+                rejected: RefCell::new(vec![]),
+            }
+        }
+
+        pub fn mint(&self, to: Account, amount: u128) {
+            *self.balances.borrow_mut().entry(to).or_default() += amount;
+        }
+
+        pub fn balance(&self, account: &Account) -> u128 {
+            self.balances.borrow().get(account).cloned().unwrap_or_default()
+        }
+
+        fn debit_credit(&self, from: Account, to: Account, amount: u128) -> Result<u128, String> {
+            if self.rejected.borrow().contains(&to) {
+                return Err("Recipient rejected".into());
+            }
+            let mut balances = self.balances.borrow_mut();
+            let balance = balances.get(&from).cloned().unwrap_or_default();
+            if balance < amount + self.fee {
+                return Err("InsufficientFunds".into());
+            }
+            balances.insert(from, balance - amount - self.fee);
+            *balances.entry(to).or_default() += amount;
+            Ok(0)
+        }
+    }
+
+    // so that the flows taking a ledger per principal can be handed the same stand-in
+    impl<L: Ledger> Ledger for &L {
+        async fn fee(&self) -> Result<u128, String> {
+            (**self).fee().await
+        }
+
+        async fn balance_of(&self, account: Account) -> Result<u128, String> {
+            (**self).balance_of(account).await
+        }
+
+        async fn transfer(&self, arg: TransferArg) -> Result<u128, String> {
+            (**self).transfer(arg).await
+        }
+
+        async fn transfer_from(&self, arg: TransferFromArgs) -> Result<u128, String> {
+            (**self).transfer_from(arg).await
+        }
+    }
+
+    impl Ledger for LocalLedger {
+        async fn fee(&self) -> Result<u128, String> {
+            Ok(self.fee)
+        }
+
+        async fn balance_of(&self, account: Account) -> Result<u128, String> {
+            Ok(self.balance(&account))
+        }
+
+        async fn transfer(&self, arg: TransferArg) -> Result<u128, String> {
+            let from = Account {
+                owner: self.canister,
+                subaccount: arg.from_subaccount,
+            };
+            self.debit_credit(from, arg.to, crate::ledger::nat_to_u128(arg.amount)?)
+        }
+
+        async fn transfer_from(&self, arg: TransferFromArgs) -> Result<u128, String> {
+            self.debit_credit(arg.from, arg.to, crate::ledger::nat_to_u128(arg.amount)?)
+        }
+    }
+
+    pub fn block_on<F: Future>(f: F) -> F::Output {
+        let mut f = pin!(f);
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        loop {
+            if let std::task::Poll::Ready(res) = f.as_mut().poll(&mut cx) {
+                return res;
+            }
+        }
+    }
+
+    pub fn account(n: u8) -> Account {
+        Account {
+            owner: Principal::from_slice(&[n]),
+            subaccount: None,
+        }
+    }
+
+    #[test]
+    fn split_proceeds_pays_shares_then_seller() {
+        let seller = account(1);
+        let fee_account = account(2);
+        let payouts = split_proceeds(10_000, &[(fee_account, 250)], seller);
+        assert_eq!(payouts, vec![(fee_account, 250), (seller, 9_750)]);
+
+        let payouts = split_proceeds(u128::MAX, &[(fee_account, MAX_BPS)], seller);
+        assert_eq!(payouts, vec![(fee_account, u128::MAX)]);
+    }
+
+    #[test]
+    fn pay_out_keeps_undelivered_payouts() {
+        let canister = Principal::from_slice(&[9]);
+        let ledger = LocalLedger::new(canister, 10);
+        let escrow = Account {
+            owner: canister,
+            subaccount: Some(market_subaccount()),
+        };
+        ledger.mint(escrow, 1_000);
+        ledger.rejected.borrow_mut().push(account(3));
+
+        let payout = |to, amount| Payout {
+            ledger: canister,
+            from_subaccount: Some(market_subaccount()),
+            to,
+            amount,
+        };
+        let payouts = vec![payout(account(1), 600), payout(account(3), 300), payout(account(4), 5)];
+        let failed = block_on(pay_out(&ledger, payouts, 10));
+
+        assert_eq!(ledger.balance(&account(1)), 590);
+        assert_eq!(ledger.balance(&account(4)), 0);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].to, account(3));
+        assert_eq!(ledger.balance(&escrow), 400);
+    }
+
+    // A collection whose token 1 is listed by `seller` for 10_000, with a 2.5% market fee
+    fn listed_token(ledger: &LocalLedger, seller: Account) {
+        STATE.with_borrow_mut(|s| {
+            let token = Icrc7Token {
+                token_id: 1,
+                token_owner: seller,
+                token_meta: Default::default(),
+                edition: None,
+                token_user: None,
+            };
+            s.tokens.insert(1, token);
+            s.set_market_fee(Some(MarketFee {
+                fee_bps: 250,
+                fee_account: account(5),
+            }))
+            .unwrap();
+            let arg = ListTokenArg {
+                from_subaccount: seller.subaccount,
+                token_id: 1,
+                price: 10_000,
+                ledger: ledger.canister,
+                expires_at: None,
+            };
+            s.list_token(&seller.owner, arg).unwrap();
+        });
+    }
+
+    fn seller() -> Account {
+        default_account(&account(1).owner)
+    }
+
+    fn buyer() -> Account {
+        default_account(&account(2).owner)
+    }
+
+    fn buy_arg() -> BuyArg {
+        BuyArg {
+            from_subaccount: None,
+            token_id: 1,
+            price: 10_000,
+            to: None,
+        }
+    }
+
+    #[test]
+    fn buying_pays_the_seller_and_the_fee() {
+        let ledger = LocalLedger::new(canister_id(), 10);
+        let (seller, buyer) = (seller(), buyer());
+        listed_token(&ledger, seller);
+        ledger.mint(buyer, 10_010);
+
+        let arg = BuyArg {
+            price: 9_000,
+            ..buy_arg()
+        };
+        let res = block_on(buy_with(|_| &ledger, buyer.owner, arg));
+        assert!(matches!(res, Err(MarketError::PriceMismatch { price: 10_000 })));
+        assert!(block_on(buy_with(|_| &ledger, buyer.owner, buy_arg())).is_ok());
+
+        STATE.with_borrow(|s| {
+            assert_eq!(s.tokens.get(&1).unwrap().token_owner, buyer);
+            assert!(s.listings.is_empty());
+            assert!(s.settling_tokens.is_empty());
+            assert!(s.failed_payouts.is_empty());
+        });
+        assert_eq!(ledger.balance(&buyer), 0);
+        assert_eq!(ledger.balance(&seller), 9_740);
+        assert_eq!(ledger.balance(&account(5)), 240);
+        assert_eq!(ledger.balance(&market_account()), 0);
+    }
+
+    #[test]
+    fn buyers_are_refunded_when_the_sale_cannot_complete() {
+        let ledger = LocalLedger::new(canister_id(), 10);
+        let (seller, buyer) = (seller(), buyer());
+        listed_token(&ledger, seller);
+        ledger.mint(buyer, 10_010);
+
+        let listing = STATE.with_borrow_mut(|s| s.prepare_sale(&buyer, &buy_arg(), time()));
+        let listing = listing.unwrap();
+        // the listing goes away while the payment is being collected
+        STATE.with_borrow_mut(|s| s.listings.remove(&1));
+        let res = block_on(settle_sale(&ledger, listing, buyer, buyer));
+
+        assert!(matches!(res, Err(MarketError::NotListed)));
+        STATE.with_borrow(|s| {
+            assert_eq!(s.tokens.get(&1).unwrap().token_owner, seller);
+            assert!(s.settling_tokens.is_empty());
+        });
+        // both transfers cost the ledger fee
+        assert_eq!(ledger.balance(&buyer), 9_990);
+        assert_eq!(ledger.balance(&seller), 0);
+    }
+
+    #[test]
+    fn undelivered_proceeds_are_retried() {
+        let ledger = LocalLedger::new(canister_id(), 10);
+        let (seller, buyer) = (seller(), buyer());
+        listed_token(&ledger, seller);
+        ledger.mint(buyer, 10_010);
+        ledger.rejected.borrow_mut().push(seller);
+
+        assert!(block_on(buy_with(|_| &ledger, buyer.owner, buy_arg())).is_ok());
+        STATE.with_borrow(|s| {
+            assert_eq!(s.tokens.get(&1).unwrap().token_owner, buyer);
+            assert_eq!(s.failed_payouts.len(), 1);
+            assert_eq!(s.failed_payouts[0].to, seller);
+        });
+        assert_eq!(ledger.balance(&market_account()), 9_750);
+
+        assert_eq!(block_on(retry_failed_payouts_with(|_| &ledger)), 1);
+        ledger.rejected.borrow_mut().clear();
+        assert_eq!(block_on(retry_failed_payouts_with(|_| &ledger)), 0);
+        assert_eq!(ledger.balance(&seller), 9_740);
+        assert_eq!(ledger.balance(&market_account()), 0);
+    }
+}
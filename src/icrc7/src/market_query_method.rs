@@ -0,0 +1,65 @@
+use ic_cdk_macros::query;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::market_types::{Auction, Listing, MarketFee, Offer, Payout};
+use crate::state::STATE;
+
+// Returns the active listing of a token, if any.
+#[query]
+pub fn get_listing(token_id: u128) -> Option<Listing> {
+    STATE.with(|s| s.borrow().get_listing(token_id))
+}
+
+// Returns the active listings, ordered by token id.
+#[query]
+pub fn get_listings(prev: Option<u128>, take: Option<u128>) -> Vec<Listing> {
+    STATE.with(|s| s.borrow().get_listings(prev, take))
+}
+
+#[query]
+pub fn market_fee() -> Option<MarketFee> {
+    STATE.with(|s| s.borrow().market_fee.clone())
+}
+
+#[query]
+pub fn failed_payouts() -> Vec<Payout> {
+    STATE.with(|s| s.borrow().failed_payouts.clone())
+}
+
+#[query]
+pub fn get_auction(auction_id: u128) -> Option<Auction> {
+    STATE.with(|s| s.borrow().get_auction(auction_id))
+}
+
+// Returns the active auctions, ordered by auction id.
+#[query]
+pub fn get_auctions(prev: Option<u128>, take: Option<u128>) -> Vec<Auction> {
+    STATE.with(|s| s.borrow().get_auctions(prev, take))
+}
+
+// Returns the lowest bid currently accepted by an open auction (the current price for Dutch auctions).
+#[query]
+pub fn auction_min_bid(auction_id: u128) -> Option<u128> {
+    STATE.with(|s| s.borrow().auction_min_bid(auction_id))
+}
+
+#[query]
+pub fn get_offer(offer_id: u128) -> Option<Offer> {
+    STATE.with(|s| s.borrow().get_offer(offer_id))
+}
+
+// Returns the active offers a token can accept, including collection-wide and matching trait offers.
+#[query]
+pub fn offers_by_token(token_id: u128, prev: Option<u128>, take: Option<u128>) -> Vec<Offer> {
+    STATE.with(|s| s.borrow().offers_by_token(token_id, prev, take))
+}
+
+#[query]
+pub fn offers_by_bidder(bidder: Account, prev: Option<u128>, take: Option<u128>) -> Vec<Offer> {
+    STATE.with(|s| s.borrow().offers_by_bidder(bidder, prev, take))
+}
+
+// Returns the active collection-wide and trait offers.
+#[query]
+pub fn collection_offers(prev: Option<u128>, take: Option<u128>) -> Vec<Offer> {
+    STATE.with(|s| s.borrow().collection_offers(prev, take))
+}
@@ -0,0 +1,118 @@
+use ic_cdk_macros::update;
+use icrc7_types::{
+    errors::MarketError,
+    icrc7_types::RoyaltyRecipient,
+    market_types::{
+        AcceptOfferArg, AcceptOfferResult, BidArg, BidResult, BuyArg, BuyResult,
+        CancelAuctionArg, CancelOfferArg, CreateAuctionArg, CreateAuctionResult, DelistArg,
+        DelistResult, ListTokenArg, ListTokenResult, MakeOfferArg, MakeOfferResult, MarketFee,
+    },
+};
+use crate::{
+    auction,
+    guards::{admin_guard, authenticated_guard, not_paused_guard, treasurer_operation_guard},
+    http, market, offer,
+    state::STATE,
+};
+
+// Lists a token for sale at a fixed price, approving the canister to transfer it on a sale.
+#[update(guard = "not_paused_guard")]
+pub fn list_token(arg: ListTokenArg) -> ListTokenResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().list_token(&caller, arg))
+}
+
+// Removes a listing and its approval.
+#[update(guard = "authenticated_guard")]
+pub fn delist(arg: DelistArg) -> DelistResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().delist(&caller, arg))
+}
+
+// Buys a listed token. The caller must have approved this canister on the listing ledger
+// for the price plus the ledger fee.
+#[update(guard = "not_paused_guard")]
+pub async fn buy(arg: BuyArg) -> BuyResult {
+    let caller = ic_cdk::caller();
+    market::buy(caller, arg).await
+}
+
+#[update(guard = "admin_guard")]
+pub fn set_market_fee(fee: Option<MarketFee>) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().set_market_fee(fee))
+}
+
+// Retries the payouts that could not be delivered, returning how many are still pending.
+#[update(guard = "treasurer_operation_guard")]
+pub async fn retry_failed_payouts() -> u64 {
+    market::retry_failed_payouts().await as u64
+}
+
+// Puts a token up for auction, moving it into escrow until the auction is settled.
+#[update(guard = "not_paused_guard")]
+pub fn create_auction(arg: CreateAuctionArg) -> CreateAuctionResult {
+    let caller = ic_cdk::caller();
+    let end_at = arg.end_at;
+    let auction_id = STATE.with(|s| s.borrow_mut().create_auction(&caller, arg))?;
+    auction::schedule_auction_settlement(auction_id, end_at);
+    Ok(auction_id)
+}
+
+// Places a bid. The caller must have approved this canister on the auction ledger
+// for the amount plus the ledger fee.
+#[update(guard = "not_paused_guard")]
+pub async fn bid(arg: BidArg) -> BidResult {
+    let caller = ic_cdk::caller();
+    auction::bid(caller, arg).await
+}
+
+// Withdraws an auction nobody bid on yet, handing the token back.
+#[update(guard = "not_paused_guard")]
+pub fn cancel_auction(arg: CancelAuctionArg) -> Result<(), MarketError> {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().cancel_auction(&caller, &arg, ic_cdk::api::time()))
+}
+
+// Settles an ended auction, in case its timer did not.
+#[update(guard = "authenticated_guard")]
+pub async fn settle_auction(auction_id: u128) -> Result<(), MarketError> {
+    auction::settle_auction(auction_id).await
+}
+
+// Places an offer on a token, on any token or on any token carrying a trait, escrowing the amount.
+// The caller must have approved this canister on the offer ledger for the amount plus the ledger fee.
+#[update(guard = "not_paused_guard")]
+pub async fn make_offer(arg: MakeOfferArg) -> MakeOfferResult {
+    let caller = ic_cdk::caller();
+    offer::make_offer(caller, arg).await
+}
+
+// Withdraws an offer, refunding the escrowed amount.
+#[update(guard = "authenticated_guard")]
+pub async fn cancel_offer(arg: CancelOfferArg) -> Result<(), MarketError> {
+    let caller = ic_cdk::caller();
+    offer::cancel_offer(caller, arg).await
+}
+
+// Accepts an offer: the token goes to the bidder and the escrowed amount to the owner.
+#[update(guard = "not_paused_guard")]
+pub async fn accept_offer(arg: AcceptOfferArg) -> AcceptOfferResult {
+    let caller = ic_cdk::caller();
+    offer::accept_offer(caller, arg).await
+}
+
+// Sets the royalties paid to the creators on every sale of the collection tokens.
+#[update(guard = "admin_guard")]
+pub fn set_royalties(royalties: Vec<RoyaltyRecipient>) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().set_royalties(royalties))?;
+    http::certify_collection();
+    Ok(())
+}
+
+#[update(guard = "admin_guard")]
+pub fn set_token_royalties(
+    token_id: u128,
+    royalties: Option<Vec<RoyaltyRecipient>>,
+) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().set_token_royalties(token_id, royalties))
+}
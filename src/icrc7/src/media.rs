@@ -0,0 +1,364 @@
+use candid::Principal;
+use icrc_ledger_types::icrc::generic_value::Value;
+use icrc7_types::{
+    errors::MediaError,
+    icrc7_types::Icrc7TokenMetadata,
+    media_types::{
+        CommitUploadResult, CreateUploadArg, CreateUploadResult, MediaAsset, MediaQuota,
+        MediaUsage, Upload, UploadChunkArg, UploadChunkResult, MEDIA_REF_PREFIX,
+    },
+};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+use crate::{http::MAX_SERVED_ASSET_SIZE, state::State};
+
+impl State {
+    pub fn create_upload(
+        &mut self,
+        caller: Principal,
+        arg: CreateUploadArg,
+        now: u64,
+    ) -> CreateUploadResult {
+        self.check_metadata_not_frozen()?;
+        if arg.content_type.trim().is_empty() {
+            return Err(MediaError::GenericError {
+                error_code: 1,
+                message: "Content Type Required".into(),
+            });
+        }
+        if arg.sha256.as_ref().is_some_and(|sha256| sha256.len() != 32) {
+            return Err(MediaError::GenericError {
+                error_code: 2,
+                message: "Invalid Sha256".into(),
+            });
+        }
+        if self.uploads.len() as u32 >= self.media_quota.max_uploads {
+            return Err(MediaError::QuotaExceeded {
+                message: "Too many uploads in progress".into(),
+            });
+        }
+        let id = self.next_upload_id;
+        self.next_upload_id += 1;
+        self.uploads.insert(
+            id,
+            Upload {
+                id,
+                uploader: caller,
+                content_type: arg.content_type,
+                sha256: arg.sha256,
+                size: 0,
+                chunks: 0,
+                created_at: now,
+            },
+        );
+        Ok(id)
+    }
+
+    // Appends the next chunk of an upload
+    pub fn upload_chunk(&mut self, caller: Principal, arg: UploadChunkArg) -> UploadChunkResult {
+        let pending_size = self.pending_media_size();
+        let upload = self.own_upload(caller, arg.upload_id)?;
+        let chunk_size = arg.content.len() as u64;
+        if upload.size + chunk_size > self.media_quota.max_asset_size {
+            return Err(MediaError::QuotaExceeded {
+                message: "The media exceeds the maximum asset size".into(),
+            });
+        }
+        if self.media_size + pending_size + chunk_size > self.media_quota.max_total_size {
+            return Err(MediaError::QuotaExceeded {
+                message: "The collection media storage is full".into(),
+            });
+        }
+        let index = upload.chunks;
+        self.upload_chunks
+            .insert((arg.upload_id, index), arg.content.into_vec());
+        let upload = self.uploads.get_mut(&arg.upload_id).unwrap();
+        upload.chunks += 1;
+        upload.size += chunk_size;
+        Ok(())
+    }
+
+    // Stores the uploaded content under its sha256, unless the same content is already stored
+    pub fn commit_upload(
+        &mut self,
+        caller: Principal,
+        upload_id: u128,
+        now: u64,
+    ) -> CommitUploadResult {
+        self.check_metadata_not_frozen()?;
+        let upload = self.own_upload(caller, upload_id)?;
+        if upload.size == 0 {
+            return Err(MediaError::GenericError {
+                error_code: 3,
+                message: "Empty Upload".into(),
+            });
+        }
+        let mut hasher = Sha256::new();
+        for index in 0..upload.chunks {
+            hasher.update(self.upload_chunks.get(&(upload_id, index)).unwrap());
+        }
+        let sha256: [u8; 32] = hasher.finalize().into();
+        if upload
+            .sha256
+            .as_ref()
+            .is_some_and(|expected| expected.as_slice() != sha256)
+        {
+            return Err(MediaError::HashMismatch {
+                sha256: sha256.to_vec(),
+            });
+        }
+
+        self.uploads.remove(&upload_id);
+        let chunks: Vec<Vec<u8>> = (0..upload.chunks)
+            .filter_map(|index| self.upload_chunks.remove(&(upload_id, index)))
+            .collect();
+        if let Some(media) = self.media.get(&sha256) {
+            return Ok(media);
+        }
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            self.media_chunks.insert((sha256, index as u32), chunk);
+        }
+        let media = MediaAsset {
+            sha256: ByteBuf::from(sha256.to_vec()),
+            content_type: upload.content_type,
+            size: upload.size,
+            chunks: upload.chunks,
+            refs: 0,
+            created_at: now,
+        };
+        self.media.insert(sha256, media.clone());
+        self.media_size += upload.size;
+        Ok(media)
+    }
+
+    pub fn cancel_upload(&mut self, caller: Principal, upload_id: u128) -> Result<(), MediaError> {
+        let upload = self.own_upload(caller, upload_id)?;
+        self.uploads.remove(&upload_id);
+        for index in 0..upload.chunks {
+            self.upload_chunks.remove(&(upload_id, index));
+        }
+        Ok(())
+    }
+
+    // Deletes media no metadata references, typically uploaded but never minted
+    pub fn delete_media(&mut self, sha256: &[u8; 32]) -> Result<(), MediaError> {
+        self.check_metadata_not_frozen()?;
+        let media = self.media.get(sha256).ok_or(MediaError::MediaNotFound)?;
+        if media.refs > 0 {
+            return Err(MediaError::InUse { refs: media.refs });
+        }
+        self.remove_media(sha256, &media);
+        Ok(())
+    }
+
+    pub fn set_media_quota(&mut self, quota: MediaQuota) -> Result<(), String> {
+        if quota.max_asset_size == 0 || quota.max_asset_size > quota.max_total_size {
+            return Err("The asset size must be positive and within the total size".into());
+        }
+        if quota.max_asset_size > MAX_SERVED_ASSET_SIZE {
+            return Err(format!(
+                "The asset size cannot exceed {} bytes, served in a single response",
+                MAX_SERVED_ASSET_SIZE
+            ));
+        }
+        self.media_quota = quota;
+        Ok(())
+    }
+
+    // Once the metadata is frozen, the media it can reference is fixed too
+    fn check_metadata_not_frozen(&self) -> Result<(), MediaError> {
+        if self.is_metadata_frozen() {
+            return Err(MediaError::GenericError {
+                error_code: 4,
+                message: "Metadata Frozen".into(),
+            });
+        }
+        Ok(())
+    }
+
+    fn own_upload(&self, caller: Principal, upload_id: u128) -> Result<Upload, MediaError> {
+        let upload = self
+            .uploads
+            .get(&upload_id)
+            .ok_or(MediaError::UploadNotFound)?;
+        if upload.uploader != caller {
+            return Err(MediaError::Unauthorized);
+        }
+        Ok(upload.clone())
+    }
+
+    fn remove_media(&mut self, sha256: &[u8; 32], media: &MediaAsset) {
+        for index in 0..media.chunks {
+            self.media_chunks.remove(&(*sha256, index));
+        }
+        self.media.remove(sha256);
+        self.media_size -= media.size;
+    }
+
+    fn pending_media_size(&self) -> u64 {
+        self.uploads.values().map(|upload| upload.size).sum()
+    }
+
+    // Metadata can only reference stored media
+    pub fn check_media(&self, meta: &Icrc7TokenMetadata) -> Result<(), MediaError> {
+        if media_refs(meta)
+            .iter()
+            .all(|sha256| self.media.contains_key(sha256))
+        {
+            Ok(())
+        } else {
+            Err(MediaError::MediaNotFound)
+        }
+    }
+
+    pub fn acquire_media(&mut self, meta: &Icrc7TokenMetadata) {
+        for sha256 in media_refs(meta) {
+            if let Some(mut media) = self.media.get(&sha256) {
+                media.refs += 1;
+                self.media.insert(sha256, media);
+            }
+        }
+    }
+
+    // Media no longer referenced goes along with the metadata that referenced it
+    pub fn release_media(&mut self, meta: &Icrc7TokenMetadata) {
+        for sha256 in media_refs(meta) {
+            if let Some(mut media) = self.media.get(&sha256) {
+                media.refs = media.refs.saturating_sub(1);
+                if media.refs == 0 {
+                    self.remove_media(&sha256, &media);
+                } else {
+                    self.media.insert(sha256, media);
+                }
+            }
+        }
+    }
+
+    pub fn media_content(&self, sha256: &[u8; 32]) -> Option<(MediaAsset, Vec<u8>)> {
+        let media = self.media.get(sha256)?;
+        let mut content = Vec::with_capacity(media.size as usize);
+        for index in 0..media.chunks {
+            content.extend(self.media_chunks.get(&(*sha256, index))?);
+        }
+        Some((media, content))
+    }
+
+    pub fn media_usage(&self) -> MediaUsage {
+        MediaUsage {
+            stored_size: self.media_size,
+            pending_size: self.pending_media_size(),
+            assets: self.media.len(),
+            quota: self.media_quota.clone(),
+        }
+    }
+}
+
+// The media referenced by `sha256:<hex>` text values, nested ones included
+pub fn media_refs(meta: &Icrc7TokenMetadata) -> Vec<[u8; 32]> {
+    fn collect(value: &Value, refs: &mut Vec<[u8; 32]>) {
+        match value {
+            Value::Text(text) => refs.extend(media_ref(text)),
+            Value::Array(values) => values.iter().for_each(|value| collect(value, refs)),
+            Value::Map(map) => map.values().for_each(|value| collect(value, refs)),
+            _ => {}
+        }
+    }
+    let mut refs = vec![];
+    meta.values().for_each(|value| collect(value, &mut refs));
+    refs
+}
+
+pub fn media_ref(text: &str) -> Option<[u8; 32]> {
+    let hex = text.strip_prefix(MEDIA_REF_PREFIX)?;
+    hex::decode(hex).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn media_is_deduplicated_and_lives_with_its_references() {
+        let uploader = Principal::from_slice(&[1]);
+        let mut state = State::default();
+        let upload = |state: &mut State, sha256: Option<ByteBuf>| {
+            let id = state
+                .create_upload(
+                    uploader,
+                    CreateUploadArg {
+                        content_type: "image/png".into(),
+                        sha256,
+                    },
+                    1,
+                )
+                .unwrap();
+            for chunk in [b"\x89PNG".to_vec(), b"pixels".to_vec()] {
+                let arg = UploadChunkArg {
+                    upload_id: id,
+                    content: ByteBuf::from(chunk),
+                };
+                state.upload_chunk(uploader, arg).unwrap();
+            }
+            id
+        };
+
+        let id = upload(&mut state, None);
+        assert_eq!(
+            state.commit_upload(Principal::from_slice(&[2]), id, 2),
+            Err(MediaError::Unauthorized)
+        );
+        let media = state.commit_upload(uploader, id, 2).unwrap();
+        let sha256: [u8; 32] = media.sha256.as_slice().try_into().unwrap();
+        assert_eq!(state.media_content(&sha256).unwrap().1, b"\x89PNGpixels");
+
+        let id = upload(&mut state, Some(ByteBuf::from(vec![0; 32])));
+        assert!(matches!(
+            state.commit_upload(uploader, id, 3),
+            Err(MediaError::HashMismatch { .. })
+        ));
+        state.cancel_upload(uploader, id).unwrap();
+        let id = upload(&mut state, Some(media.sha256.clone()));
+        assert_eq!(state.commit_upload(uploader, id, 3), Ok(media));
+        assert_eq!(state.media_size, 10);
+        assert!(state.upload_chunks.is_empty());
+
+        let meta = Icrc7TokenMetadata::from([(
+            "image".to_string(),
+            Value::text(format!("sha256:{}", hex::encode(sha256))),
+        )]);
+        let missing = Icrc7TokenMetadata::from([(
+            "image".to_string(),
+            Value::text(format!("sha256:{}", hex::encode([7; 32]))),
+        )]);
+        assert_eq!(state.check_media(&meta), Ok(()));
+        assert_eq!(state.check_media(&missing), Err(MediaError::MediaNotFound));
+        state.acquire_media(&meta);
+        state.acquire_media(&meta);
+        assert_eq!(state.delete_media(&sha256), Err(MediaError::InUse { refs: 2 }));
+        state.release_media(&meta);
+        state.release_media(&meta);
+        assert!(state.media.is_empty());
+        assert!(state.media_chunks.is_empty());
+        assert_eq!(state.media_size, 0);
+
+        state.media_quota.max_asset_size = 8;
+        let id = state
+            .create_upload(
+                uploader,
+                CreateUploadArg {
+                    content_type: "image/png".into(),
+                    sha256: None,
+                },
+                4,
+            )
+            .unwrap();
+        let arg = UploadChunkArg {
+            upload_id: id,
+            content: ByteBuf::from(vec![0; 9]),
+        };
+        assert!(matches!(
+            state.upload_chunk(uploader, arg),
+            Err(MediaError::QuotaExceeded { .. })
+        ));
+    }
+}
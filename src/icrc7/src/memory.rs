@@ -3,8 +3,14 @@ use ic_stable_structures::{
     DefaultMemoryImpl, StableBTreeMap,
 };
 use icrc7_types::{
+    compliance_types::FreezeRecord,
     icrc37_types::{CollectionApprovalInfo, TokenApprovalInfo, UserAccount},
-    icrc7_types::Transaction
+    icrc7_types::Transaction,
+    market_types::{Auction, Listing, Offer},
+    media_types::MediaAsset,
+    multisig_types::Proposal,
+    series_types::Series,
+    revenue_types::RevenueEntry,
 };
 use crate::state::{Icrc7Token, MEMORY_MANAGER};
 
@@ -33,3 +39,63 @@ pub fn get_collection_approvals_memory(
 ) -> StableBTreeMap<UserAccount, CollectionApprovalInfo, Memory> {
     StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))))
 }
+
+pub fn get_listings_memory() -> StableBTreeMap<u128, Listing, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5))))
+}
+
+pub fn get_auctions_memory() -> StableBTreeMap<u128, Auction, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6))))
+}
+
+pub fn get_offers_memory() -> StableBTreeMap<u128, Offer, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7))))
+}
+
+pub fn get_revenue_history_memory() -> StableBTreeMap<u128, RevenueEntry, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8))))
+}
+
+pub fn get_frozen_tokens_memory() -> StableBTreeMap<u128, FreezeRecord, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9))))
+}
+
+pub fn get_frozen_accounts_memory() -> StableBTreeMap<UserAccount, FreezeRecord, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10))))
+}
+
+pub fn get_proposals_memory() -> StableBTreeMap<u128, Proposal, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11))))
+}
+
+pub fn get_series_memory() -> StableBTreeMap<u128, Series, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12))))
+}
+
+pub fn get_series_tokens_memory() -> StableBTreeMap<(u128, u32), u128, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13))))
+}
+
+pub fn get_media_memory() -> StableBTreeMap<[u8; 32], MediaAsset, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14))))
+}
+
+pub fn get_media_chunks_memory() -> StableBTreeMap<([u8; 32], u32), Vec<u8>, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15))))
+}
+
+pub fn get_upload_chunks_memory() -> StableBTreeMap<(u128, u32), Vec<u8>, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16))))
+}
+
+pub fn get_trait_index_memory() -> StableBTreeMap<(String, String, u128), (), Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17))))
+}
+
+pub fn get_ownership_history_memory() -> StableBTreeMap<(u128, u128), UserAccount, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18))))
+}
+
+pub fn get_response_hashes_memory() -> StableBTreeMap<String, [u8; 32], Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19))))
+}
@@ -0,0 +1,834 @@
+use std::{
+    collections::BTreeMap,
+    ops::Bound::{Excluded, Unbounded},
+};
+use candid::Principal;
+use icrc_ledger_types::{icrc::generic_value::Hash, icrc1::account::Account};
+use icrc7_types::{
+    compliance_types::FreezeRecord,
+    icrc37_types::UserAccount,
+    icrc3_types::{Block, QueryBlock},
+    market_types::AuctionStatus,
+    media_types::MediaAsset,
+    migration_types::{
+        ArchivesExport, CollectionApprovalsExport, ConfigExport, TokenApprovalsExport, TokenExport,
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+use crate::{
+    ledger::Ledger,
+    state::{Icrc7Token, State, STATE},
+    utils::token_account,
+};
+
+// Allows the `export_*` queries once nothing the migration leaves behind holds funds: the
+// collection must be paused, with no auction or offer escrowing funds, and the token accounts
+// must hold nothing on `ledgers`, as they would stay with this canister
+pub(crate) async fn prepare_export<L: Ledger>(ledgers: &[L]) -> Result<(), String> {
+    let token_ids: Vec<u128> = STATE.with_borrow(|s| {
+        s.check_market_closed()?;
+        Ok::<_, String>(s.tokens.keys().collect())
+    })?;
+    for ledger in ledgers {
+        for token_id in &token_ids {
+            if ledger.balance_of(token_account(*token_id)).await? > 0 {
+                return Err(format!("The account of token {token_id} holds funds"));
+            }
+        }
+    }
+    STATE.with_borrow_mut(|s| {
+        // the collection may have been unpaused while the balances were read
+        s.check_market_closed()?;
+        s.export_prepared = true;
+        Ok(())
+    })
+}
+
+// The hash of the last block the archives of an exported collection hold
+pub async fn archived_tip(log: &ArchivesExport) -> Result<Option<Hash>, String> {
+    let Some(index) = log.first_local_block.checked_sub(1) else {
+        return Ok(None);
+    };
+    let archive = log
+        .archives
+        .iter()
+        .find(|(_, range)| range.start <= index && index < range.start + range.length)
+        .map(|(canister_id, _)| *canister_id)
+        .ok_or_else(|| format!("No archive holds block {index}"))?;
+    let (block,): (Option<Block>,) = ic_cdk::call(archive, "get_transaction", (index,))
+        .await
+        .map_err(|(code, msg)| format!("Rejection Code: {:?}, Message: {:?}", code, msg))?;
+    let block = block.ok_or_else(|| format!("The archive does not hold block {index}"))?;
+    Ok(Some(block.value().hash()))
+}
+
+// Set on a collection installed in migration mode, which refuses normal traffic until the
+// migration is finalized
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Migration {
+    // its owner, roles, multisig and pause are only applied when finalizing, not to lock the
+    // importer out midway
+    pub config: Option<ConfigExport>,
+    pub log: Option<ArchivesExport>,
+    // the media whose chunks are still being imported, stored once they all are
+    #[serde(default)]
+    pub media: BTreeMap<[u8; 32], MediaAsset>,
+}
+
+impl State {
+    fn check_market_closed(&self) -> Result<(), String> {
+        if !self.paused {
+            return Err("The collection must be paused".into());
+        }
+        if self.auctions.iter().any(|(_, auction)| {
+            matches!(auction.status, AuctionStatus::Active | AuctionStatus::Settling)
+        }) {
+            return Err("Auctions are still open".into());
+        }
+        if !self.offers.is_empty() {
+            return Err("Offers are still open".into());
+        }
+        Ok(())
+    }
+
+    pub fn check_exportable(&self) -> Result<(), String> {
+        if !self.export_prepared {
+            return Err("The export was not prepared".into());
+        }
+        self.check_market_closed()
+    }
+
+    pub fn export_tokens(&self, prev: Option<u128>, take: Option<u128>) -> Vec<TokenExport> {
+        let take = self.get_current_take(take) as usize;
+        let start = prev.map_or(0, |prev| prev + 1);
+        self.tokens
+            .range(start..)
+            .take(take)
+            .map(|(token_id, token)| TokenExport {
+                token_id,
+                owner: token.token_owner,
+                meta: token.token_meta,
+                edition: token.edition,
+                user: token.token_user,
+                royalties: self.token_royalties.get(&token_id).cloned(),
+                locked: self.locked_tokens.contains(&token_id),
+                parent: self.parents.get(&token_id).copied(),
+            })
+            .collect()
+    }
+
+    pub fn export_token_approvals(
+        &self,
+        prev: Option<u128>,
+        take: Option<u128>,
+    ) -> Vec<TokenApprovalsExport> {
+        let take = self.get_current_take(take) as usize;
+        let start = prev.map_or(0, |prev| prev + 1);
+        self.token_approvals
+            .range(start..)
+            .take(take)
+            .map(|(token_id, approvals)| TokenApprovalsExport {
+                token_id,
+                approvals,
+            })
+            .collect()
+    }
+
+    pub fn export_collection_approvals(
+        &self,
+        prev: Option<Account>,
+        take: Option<u128>,
+    ) -> Vec<CollectionApprovalsExport> {
+        let take = self.get_current_take(take) as usize;
+        let start = prev.map_or(Unbounded, |prev| Excluded(UserAccount::new(prev)));
+        self.collection_approvals
+            .range((start, Unbounded))
+            .take(take)
+            .map(|(owner, approvals)| CollectionApprovalsExport {
+                owner: owner.into(),
+                approvals,
+            })
+            .collect()
+    }
+
+    pub fn export_config(&self) -> ConfigExport {
+        ConfigExport {
+            owner: self.owner,
+            roles: self
+                .roles
+                .iter()
+                .map(|(principal, roles)| (*principal, roles.iter().copied().collect()))
+                .collect(),
+            minting_authority: self.minting_authority,
+            symbol: self.icrc7_symbol.clone(),
+            name: self.icrc7_name.clone(),
+            description: self.icrc7_description.clone(),
+            logo: self.icrc7_logo.clone(),
+            supply_cap: self.icrc7_supply_cap,
+            total_supply: self.icrc7_total_supply,
+            next_token_id: self.next_token_id,
+            max_query_batch_size: self.icrc7_max_query_batch_size,
+            max_update_batch_size: self.icrc7_max_update_batch_size,
+            max_take_value: self.icrc7_max_take_value,
+            default_take_value: self.icrc7_default_take_value,
+            max_memo_size: self.icrc7_max_memo_size,
+            atomic_batch_transfers: self.icrc7_atomic_batch_transfers,
+            tx_window: self.tx_window,
+            permitted_drift: self.permitted_drift,
+            approval_ledger_info: self.approval_ledger_info.clone(),
+            archive_setting: self.archive_ledger_info.setting.clone(),
+            royalties: self.royalties.clone(),
+            revenue_split: self.revenue_split.clone(),
+            market_fee: self.market_fee.clone(),
+            transfer_policy: self.transfer_policy,
+            multisig: self.multisig.clone(),
+            finalization: self.finalization.clone(),
+            metadata_schema: self.metadata_schema.clone(),
+            trait_keys: self.trait_keys.iter().cloned().collect(),
+            media_quota: self.media_quota.clone(),
+            assets_canister: self.assets_canister,
+            series: self.series.iter().map(|(_, series)| series).collect(),
+            paused: self.paused,
+        }
+    }
+
+    pub fn export_archives(&self) -> ArchivesExport {
+        ArchivesExport {
+            archives: self
+                .archive_ledger_info
+                .archives
+                .iter()
+                .map(|(canister_id, range)| (*canister_id, range.clone()))
+                .collect(),
+            first_local_block: self.archive_txn_count,
+            log_length: self.txn_count,
+            last_hash: self
+                .archive_ledger_info
+                .latest_hash
+                .map(|hash| ByteBuf::from(hash.to_vec())),
+        }
+    }
+
+    // The blocks the collection has not archived yet
+    pub fn export_log_blocks(&self, prev: Option<u128>, take: Option<u128>) -> Vec<QueryBlock> {
+        let take = self.get_current_take(take) as usize;
+        let start = prev.map_or(0, |prev| prev + 1);
+        self.txn_ledger
+            .range(start..)
+            .take(take)
+            .filter_map(|(id, txn)| {
+                Some(QueryBlock {
+                    id,
+                    block: txn.block?.into_inner(),
+                })
+            })
+            .collect()
+    }
+
+    // The stored media after `prev`, its chunks being read with `get_media_chunk`
+    pub fn export_media(&self, prev: Option<ByteBuf>, take: Option<u128>) -> Vec<MediaAsset> {
+        let take = self.get_current_take(take) as usize;
+        let start = prev
+            .and_then(|prev| <[u8; 32]>::try_from(prev.as_slice()).ok())
+            .map_or(Unbounded, Excluded);
+        self.media
+            .range((start, Unbounded))
+            .take(take)
+            .map(|(_, media)| media)
+            .collect()
+    }
+
+    fn migration_mut(&mut self) -> Result<&mut Migration, String> {
+        self.migration
+            .as_mut()
+            .ok_or_else(|| String::from("The collection is not being migrated"))
+    }
+
+    pub fn import_config(&mut self, config: ConfigExport) -> Result<(), String> {
+        self.migration_mut()?;
+        self.minting_authority = config.minting_authority;
+        self.icrc7_symbol = config.symbol.clone();
+        self.icrc7_name = config.name.clone();
+        self.icrc7_description = config.description.clone();
+        self.icrc7_logo = config.logo.clone();
+        self.icrc7_supply_cap = config.supply_cap;
+        self.icrc7_total_supply = config.total_supply;
+        self.next_token_id = config.next_token_id;
+        self.icrc7_max_query_batch_size = config.max_query_batch_size;
+        self.icrc7_max_update_batch_size = config.max_update_batch_size;
+        self.icrc7_max_take_value = config.max_take_value;
+        self.icrc7_default_take_value = config.default_take_value;
+        self.icrc7_max_memo_size = config.max_memo_size;
+        self.icrc7_atomic_batch_transfers = config.atomic_batch_transfers;
+        self.tx_window = config.tx_window;
+        self.permitted_drift = config.permitted_drift;
+        self.approval_ledger_info = config.approval_ledger_info.clone();
+        self.archive_ledger_info.setting = config.archive_setting.clone();
+        self.royalties = config.royalties.clone();
+        self.revenue_split = config.revenue_split.clone();
+        self.market_fee = config.market_fee.clone();
+        self.transfer_policy = config.transfer_policy;
+        self.finalization = config.finalization.clone();
+        self.metadata_schema = config.metadata_schema.clone();
+        self.trait_keys = config.trait_keys.iter().cloned().collect();
+        self.media_quota = config.media_quota.clone();
+        self.assets_canister = config.assets_canister;
+        for series in &config.series {
+            self.series.insert(series.id, series.clone());
+        }
+        self.migration_mut()?.config = Some(config);
+        Ok(())
+    }
+
+    // Equipped tokens move to the same token account of this canister
+    pub fn import_tokens(
+        &mut self,
+        tokens: Vec<TokenExport>,
+        canister: Principal,
+    ) -> Result<(), String> {
+        self.migration_mut()?;
+        for token in tokens {
+            let token_id = token.token_id;
+            let mut owner = token.owner;
+            if let Some(parent_id) = token.parent {
+                owner.owner = canister;
+                self.parents.insert(token_id, parent_id);
+            }
+            if let Some(edition) = token.edition {
+                self.series_tokens
+                    .insert((edition.series_id, edition.number), token_id);
+            }
+            if let Some(user) = token.user {
+                self.rentals.insert(token_id, user.expires_at);
+            }
+            if let Some(royalties) = token.royalties {
+                self.token_royalties.insert(token_id, royalties);
+            }
+            if token.locked {
+                self.locked_tokens.insert(token_id);
+            }
+            let token = Icrc7Token {
+                token_id,
+                token_owner: owner,
+                token_meta: token.meta,
+                edition: token.edition,
+                token_user: token.user,
+            };
+            self.tokens.insert(token_id, token);
+        }
+        Ok(())
+    }
+
+    pub fn import_token_approvals(
+        &mut self,
+        approvals: Vec<TokenApprovalsExport>,
+    ) -> Result<(), String> {
+        self.migration_mut()?;
+        for export in approvals {
+            self.token_approvals.insert(export.token_id, export.approvals);
+        }
+        Ok(())
+    }
+
+    pub fn import_collection_approvals(
+        &mut self,
+        approvals: Vec<CollectionApprovalsExport>,
+    ) -> Result<(), String> {
+        self.migration_mut()?;
+        for export in approvals {
+            self.collection_approvals
+                .insert(UserAccount::new(export.owner), export.approvals);
+        }
+        Ok(())
+    }
+
+    pub fn import_frozen_tokens(
+        &mut self,
+        freezes: Vec<(u128, FreezeRecord)>,
+    ) -> Result<(), String> {
+        self.migration_mut()?;
+        for (token_id, record) in freezes {
+            self.frozen_tokens.insert(token_id, record);
+        }
+        Ok(())
+    }
+
+    pub fn import_frozen_accounts(
+        &mut self,
+        freezes: Vec<(Account, FreezeRecord)>,
+    ) -> Result<(), String> {
+        self.migration_mut()?;
+        for (account, record) in freezes {
+            self.frozen_accounts.insert(UserAccount::new(account), record);
+        }
+        Ok(())
+    }
+
+    // Declares the media to import, their content following with `import_media_chunk`
+    pub fn import_media(&mut self, media: Vec<MediaAsset>) -> Result<(), String> {
+        self.migration_mut()?;
+        for asset in media {
+            let sha256 = parse_sha256(&asset.sha256)?;
+            if asset.chunks == 0 {
+                return Err("The media has no content".into());
+            }
+            if self.media.contains_key(&sha256) {
+                continue;
+            }
+            // the references are counted again when finalizing
+            let asset = MediaAsset { refs: 0, ..asset };
+            self.migration_mut()?.media.insert(sha256, asset);
+        }
+        Ok(())
+    }
+
+    // Stores a chunk of imported media, the media being stored once its content is complete
+    // and hashes to its sha256
+    pub fn import_media_chunk(
+        &mut self,
+        sha256: ByteBuf,
+        index: u32,
+        content: ByteBuf,
+    ) -> Result<(), String> {
+        let sha256 = parse_sha256(&sha256)?;
+        let media = self
+            .migration_mut()?
+            .media
+            .get(&sha256)
+            .cloned()
+            .ok_or_else(|| String::from("The media is not being imported"))?;
+        if index >= media.chunks {
+            return Err(format!("The media has {} chunks", media.chunks));
+        }
+        self.media_chunks.insert((sha256, index), content.into_vec());
+        if !(0..media.chunks).all(|index| self.media_chunks.contains_key(&(sha256, index))) {
+            return Ok(());
+        }
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        for index in 0..media.chunks {
+            let chunk = self.media_chunks.get(&(sha256, index)).unwrap();
+            size += chunk.len() as u64;
+            hasher.update(chunk);
+        }
+        let hash: [u8; 32] = hasher.finalize().into();
+        if hash != sha256 || size != media.size {
+            for index in 0..media.chunks {
+                self.media_chunks.remove(&(sha256, index));
+            }
+            return Err("The media content does not match its hash".into());
+        }
+        self.migration_mut()?.media.remove(&sha256);
+        self.media_size += media.size;
+        self.media.insert(sha256, media);
+        Ok(())
+    }
+
+    // Points at the archives of the previous collection, the blocks it did not archive being
+    // imported next with `import_log_blocks`. `archived_tip` is the hash of the last archived
+    // block, read from its archive.
+    pub fn import_archives(
+        &mut self,
+        log: ArchivesExport,
+        archived_tip: Option<Hash>,
+    ) -> Result<(), String> {
+        self.migration_mut()?;
+        if !self.txn_ledger.is_empty() {
+            return Err("The log blocks are already being imported".into());
+        }
+        if archived_tip.is_some() != (log.first_local_block > 0) {
+            return Err("The last archived block does not match the archives".into());
+        }
+        let last_hash = match log.last_hash {
+            Some(ref hash) => Some(
+                hash.as_slice()
+                    .try_into()
+                    .map_err(|_| String::from("Invalid last hash"))?,
+            ),
+            None => None,
+        };
+        if log.first_local_block == log.log_length && archived_tip != last_hash {
+            return Err("The archives do not end at the exported tip".into());
+        }
+        // the archives only take blocks from the collection that created them
+        self.closed_archives = log.archives.iter().map(|(canister_id, _)| *canister_id).collect();
+        let info = &mut self.archive_ledger_info;
+        info.archives = log.archives.iter().cloned().collect();
+        info.first_index = log.first_local_block;
+        info.last_index = log.first_local_block;
+        info.local_ledger_size = 0;
+        // the first block not archived links to the last archived one
+        info.latest_hash = archived_tip;
+        self.txn_count = log.first_local_block;
+        self.archive_txn_count = log.first_local_block;
+        self.migration_mut()?.log = Some(log);
+        Ok(())
+    }
+
+    // Appends the blocks the previous collection did not archive, in order, checking they
+    // link to each other
+    pub fn import_log_blocks(&mut self, blocks: Vec<QueryBlock>) -> Result<u128, String> {
+        self.migration_mut()?
+            .log
+            .as_ref()
+            .ok_or_else(|| String::from("The archives must be imported first"))?;
+        for QueryBlock { id, block } in blocks {
+            if id != self.txn_count {
+                return Err(format!("Expected block {}, got block {id}", self.txn_count));
+            }
+            let block = Block::try_from(block)?;
+            let txn = block.transaction()?;
+            let phash = match block.value() {
+                icrc_ledger_types::icrc::generic_value::Value::Map(map) => map.get("phash"),
+                _ => None,
+            };
+            let phash = match phash {
+                Some(icrc_ledger_types::icrc::generic_value::Value::Blob(phash)) => {
+                    phash.as_slice().try_into().ok()
+                }
+                _ => None,
+            };
+            if phash != self.archive_ledger_info.latest_hash {
+                return Err(format!("The phash of block {id} breaks the chain"));
+            }
+            self.archive_ledger_info.latest_hash = Some(block.value().hash());
+            self.txn_ledger.insert(id, txn);
+            self.txn_count += 1;
+            self.archive_ledger_info.last_index += 1;
+            self.archive_ledger_info.local_ledger_size += 1;
+        }
+        Ok(self.txn_count)
+    }
+
+    // Checks the whole log was imported, applies the access control and rebuilds what derives
+    // from the tokens
+    pub fn finalize_migration(&mut self) -> Result<(), String> {
+        let migration = self.migration_mut()?.clone();
+        let config = migration
+            .config
+            .ok_or_else(|| String::from("The config was not imported"))?;
+        let log = migration
+            .log
+            .ok_or_else(|| String::from("The archives were not imported"))?;
+        if self.txn_count != log.log_length {
+            return Err(format!(
+                "{} of the {} blocks were imported",
+                self.txn_count, log.log_length
+            ));
+        }
+        let latest_hash = self
+            .archive_ledger_info
+            .latest_hash
+            .map(|hash| ByteBuf::from(hash.to_vec()));
+        if latest_hash != log.last_hash {
+            return Err("The imported log does not end at the exported tip".into());
+        }
+        if !migration.media.is_empty() {
+            return Err(format!("{} media were not fully imported", migration.media.len()));
+        }
+        let metas: Vec<_> = self
+            .tokens
+            .iter()
+            .map(|(token_id, token)| (format!("Token {token_id}"), token.token_meta))
+            .chain(
+                self.series
+                    .iter()
+                    .map(|(series_id, series)| (format!("Series {series_id}"), series.meta)),
+            )
+            .collect();
+        if let Some((name, _)) = metas.iter().find(|(_, meta)| self.check_media(meta).is_err()) {
+            return Err(format!("{name} references media that was not imported"));
+        }
+
+        self.owner = config.owner;
+        self.roles = config
+            .roles
+            .into_iter()
+            .map(|(principal, roles)| (principal, roles.into_iter().collect()))
+            .collect();
+        self.multisig = config.multisig;
+        self.paused = config.paused;
+        self.holdings = None;
+        self.migrate_holdings();
+        self.ownership_history_start = None;
+        self.migrate_ownership_history();
+        let trait_keys = self.trait_keys.iter().cloned().collect();
+        self.set_trait_keys(trait_keys)?;
+        for (_, meta) in &metas {
+            self.acquire_media(meta);
+        }
+        self.migration = None;
+        Ok(())
+    }
+}
+
+fn parse_sha256(sha256: &ByteBuf) -> Result<[u8; 32], String> {
+    sha256
+        .as_slice()
+        .try_into()
+        .map_err(|_| String::from("Invalid sha256"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        market::tests::{account, block_on, LocalLedger},
+        utils::{canister_id, token_subaccount},
+    };
+    use icrc_ledger_types::icrc::generic_value::Value;
+    use icrc7_types::{
+        icrc37_types::{ApprovalInfo, CollectionApprovalInfo, TokenApprovalInfo},
+        icrc3_types::TransactionRange,
+        icrc7_types::{Icrc7TokenMetadata, Transaction},
+        market_types::{Offer, OfferTarget},
+        media_types::{CreateUploadArg, UploadChunkArg, MEDIA_REF_PREFIX},
+        rbac_types::Role,
+    };
+
+    fn upload_arg() -> CreateUploadArg {
+        CreateUploadArg {
+            content_type: "image/png".into(),
+            sha256: None,
+        }
+    }
+
+    fn media_text(media: &MediaAsset) -> String {
+        format!("{MEDIA_REF_PREFIX}{}", hex::encode(&media.sha256))
+    }
+
+    struct Export {
+        config: ConfigExport,
+        tokens: Vec<Vec<TokenExport>>,
+        token_approvals: Vec<TokenApprovalsExport>,
+        collection_approvals: Vec<CollectionApprovalsExport>,
+        archives: ArchivesExport,
+        blocks: Vec<QueryBlock>,
+        media: Vec<MediaAsset>,
+        chunks: Vec<ByteBuf>,
+        frozen_tokens: Vec<(u128, FreezeRecord)>,
+        frozen_accounts: Vec<(Account, FreezeRecord)>,
+        archived_tip: Option<Hash>,
+        tip: Option<Hash>,
+    }
+
+    // Exports a collection whose first block was archived. The stable memory being per thread,
+    // it lives on its own thread not to share the maps of the collection importing it.
+    fn export_collection() -> Export {
+        std::thread::spawn(|| {
+            let (alice, bob) = (account(1), account(2));
+            let mut old = State {
+                icrc7_max_take_value: Some(100),
+                ..Default::default()
+            };
+            // the tokens share an image stored in two chunks
+            let image = old.create_upload(alice.owner, upload_arg(), 0).unwrap();
+            for content in [b"ro".to_vec(), b"se".to_vec()] {
+                let arg = UploadChunkArg {
+                    upload_id: image,
+                    content: ByteBuf::from(content),
+                };
+                old.upload_chunk(alice.owner, arg).unwrap();
+            }
+            let image = old.commit_upload(alice.owner, image, 0).unwrap();
+            let meta = Icrc7TokenMetadata::from([
+                ("name".to_string(), Value::text("Rose")),
+                ("image".to_string(), Value::text(media_text(&image))),
+            ]);
+            let mut phash = None;
+            let mut archived_tip = None;
+            for txn in [
+                Transaction::mint(1, 1, Some(alice), alice, meta.clone(), None),
+                Transaction::mint(2, 2, Some(alice), alice, meta.clone(), None),
+                Transaction::transfer(3, 2, alice, bob, None),
+            ] {
+                let block = Block::new(phash, txn.clone());
+                phash = Some(block.value().hash());
+                if old.txn_count > 0 {
+                    let txn = Transaction {
+                        block: Some(block),
+                        ..txn
+                    };
+                    old.txn_ledger.insert(old.txn_count, txn);
+                } else {
+                    archived_tip = phash;
+                }
+                old.txn_count += 1;
+            }
+            old.archive_txn_count = 1;
+            let info = &mut old.archive_ledger_info;
+            info.first_index = 1;
+            info.last_index = 3;
+            info.local_ledger_size = 2;
+            info.latest_hash = phash;
+            let range = TransactionRange {
+                start: 0,
+                length: 1,
+            };
+            info.archives.insert(Principal::from_slice(&[8]), range);
+
+            old.owner = Some(alice.owner);
+            old.roles.insert(alice.owner, [Role::Minter].into());
+            old.icrc7_name = "Flowers".into();
+            old.trait_keys.insert("name".into());
+            // token 3 is equipped to token 2
+            let equipped = Account {
+                owner: Principal::from_slice(&[9]),
+                subaccount: Some(token_subaccount(2)),
+            };
+            for (token_id, owner) in [(1, alice), (2, bob), (3, equipped)] {
+                let token = Icrc7Token {
+                    token_id,
+                    token_owner: owner,
+                    token_meta: meta.clone(),
+                    edition: None,
+                    token_user: None,
+                };
+                old.tokens.insert(token_id, token);
+            }
+            old.parents.insert(3, 2);
+            let approval = ApprovalInfo::new(None, alice, None, None, None);
+            old.token_approvals.insert(2, TokenApprovalInfo::new(bob, approval));
+            let approval = ApprovalInfo::new(None, bob, None, None, None);
+            old.collection_approvals
+                .insert(UserAccount::new(alice), CollectionApprovalInfo::new(bob, approval));
+            let record = FreezeRecord {
+                reason: "stolen".into(),
+                by: alice,
+                at: 0,
+            };
+            old.frozen_tokens.insert(1, record.clone());
+            old.frozen_accounts.insert(UserAccount::new(bob), record);
+            let sha256: [u8; 32] = image.sha256.as_slice().try_into().unwrap();
+
+            Export {
+                config: old.export_config(),
+                tokens: vec![old.export_tokens(None, Some(2)), old.export_tokens(Some(2), None)],
+                token_approvals: old.export_token_approvals(None, None),
+                collection_approvals: old.export_collection_approvals(None, None),
+                archives: old.export_archives(),
+                blocks: old.export_log_blocks(None, None),
+                media: old.export_media(None, None),
+                chunks: (0..image.chunks)
+                    .map(|index| ByteBuf::from(old.media_chunks.get(&(sha256, index)).unwrap()))
+                    .collect(),
+                frozen_tokens: old.get_frozen_tokens(None, None),
+                frozen_accounts: old.get_frozen_accounts(None, None),
+                archived_tip,
+                tip: phash,
+            }
+        })
+        .join()
+        .unwrap()
+    }
+
+    #[test]
+    fn collections_migrate_with_their_log() {
+        let (alice, bob) = (account(1), account(2));
+        let export = export_collection();
+        assert_eq!(export.tokens.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(export.blocks.len(), 2);
+
+        let canister = Principal::from_slice(&[10]);
+        let mut new = State::default();
+        assert!(new.import_config(export.config.clone()).is_err());
+        new.migration = Some(Migration::default());
+        new.import_config(export.config).unwrap();
+        for tokens in export.tokens {
+            new.import_tokens(tokens, canister).unwrap();
+        }
+        new.import_token_approvals(export.token_approvals).unwrap();
+        new.import_collection_approvals(export.collection_approvals).unwrap();
+        assert!(new.import_log_blocks(export.blocks.clone()).is_err());
+        assert!(new.import_archives(export.archives.clone(), None).is_err());
+        // the first block must link to the one the archives end with
+        new.import_archives(export.archives.clone(), Some([0; 32])).unwrap();
+        assert!(new.import_log_blocks(export.blocks[..1].to_vec()).is_err());
+        new.import_archives(export.archives, export.archived_tip).unwrap();
+        assert!(new.writable_archive().is_none());
+        assert!(new.import_log_blocks(export.blocks[1..].to_vec()).is_err());
+        assert_eq!(new.import_log_blocks(export.blocks[..1].to_vec()), Ok(2));
+        assert!(new.finalize_migration().is_err());
+        assert_eq!(new.import_log_blocks(export.blocks[1..].to_vec()), Ok(3));
+        new.import_frozen_tokens(export.frozen_tokens).unwrap();
+        new.import_frozen_accounts(export.frozen_accounts).unwrap();
+        // the tokens reference media that was not imported
+        assert!(new.finalize_migration().is_err());
+        new.import_media(export.media.clone()).unwrap();
+        let sha256 = export.media[0].sha256.clone();
+        let chunk = |index: usize| export.chunks[index].clone();
+        new.import_media_chunk(sha256.clone(), 1, chunk(1)).unwrap();
+        assert!(new.finalize_migration().is_err());
+        assert!(new.import_media_chunk(sha256.clone(), 0, chunk(1)).is_err());
+        new.import_media_chunk(sha256.clone(), 1, chunk(1)).unwrap();
+        new.import_media_chunk(sha256.clone(), 0, chunk(0)).unwrap();
+        new.finalize_migration().unwrap();
+        assert!(new.migration.is_none());
+
+        assert_eq!(new.icrc7_name, "Flowers");
+        assert_eq!(new.owner, Some(alice.owner));
+        assert!(new.has_role(&alice.owner, Role::Minter));
+        assert_eq!(new.icrc7_owner_of(&[1, 2]), vec![Some(alice), Some(bob)]);
+        assert_eq!(new.tokens.get(&3).unwrap().token_owner.owner, canister);
+        assert_eq!(new.parents.get(&3), Some(&2));
+        assert!(new.is_approved_by_token(&2, &bob, &alice, 0));
+        let approvals = new.collection_approvals.get(&UserAccount::new(alice)).unwrap();
+        assert!(approvals.into_map().contains_key(&bob));
+        assert_eq!(new.holder_stats().holders, 2);
+        assert_eq!(new.trait_counts.get(&("name".into(), "Rose".into())), Some(&3));
+        // the next block links to the previous collection's tip
+        assert_eq!(new.archive_ledger_info.latest_hash, export.tip);
+        assert_eq!(new.txn_count, 3);
+        assert_eq!(new.export_archives().first_local_block, 1);
+        assert_eq!(new.export_archives().archives[0].0, Principal::from_slice(&[8]));
+        // the media is referenced by the three tokens
+        assert_eq!(new.export_media(None, None)[0].refs, 3);
+        assert_eq!(new.media_content(&parse_sha256(&sha256).unwrap()).unwrap().1, b"rose");
+        assert!(new.is_frozen(&1, &[]));
+        assert!(new.is_any_account_frozen(&[&bob]));
+    }
+
+    #[test]
+    fn exports_wait_for_the_market_to_close_and_the_token_accounts_to_empty() {
+        let ledger = LocalLedger::new(canister_id(), 10);
+        let ledgers = std::slice::from_ref(&ledger);
+        STATE.with_borrow_mut(|s| {
+            let token = Icrc7Token {
+                token_id: 1,
+                token_owner: account(1),
+                token_meta: Default::default(),
+                edition: None,
+                token_user: None,
+            };
+            s.tokens.insert(1, token);
+            let offer = Offer {
+                id: 0,
+                bidder: account(2),
+                target: OfferTarget::Token(1),
+                ledger: ledger.canister,
+                amount: 100,
+                expires_at: None,
+                created_at: 0,
+            };
+            s.offers.insert(0, offer);
+        });
+        ledger.mint(token_account(1), 100);
+
+        assert!(block_on(prepare_export(ledgers)).is_err());
+        STATE.with_borrow_mut(|s| s.paused = true);
+        // the offer escrows funds
+        assert!(block_on(prepare_export(ledgers)).is_err());
+        STATE.with_borrow_mut(|s| s.offers.remove(&0));
+        // and so does the token account
+        assert!(block_on(prepare_export(ledgers)).is_err());
+        assert!(STATE.with_borrow(|s| s.check_exportable()).is_err());
+        ledger.balances.borrow_mut().remove(&token_account(1));
+        block_on(prepare_export(ledgers)).unwrap();
+        assert!(STATE.with_borrow(|s| s.check_exportable()).is_ok());
+
+        STATE.with_borrow_mut(|s| s.unpause(account(1), "done".into(), 0)).unwrap();
+        assert!(STATE.with_borrow(|s| s.check_exportable()).is_err());
+    }
+}
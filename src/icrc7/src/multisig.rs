@@ -0,0 +1,447 @@
+use std::collections::BTreeSet;
+use candid::Principal;
+use icrc7_types::{
+    errors::{MediaError, MultisigError},
+    multisig_types::{
+        ApproveProposalResult, MultisigConfig, Proposal, ProposalAction, ProposalStatus,
+        ProposeResult,
+    },
+};
+use crate::{
+    finalize, http,
+    state::{State, STATE},
+    update_method::grant_minting_authority_transfer_approval,
+    utils::default_account,
+};
+
+impl State {
+    pub fn is_signer(&self, principal: &Principal) -> bool {
+        self.multisig
+            .as_ref()
+            .is_some_and(|config| config.signers.contains(principal))
+    }
+
+    // Enables, reconfigures or, with `None`, disables the multisig mode. Open proposals are
+    // expired when it is disabled.
+    pub fn set_multisig(&mut self, config: Option<MultisigConfig>) -> Result<(), MultisigError> {
+        if let Some(ref config) = config {
+            validate_multisig_config(config)?;
+        } else {
+            for id in std::mem::take(&mut self.open_proposals) {
+                self.set_proposal_status(id, ProposalStatus::Expired);
+            }
+        }
+        self.multisig = config;
+        Ok(())
+    }
+
+    // Opens a proposal, counting it as approved by the proposer
+    pub fn propose(
+        &mut self,
+        caller: &Principal,
+        action: ProposalAction,
+        now: u64,
+    ) -> ProposeResult {
+        let ttl = match self.multisig {
+            None => return Err(MultisigError::NotEnabled),
+            Some(ref config) => config.proposal_ttl,
+        };
+        if !self.is_signer(caller) {
+            return Err(MultisigError::NotASigner);
+        }
+        if let ProposalAction::SetMultisig(Some(ref config)) = action {
+            validate_multisig_config(config)?;
+        }
+        self.expire_proposals(now);
+
+        let id = self.proposals.len() as u128;
+        self.proposals.insert(
+            id,
+            Proposal {
+                id,
+                proposer: *caller,
+                action,
+                approvals: vec![*caller],
+                created_at: now,
+                expires_at: now.saturating_add(ttl),
+                status: ProposalStatus::Open,
+            },
+        );
+        self.open_proposals.insert(id);
+        Ok(id)
+    }
+
+    pub fn approve_proposal(
+        &mut self,
+        caller: &Principal,
+        id: u128,
+        now: u64,
+    ) -> Result<(), MultisigError> {
+        if self.multisig.is_none() {
+            return Err(MultisigError::NotEnabled);
+        }
+        if !self.is_signer(caller) {
+            return Err(MultisigError::NotASigner);
+        }
+        self.expire_proposals(now);
+
+        let mut proposal = self.proposals.get(&id).ok_or(MultisigError::ProposalNotFound)?;
+        match proposal.status {
+            ProposalStatus::Open => (),
+            ProposalStatus::Expired => return Err(MultisigError::ProposalExpired),
+            _ => return Err(MultisigError::ProposalClosed),
+        }
+        if proposal.approvals.contains(caller) {
+            return Err(MultisigError::AlreadyApproved);
+        }
+        proposal.approvals.push(*caller);
+        self.proposals.insert(id, proposal);
+        Ok(())
+    }
+
+    // The action of an open proposal approved by enough of the current signers
+    pub fn approved_action(&self, id: u128) -> Option<ProposalAction> {
+        let config = self.multisig.as_ref()?;
+        let proposal = self.proposals.get(&id)?;
+        if proposal.status != ProposalStatus::Open {
+            return None;
+        }
+        let approvals = proposal
+            .approvals
+            .iter()
+            .filter(|principal| config.signers.contains(principal))
+            .count();
+        if approvals < config.threshold as usize {
+            return None;
+        }
+        Some(proposal.action)
+    }
+
+    pub fn close_proposal(&mut self, id: u128, status: ProposalStatus) {
+        self.open_proposals.remove(&id);
+        self.set_proposal_status(id, status);
+    }
+
+    pub fn expire_proposals(&mut self, now: u64) {
+        let expired: Vec<u128> = self
+            .open_proposals
+            .iter()
+            .filter(|id| {
+                self.proposals
+                    .get(id)
+                    .is_none_or(|proposal| proposal.expires_at <= now)
+            })
+            .copied()
+            .collect();
+        for id in expired {
+            self.close_proposal(id, ProposalStatus::Expired);
+        }
+    }
+
+    pub fn get_proposal(&self, id: u128) -> Option<Proposal> {
+        self.proposals.get(&id)
+    }
+
+    pub fn get_proposals(&self, prev: Option<u128>, take: Option<u128>) -> Vec<Proposal> {
+        let take = self.get_current_take(take);
+        let start = prev.map_or(0, |prev| prev + 1);
+        self.proposals
+            .range(start..)
+            .map(|(_, proposal)| proposal)
+            .take(take as usize)
+            .collect()
+    }
+
+    // Checks an upgrade requested through the launchpad: in multisig mode the wasm must have
+    // been authorized by a proposal, otherwise the requester must own the collection
+    pub fn consume_upgrade(
+        &mut self,
+        requester: &Principal,
+        wasm_hash: &[u8],
+    ) -> Result<(), String> {
+        if self.multisig.is_some() {
+            if self.authorized_upgrade.as_deref() != Some(wasm_hash) {
+                return Err("The upgrade was not authorized by a multisig proposal".into());
+            }
+            self.authorized_upgrade = None;
+            Ok(())
+        } else if self.owner == Some(*requester) {
+            Ok(())
+        } else {
+            Err("Only the owner can upgrade the collection".into())
+        }
+    }
+
+    fn set_proposal_status(&mut self, id: u128, status: ProposalStatus) {
+        if let Some(mut proposal) = self.proposals.get(&id) {
+            proposal.status = status;
+            self.proposals.insert(id, proposal);
+        }
+    }
+}
+
+pub fn validate_multisig_config(config: &MultisigConfig) -> Result<(), MultisigError> {
+    let signers: BTreeSet<&Principal> = config.signers.iter().collect();
+    let message = if signers.len() != config.signers.len() {
+        "Duplicated signers"
+    } else if signers.contains(&Principal::anonymous()) {
+        "The anonymous principal cannot be a signer"
+    } else if config.threshold == 0 || config.threshold as usize > signers.len() {
+        "The threshold must be between 1 and the number of signers"
+    } else if config.proposal_ttl == 0 {
+        "The proposal ttl must be greater than zero"
+    } else {
+        return Ok(());
+    };
+    Err(MultisigError::InvalidConfig {
+        message: message.into(),
+    })
+}
+
+// Executes the proposal if it reached the threshold, returning its current status
+pub async fn execute_if_approved(id: u128) -> ApproveProposalResult {
+    let Some(action) = STATE.with_borrow(|s| s.approved_action(id)) else {
+        return STATE
+            .with_borrow(|s| s.get_proposal(id))
+            .map(|proposal| proposal.status)
+            .ok_or(MultisigError::ProposalNotFound);
+    };
+    let at = ic_cdk::api::time();
+    let mut status = match execute(action.clone(), at) {
+        Ok(result) => ProposalStatus::Executed { at, result },
+        Err(message) => ProposalStatus::Failed { at, message },
+    };
+    STATE.with_borrow_mut(|s| s.close_proposal(id, status.clone()));
+
+    // the launchpad is called once the proposal is closed, so that it can't run twice meanwhile
+    let release = match action {
+        ProposalAction::Finalize(arg) if arg.remove_launchpad => {
+            Some("Finalized, but the launchpad was kept")
+        }
+        ProposalAction::RemoveLaunchpad => Some("The launchpad was kept"),
+        _ => None,
+    };
+    if let (Some(context), ProposalStatus::Executed { .. }) = (release, &status) {
+        if let Err(message) = finalize::remove_launchpad().await {
+            status = ProposalStatus::Failed {
+                at,
+                message: format!("{}: {}", context, message),
+            };
+            STATE.with_borrow_mut(|s| s.close_proposal(id, status.clone()));
+        }
+    }
+    Ok(status)
+}
+
+// Privileged actions are carried out on behalf of the owner, mints on behalf of the
+// minting authority
+fn execute(action: ProposalAction, at: u64) -> Result<Vec<String>, String> {
+    let owner = STATE
+        .with_borrow(|s| s.owner)
+        .ok_or_else(|| "The collection has no owner".to_string())?;
+    let by = default_account(&owner);
+    let block = |index: u128| vec![format!("block {}", index)];
+
+    match action {
+        ProposalAction::Mint(args) => {
+            let minting_authority = STATE
+                .with_borrow(|s| s.minting_authority)
+                .ok_or_else(|| "Minting Authority Not Set".to_string())?;
+            let mut results = vec![];
+            for arg in args {
+                let to = arg.to.owner;
+                match STATE.with_borrow_mut(|s| s.mint_as(minting_authority, arg)) {
+                    Ok((_, token_id)) => {
+                        grant_minting_authority_transfer_approval(&to, vec![token_id]);
+                        results.push(format!("token {}", token_id));
+                    }
+                    Err(err) => results.push(format!("{:?}", err)),
+                }
+            }
+            Ok(results)
+        }
+        ProposalAction::CreateSeries(arg) => STATE
+            .with_borrow_mut(|s| s.create_series(arg, at))
+            .map(|series_id| vec![format!("series {}", series_id)]),
+        ProposalAction::MintEditions(args) => {
+            let minting_authority = STATE
+                .with_borrow(|s| s.minting_authority)
+                .ok_or_else(|| "Minting Authority Not Set".to_string())?;
+            let mut results = vec![];
+            for arg in args {
+                let to = arg.to.owner;
+                match STATE.with_borrow_mut(|s| s.mint_edition_as(minting_authority, arg)) {
+                    Ok((_, token_id)) => {
+                        grant_minting_authority_transfer_approval(&to, vec![token_id]);
+                        results.push(format!("token {}", token_id));
+                    }
+                    Err(err) => results.push(format!("{:?}", err)),
+                }
+            }
+            Ok(results)
+        }
+        ProposalAction::UpdateTokenMetadata(arg) => STATE
+            .with_borrow_mut(|s| s.update_token_metadata(&owner, arg, at))
+            .map(block),
+        ProposalAction::UpdateCollectionConfig(arg) => STATE
+            .with_borrow_mut(|s| s.update_collection_config(by, *arg, at))
+            .map(block),
+        ProposalAction::SetMarketFee(fee) => {
+            STATE.with_borrow_mut(|s| s.set_market_fee(fee)).map(|_| vec![])
+        }
+        ProposalAction::SetRoyalties(royalties) => {
+            STATE.with_borrow_mut(|s| s.set_royalties(royalties)).map(|_| {
+                http::certify_collection();
+                vec![]
+            })
+        }
+        ProposalAction::SetTokenRoyalties {
+            token_id,
+            royalties,
+        } => STATE
+            .with_borrow_mut(|s| s.set_token_royalties(token_id, royalties))
+            .map(|_| vec![]),
+        ProposalAction::SetRevenueSplit(split) => {
+            STATE.with_borrow_mut(|s| s.set_revenue_split(split)).map(|_| vec![])
+        }
+        ProposalAction::GrantRole(arg) => STATE
+            .with_borrow_mut(|s| s.grant_role(&owner, arg, at))
+            .map(block)
+            .map_err(|err| format!("{:?}", err)),
+        ProposalAction::RevokeRole(arg) => STATE
+            .with_borrow_mut(|s| s.revoke_role(&owner, arg, at))
+            .map(block)
+            .map_err(|err| format!("{:?}", err)),
+        ProposalAction::TransferOwnership(new_owner) => STATE
+            .with_borrow_mut(|s| s.transfer_ownership(&owner, new_owner, at))
+            .map(block)
+            .map_err(|err| format!("{:?}", err)),
+        ProposalAction::Pause(arg) => {
+            STATE.with_borrow_mut(|s| s.pause(by, arg.reason, at)).map(block)
+        }
+        ProposalAction::Unpause(arg) => {
+            STATE.with_borrow_mut(|s| s.unpause(by, arg.reason, at)).map(block)
+        }
+        ProposalAction::Freeze(arg) => STATE.with_borrow_mut(|s| s.freeze(by, arg, at)).map(block),
+        ProposalAction::Unfreeze(arg) => {
+            STATE.with_borrow_mut(|s| s.unfreeze(by, arg, at)).map(block)
+        }
+        ProposalAction::LockTokens(token_ids) => {
+            let locked = STATE.with_borrow_mut(|s| s.lock_tokens(token_ids));
+            Ok(locked.iter().map(bool::to_string).collect())
+        }
+        ProposalAction::UnlockTokens(token_ids) => {
+            let unlocked = STATE.with_borrow_mut(|s| s.unlock_tokens(token_ids));
+            Ok(unlocked.iter().map(bool::to_string).collect())
+        }
+        ProposalAction::DeleteMedia { sha256 } => {
+            let sha256: [u8; 32] = sha256
+                .try_into()
+                .map_err(|_| format!("{:?}", MediaError::MediaNotFound))?;
+            STATE
+                .with_borrow_mut(|s| s.delete_media(&sha256))
+                .map(|_| vec![])
+                .map_err(|err| format!("{:?}", err))
+        }
+        ProposalAction::SetMediaQuota(quota) => {
+            STATE.with_borrow_mut(|s| s.set_media_quota(quota)).map(|_| vec![])
+        }
+        ProposalAction::SetMetadataSchema(schema) => {
+            STATE.with_borrow_mut(|s| s.set_metadata_schema(schema)).map(|_| vec![])
+        }
+        ProposalAction::SetTraitKeys(keys) => {
+            STATE.with_borrow_mut(|s| s.set_trait_keys(keys)).map(|_| vec![])
+        }
+        ProposalAction::AuthorizeUpgrade { wasm_hash } => {
+            STATE.with_borrow_mut(|s| s.authorized_upgrade = Some(wasm_hash));
+            Ok(vec![])
+        }
+        // the launchpad is released by `execute_if_approved`
+        ProposalAction::Finalize(arg) => {
+            let block_index = STATE.with_borrow_mut(|s| s.finalize(by, &arg, at))?;
+            Ok(block(block_index))
+        }
+        ProposalAction::RemoveLaunchpad => {
+            STATE.with_borrow(|s| s.check_launchpad_removable())?;
+            Ok(vec![])
+        }
+        ProposalAction::SetMultisig(config) => STATE
+            .with_borrow_mut(|s| s.set_multisig(config))
+            .map(|_| vec![])
+            .map_err(|err| format!("{:?}", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{market::tests::account, state::Icrc7Token};
+
+    #[test]
+    fn proposals_need_the_threshold_before_running() {
+        let signers: Vec<Principal> = (1..=3).map(|n| Principal::from_slice(&[n])).collect();
+        let outsider = Principal::from_slice(&[9]);
+        let mut state = State::default();
+
+        assert!(state
+            .set_multisig(Some(MultisigConfig {
+                signers: signers.clone(),
+                threshold: 4,
+                proposal_ttl: 100,
+            }))
+            .is_err());
+        state
+            .set_multisig(Some(MultisigConfig {
+                signers: signers.clone(),
+                threshold: 2,
+                proposal_ttl: 100,
+            }))
+            .unwrap();
+
+        let action = ProposalAction::SetMarketFee(None);
+        assert!(matches!(
+            state.propose(&outsider, action.clone(), 0),
+            Err(MultisigError::NotASigner)
+        ));
+        let id = state.propose(&signers[0], action.clone(), 0).unwrap();
+        assert!(state.approved_action(id).is_none());
+        assert!(matches!(
+            state.approve_proposal(&signers[0], id, 10),
+            Err(MultisigError::AlreadyApproved)
+        ));
+        state.approve_proposal(&signers[1], id, 10).unwrap();
+        assert!(state.approved_action(id).is_some());
+        state.close_proposal(id, ProposalStatus::Executed { at: 10, result: vec![] });
+        assert!(state.approved_action(id).is_none());
+
+        let id = state.propose(&signers[2], action, 20).unwrap();
+        assert!(matches!(
+            state.approve_proposal(&signers[1], id, 120),
+            Err(MultisigError::ProposalExpired)
+        ));
+        assert_eq!(state.get_proposal(id).unwrap().status, ProposalStatus::Expired);
+        assert_eq!(state.get_proposals(None, None).len(), 2);
+    }
+
+    #[test]
+    fn locks_and_index_settings_run_through_proposals() {
+        STATE.with_borrow_mut(|s| {
+            s.owner = Some(account(1).owner);
+            let token = Icrc7Token {
+                token_id: 1,
+                token_owner: account(2),
+                token_meta: Default::default(),
+                edition: None,
+                token_user: None,
+            };
+            s.tokens.insert(1, token);
+        });
+
+        let res = execute(ProposalAction::LockTokens(vec![1, 2]), 0).unwrap();
+        assert_eq!(res, vec!["true", "false"]);
+        assert!(STATE.with_borrow(|s| s.locked_tokens.contains(&1)));
+        execute(ProposalAction::SetTraitKeys(vec!["color".into()]), 0).unwrap();
+        let action = ProposalAction::DeleteMedia { sha256: vec![0; 32] };
+        assert!(execute(action, 0).is_err());
+    }
+}
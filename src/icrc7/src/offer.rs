@@ -0,0 +1,473 @@
+use std::time::Duration;
+use candid::Principal;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::{
+    errors::MarketError,
+    market_types::{
+        AcceptOfferArg, AcceptOfferResult, CancelOfferArg, MakeOfferArg, MakeOfferResult, Offer,
+        OfferTarget, Payout,
+    },
+};
+use crate::{
+    ledger::{IcrcLedger, Ledger},
+    market::{collect_payment, pay_out, proceeds_payouts},
+    state::{State, STATE},
+    utils::{account_transformer, offer_account, offer_subaccount, time},
+};
+
+impl State {
+    // Validates an offer, reserving its id for the escrow subaccount
+    pub fn prepare_offer(
+        &mut self,
+        bidder: &Account,
+        arg: &MakeOfferArg,
+        now: u64,
+    ) -> Result<u128, MarketError> {
+        if arg.amount == 0 {
+            return Err(MarketError::InvalidPrice);
+        }
+        if self.is_any_account_frozen(&[bidder]) {
+            return Err(MarketError::Frozen);
+        }
+        if let Some(expires_at) = arg.expires_at {
+            if expires_at <= now {
+                return Err(MarketError::OfferExpired);
+            }
+        }
+        if let OfferTarget::Token(token_id) = arg.target {
+            match self.tokens.get(&token_id) {
+                None => return Err(MarketError::NonExistingTokenId),
+                Some(token) => {
+                    if token.token_owner == *bidder {
+                        return Err(MarketError::Unauthorized);
+                    }
+                }
+            }
+        }
+        let offer_id = self.next_offer_id;
+        self.next_offer_id += 1;
+        Ok(offer_id)
+    }
+
+    pub fn insert_offer(&mut self, offer: Offer) {
+        self.offers.insert(offer.id, offer);
+    }
+
+    // Removes an offer of the caller, returning it so its escrow can be refunded
+    pub fn cancel_offer(&mut self, bidder: &Account, offer_id: u128) -> Result<Offer, MarketError> {
+        let offer = self
+            .offers
+            .get(&offer_id)
+            .ok_or(MarketError::OfferNotFound)?;
+        if offer.bidder != *bidder {
+            return Err(MarketError::Unauthorized);
+        }
+        self.offers.remove(&offer_id);
+        Ok(offer)
+    }
+
+    pub fn take_expired_offer(&mut self, offer_id: u128, now: u64) -> Option<Offer> {
+        match self.offers.get(&offer_id) {
+            Some(offer) if offer.is_expired(now) => self.offers.remove(&offer_id),
+            _ => None,
+        }
+    }
+
+    // Hands the token to the bidder, returning the payouts due from the offer escrow
+    pub fn accept_offer(
+        &mut self,
+        caller: &Principal,
+        arg: &AcceptOfferArg,
+        now: u64,
+    ) -> Result<(u128, Vec<Payout>), MarketError> {
+        let owner = account_transformer(Account {
+            owner: *caller,
+            subaccount: arg.from_subaccount,
+        });
+        let offer = self
+            .offers
+            .get(&arg.offer_id)
+            .ok_or(MarketError::OfferNotFound)?;
+        if offer.is_expired(now) {
+            return Err(MarketError::OfferExpired);
+        }
+        if offer.ledger != arg.ledger || offer.amount != arg.amount {
+            return Err(MarketError::OfferMismatch {
+                ledger: offer.ledger,
+                amount: offer.amount,
+            });
+        }
+        let token = self
+            .tokens
+            .get(&arg.token_id)
+            .ok_or(MarketError::NonExistingTokenId)?;
+        if token.token_owner != owner {
+            return Err(MarketError::Unauthorized);
+        }
+        // whatever the target, selling to oneself would only move royalties and fees around
+        if offer.bidder == owner {
+            return Err(MarketError::Unauthorized);
+        }
+        if self.settling_tokens.contains(&arg.token_id) {
+            return Err(MarketError::SettlementInProgress);
+        }
+        if !self.offer_matches(&offer.target, arg.token_id) {
+            return Err(MarketError::OfferTargetMismatch);
+        }
+        if self.is_frozen(&arg.token_id, &[&owner, &offer.bidder]) {
+            return Err(MarketError::Frozen);
+        }
+        if !self.is_transferable_by(&arg.token_id, &owner) {
+            return Err(MarketError::NonTransferable);
+        }
+
+        self.offers.remove(&arg.offer_id);
+        let txn_id = self.move_token(arg.token_id, owner, offer.bidder, now);
+        let shares = self.sale_shares(arg.token_id);
+        let payouts = proceeds_payouts(
+            offer.ledger,
+            offer_subaccount(offer.id),
+            offer.amount,
+            &shares,
+            self.proceeds_recipient(owner),
+        );
+        Ok((txn_id, payouts))
+    }
+
+    fn offer_matches(&self, target: &OfferTarget, token_id: u128) -> bool {
+        match target {
+            OfferTarget::Token(id) => *id == token_id,
+            OfferTarget::Collection => true,
+            OfferTarget::Trait { key, value } => self
+                .tokens
+                .get(&token_id)
+                .and_then(|token| self.token_metadata_of(&token).get(key).cloned())
+                .is_some_and(|v| v == *value),
+        }
+    }
+
+    pub fn get_offer(&self, offer_id: u128) -> Option<Offer> {
+        self.offers.get(&offer_id)
+    }
+
+    // Returns the offers a token can accept, including the collection-wide ones
+    pub fn offers_by_token(
+        &self,
+        token_id: u128,
+        prev: Option<u128>,
+        take: Option<u128>,
+    ) -> Vec<Offer> {
+        self.filter_offers(prev, take, |offer| self.offer_matches(&offer.target, token_id))
+    }
+
+    pub fn offers_by_bidder(
+        &self,
+        bidder: Account,
+        prev: Option<u128>,
+        take: Option<u128>,
+    ) -> Vec<Offer> {
+        self.filter_offers(prev, take, |offer| offer.bidder == bidder)
+    }
+
+    pub fn collection_offers(&self, prev: Option<u128>, take: Option<u128>) -> Vec<Offer> {
+        self.filter_offers(prev, take, |offer| {
+            !matches!(offer.target, OfferTarget::Token(_))
+        })
+    }
+
+    fn filter_offers(
+        &self,
+        prev: Option<u128>,
+        take: Option<u128>,
+        filter: impl Fn(&Offer) -> bool,
+    ) -> Vec<Offer> {
+        let take = self.get_current_take(take);
+        let now = time();
+        let start = prev.map_or(0, |prev| prev + 1);
+        self.offers
+            .range(start..)
+            .map(|(_, offer)| offer)
+            .filter(|offer| !offer.is_expired(now) && filter(offer))
+            .take(take as usize)
+            .collect()
+    }
+}
+
+pub async fn make_offer(caller: Principal, arg: MakeOfferArg) -> MakeOfferResult {
+    let expires_at = arg.expires_at;
+    let offer_id = make_offer_with(IcrcLedger, caller, arg).await?;
+    if let Some(expires_at) = expires_at {
+        schedule_offer_expiration(offer_id, expires_at);
+    }
+    Ok(offer_id)
+}
+
+// The offer flows pay through whichever ledger `ledger_of` gives for the offer's one
+pub(crate) async fn make_offer_with<L: Ledger>(
+    ledger_of: impl Fn(Principal) -> L,
+    caller: Principal,
+    arg: MakeOfferArg,
+) -> MakeOfferResult {
+    let bidder = account_transformer(Account {
+        owner: caller,
+        subaccount: arg.from_subaccount,
+    });
+    let offer_id = STATE.with_borrow_mut(|s| s.prepare_offer(&bidder, &arg, time()))?;
+    let ledger = ledger_of(arg.ledger);
+
+    let fee = ledger
+        .fee()
+        .await
+        .map_err(|message| MarketError::PaymentFailed { message })?;
+    collect_payment(&ledger, bidder, offer_account(offer_id), arg.amount, fee)
+        .await
+        .map_err(|message| MarketError::PaymentFailed { message })?;
+
+    STATE.with_borrow_mut(|s| {
+        s.insert_offer(Offer {
+            id: offer_id,
+            bidder,
+            target: arg.target,
+            ledger: arg.ledger,
+            amount: arg.amount,
+            expires_at: arg.expires_at,
+            created_at: time(),
+        })
+    });
+    Ok(offer_id)
+}
+
+pub async fn cancel_offer(caller: Principal, arg: CancelOfferArg) -> Result<(), MarketError> {
+    cancel_offer_with(IcrcLedger, caller, arg).await
+}
+
+pub(crate) async fn cancel_offer_with<L: Ledger>(
+    ledger_of: impl Fn(Principal) -> L,
+    caller: Principal,
+    arg: CancelOfferArg,
+) -> Result<(), MarketError> {
+    let bidder = account_transformer(Account {
+        owner: caller,
+        subaccount: arg.from_subaccount,
+    });
+    let offer = STATE.with_borrow_mut(|s| s.cancel_offer(&bidder, arg.offer_id))?;
+    refund_offer(&ledger_of(offer.ledger), offer).await;
+    Ok(())
+}
+
+pub async fn accept_offer(caller: Principal, arg: AcceptOfferArg) -> AcceptOfferResult {
+    accept_offer_with(IcrcLedger, caller, arg).await
+}
+
+pub(crate) async fn accept_offer_with<L: Ledger>(
+    ledger_of: impl Fn(Principal) -> L,
+    caller: Principal,
+    arg: AcceptOfferArg,
+) -> AcceptOfferResult {
+    let ledger = STATE
+        .with_borrow(|s| s.offers.get(&arg.offer_id).map(|offer| offer.ledger))
+        .ok_or(MarketError::OfferNotFound)?;
+    let (txn_id, payouts) = STATE.with_borrow_mut(|s| s.accept_offer(&caller, &arg, time()))?;
+
+    let ledger = ledger_of(ledger);
+    let failed = match ledger.fee().await {
+        Ok(fee) => pay_out(&ledger, payouts, fee).await,
+        Err(_) => payouts,
+    };
+    STATE.with_borrow_mut(|s| s.record_failed_payouts(failed));
+    Ok(txn_id)
+}
+
+async fn refund_offer<L: Ledger>(ledger: &L, offer: Offer) {
+    let refund = vec![Payout {
+        ledger: offer.ledger,
+        from_subaccount: Some(offer_subaccount(offer.id)),
+        to: offer.bidder,
+        amount: offer.amount,
+    }];
+    let failed = match ledger.fee().await {
+        Ok(fee) => pay_out(ledger, refund, fee).await,
+        Err(_) => refund,
+    };
+    STATE.with_borrow_mut(|s| s.record_failed_payouts(failed));
+}
+
+pub fn schedule_offer_expiration(offer_id: u128, expires_at: u64) {
+    let delay = Duration::from_nanos(expires_at.saturating_sub(time()));
+    ic_cdk_timers::set_timer(delay, move || {
+        ic_cdk::spawn(async move {
+            let offer = STATE.with_borrow_mut(|s| s.take_expired_offer(offer_id, time()));
+            if let Some(offer) = offer {
+                refund_offer(&IcrcLedger(offer.ledger), offer).await;
+            }
+        });
+    });
+}
+
+// Timers do not survive upgrades, so they are re-armed for every expiring offer
+pub fn schedule_offer_timers() {
+    let offers: Vec<(u128, u64)> = STATE.with_borrow(|s| {
+        s.offers
+            .iter()
+            .filter_map(|(id, offer)| offer.expires_at.map(|expires_at| (id, expires_at)))
+            .collect()
+    });
+    for (offer_id, expires_at) in offers {
+        schedule_offer_expiration(offer_id, expires_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use icrc_ledger_types::icrc::generic_value::Value;
+    use crate::{
+        market::tests::{account, block_on, LocalLedger},
+        state::Icrc7Token,
+        utils::{canister_id, default_account, set_time},
+    };
+
+    fn user(n: u8) -> Account {
+        default_account(&account(n).owner)
+    }
+
+    fn red() -> OfferTarget {
+        OfferTarget::Trait {
+            key: "color".into(),
+            value: Value::text("red"),
+        }
+    }
+
+    // Token 1 is red and owned by user 1, token 2 blue and owned by user 2, users 1 to 3
+    // holding 10_000 each
+    fn tokens(ledger: &LocalLedger) {
+        for n in 1..=3 {
+            ledger.mint(user(n), 10_000);
+        }
+        STATE.with_borrow_mut(|s| {
+            for (token_id, color) in [(1, "red"), (2, "blue")] {
+                let token = Icrc7Token {
+                    token_id,
+                    token_owner: user(token_id as u8),
+                    token_meta: [("color".to_string(), Value::text(color))].into(),
+                    edition: None,
+                    token_user: None,
+                };
+                s.tokens.insert(token_id, token);
+            }
+        });
+    }
+
+    fn make(
+        ledger: &LocalLedger,
+        n: u8,
+        target: OfferTarget,
+        expires_at: Option<u64>,
+    ) -> MakeOfferResult {
+        let arg = MakeOfferArg {
+            from_subaccount: None,
+            target,
+            ledger: ledger.canister,
+            amount: 1_000,
+            expires_at,
+        };
+        block_on(make_offer_with(|_| ledger, user(n).owner, arg))
+    }
+
+    fn accept(ledger: &LocalLedger, n: u8, offer_id: u128, token_id: u128) -> AcceptOfferResult {
+        let arg = AcceptOfferArg {
+            from_subaccount: None,
+            offer_id,
+            token_id,
+            ledger: ledger.canister,
+            amount: 1_000,
+        };
+        block_on(accept_offer_with(|_| ledger, user(n).owner, arg))
+    }
+
+    fn owner_of(token_id: u128) -> Account {
+        STATE.with_borrow(|s| s.tokens.get(&token_id).unwrap().token_owner)
+    }
+
+    #[test]
+    fn offers_are_accepted_by_owners_of_a_matching_token() {
+        let ledger = LocalLedger::new(canister_id(), 10);
+        tokens(&ledger);
+        let res = make(&ledger, 2, OfferTarget::Token(2), None);
+        assert!(matches!(res, Err(MarketError::Unauthorized)));
+
+        let collection = make(&ledger, 2, OfferTarget::Collection, None).unwrap();
+        assert_eq!(ledger.balance(&offer_account(collection)), 1_000);
+        // bidders cannot sell to themselves through a collection or trait offer
+        let res = accept(&ledger, 2, collection, 2);
+        assert!(matches!(res, Err(MarketError::Unauthorized)));
+        assert!(matches!(accept(&ledger, 3, collection, 1), Err(MarketError::Unauthorized)));
+        assert!(accept(&ledger, 1, collection, 1).is_ok());
+        assert_eq!(owner_of(1), user(2));
+        assert_eq!(ledger.balance(&user(1)), 10_990);
+        assert!(matches!(accept(&ledger, 1, collection, 1), Err(MarketError::OfferNotFound)));
+
+        let trait_offer = make(&ledger, 3, red(), None).unwrap();
+        let res = accept(&ledger, 2, trait_offer, 2);
+        assert!(matches!(res, Err(MarketError::OfferTargetMismatch)));
+        assert!(accept(&ledger, 2, trait_offer, 1).is_ok());
+        assert_eq!(owner_of(1), user(3));
+        assert_eq!(ledger.balance(&user(2)), 9_980);
+        let res = accept(&ledger, 3, make(&ledger, 3, red(), None).unwrap(), 1);
+        assert!(matches!(res, Err(MarketError::Unauthorized)));
+    }
+
+    #[test]
+    fn offers_are_only_accepted_on_the_terms_the_owner_saw() {
+        let ledger = LocalLedger::new(canister_id(), 10);
+        tokens(&ledger);
+        let offer_id = make(&ledger, 2, OfferTarget::Token(1), None).unwrap();
+        let accept_on = |ledger_id: Principal, amount: u128| {
+            let arg = AcceptOfferArg {
+                from_subaccount: None,
+                offer_id,
+                token_id: 1,
+                ledger: ledger_id,
+                amount,
+            };
+            block_on(accept_offer_with(|_| &ledger, user(1).owner, arg))
+        };
+        // the error tells the actual terms
+        let mismatch = |res: AcceptOfferResult| {
+            matches!(res, Err(MarketError::OfferMismatch { ledger: id, amount: 1_000 })
+                if id == ledger.canister)
+        };
+        assert!(mismatch(accept_on(Principal::from_slice(&[7]), 1_000)));
+        assert!(mismatch(accept_on(ledger.canister, 2_000)));
+        assert_eq!(owner_of(1), user(1));
+        assert!(accept_on(ledger.canister, 1_000).is_ok());
+        assert_eq!(owner_of(1), user(2));
+    }
+
+    #[test]
+    fn offers_are_refunded_when_cancelled_or_expired() {
+        let ledger = LocalLedger::new(canister_id(), 10);
+        tokens(&ledger);
+        let offer_id = make(&ledger, 3, OfferTarget::Token(1), None).unwrap();
+        let cancel = |n: u8| {
+            let arg = CancelOfferArg {
+                from_subaccount: None,
+                offer_id,
+            };
+            block_on(cancel_offer_with(|_| &ledger, user(n).owner, arg))
+        };
+        assert!(matches!(cancel(1), Err(MarketError::Unauthorized)));
+        assert!(cancel(3).is_ok());
+        assert_eq!(ledger.balance(&user(3)), 9_980);
+        assert_eq!(ledger.balance(&offer_account(offer_id)), 0);
+        assert!(matches!(cancel(3), Err(MarketError::OfferNotFound)));
+
+        let offer_id = make(&ledger, 3, OfferTarget::Token(1), Some(50)).unwrap();
+        set_time(50);
+        assert!(matches!(accept(&ledger, 1, offer_id, 1), Err(MarketError::OfferExpired)));
+        assert_eq!(owner_of(1), user(1));
+        let expired = STATE.with_borrow_mut(|s| s.take_expired_offer(offer_id, time()));
+        assert_eq!(expired.map(|offer| offer.id), Some(offer_id));
+        assert!(matches!(make(&ledger, 3, red(), Some(50)), Err(MarketError::OfferExpired)));
+    }
+}
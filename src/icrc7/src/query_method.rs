@@ -1,8 +1,32 @@
 use candid::Principal;
 use ic_cdk_macros::query;
-use icrc_ledger_types::icrc1::account::Account;
-use icrc7_types::icrc7_types::{Transaction, Icrc7TokenMetadata, Standard};
-use crate::state::STATE;
+use serde_bytes::ByteBuf;
+use icrc_ledger_types::{icrc::generic_value::Value, icrc1::account::Account};
+use icrc7_types::{
+    compliance_types::{FreezeRecord, FreezeTarget},
+    rbac_types::Role,
+    multisig_types::{MultisigConfig, Proposal},
+    series_types::Series,
+    rental_types::TokenUser,
+    token_account_types::TokenBalanceArg,
+    http_types::{HttpRequest, HttpResponse},
+    media_types::{MediaAsset, MediaUsage},
+    schema_types::MetadataSchema,
+    trait_types::{TokensByTraitArg, TraitCount},
+    holder_types::{BalanceBucket, Holder, HolderStats, HoldersArg},
+    history_types::{OwnersAtResult, OwnershipSnapshotArg, OwnershipSnapshotResult},
+    icrc3_types::QueryBlock,
+    migration_types::{
+        ArchivesExport, CollectionApprovalsExport, ConfigExport, TokenApprovalsExport, TokenExport,
+    },
+    icrc7_types::{Transaction, Icrc7TokenMetadata, RoyaltyRecipient, Standard},
+    revenue_types::{RevenueEntry, Stakeholder},
+};
+use crate::{
+    http,
+    state::{State, STATE},
+    token_account, utils,
+};
 
 #[query]
 pub fn icrc7_symbol() -> String {
@@ -91,6 +115,23 @@ pub fn icrc7_supported_standards() -> Vec<Standard> {
     ]
 }
 
+#[query]
+pub fn icrc7_collection_metadata() -> Vec<(String, Value)> {
+    STATE.with(|s| s.borrow().icrc7_collection_metadata())
+}
+
+// Returns the royalties due on a sale of each token, `None` for non existing tokens.
+#[query]
+pub fn icrc7_royalties(token_ids: Vec<u128>) -> Vec<Option<Vec<RoyaltyRecipient>>> {
+    STATE.with(|s| s.borrow().icrc7_royalties(&token_ids))
+}
+
+// Returns whether each token is locked against transfers, `None` for non existing tokens.
+#[query]
+pub fn icrc7_locked(token_ids: Vec<u128>) -> Vec<Option<bool>> {
+    STATE.with(|s| s.borrow().icrc7_locked(&token_ids))
+}
+
 #[query]
 pub fn icrc7_tokens(prev: Option<u128>, take: Option<u128>) -> Vec<u128> {
     STATE.with(|s| s.borrow().icrc7_tokens(prev, take))
@@ -125,3 +166,278 @@ pub fn txn_logs(page_number: u32, page_size: u32) -> Vec<Transaction> {
 pub fn archive_log_canister() -> Option<Principal> {
     STATE.with(|s| s.borrow().get_archive_log_canister())
 }
+
+#[query]
+pub fn revenue_split() -> Vec<Stakeholder> {
+    STATE.with(|s| s.borrow().revenue_split.clone())
+}
+
+// Returns the claimable balances of an account, per ledger.
+#[query]
+pub fn revenue_balances(account: Account) -> Vec<(Principal, u128)> {
+    STATE.with(|s| s.borrow().revenue_balances_of(&account))
+}
+
+// Returns the revenue credits and withdrawals, oldest first.
+#[query]
+pub fn revenue_history(prev: Option<u128>, take: Option<u128>) -> Vec<RevenueEntry> {
+    STATE.with(|s| s.borrow().get_revenue_history(prev, take))
+}
+
+#[query]
+pub fn is_paused() -> bool {
+    STATE.with(|s| s.borrow().paused)
+}
+
+// Returns the freeze record of each token or account, `None` when not frozen.
+#[query]
+pub fn get_freezes(targets: Vec<FreezeTarget>) -> Vec<Option<FreezeRecord>> {
+    STATE.with(|s| s.borrow().get_freezes(targets))
+}
+
+#[query]
+pub fn frozen_tokens(prev: Option<u128>, take: Option<u128>) -> Vec<(u128, FreezeRecord)> {
+    STATE.with(|s| s.borrow().get_frozen_tokens(prev, take))
+}
+
+#[query]
+pub fn frozen_accounts(prev: Option<Account>, take: Option<u128>) -> Vec<(Account, FreezeRecord)> {
+    STATE.with(|s| s.borrow().get_frozen_accounts(prev, take))
+}
+
+#[query]
+pub fn get_owner() -> Option<Principal> {
+    STATE.with(|s| s.borrow().owner)
+}
+
+#[query]
+pub fn pending_owner() -> Option<Principal> {
+    STATE.with(|s| s.borrow().pending_owner)
+}
+
+// Returns the roles held by a principal, the implicit ones excluded.
+#[query]
+pub fn get_roles(principal: Principal) -> Vec<Role> {
+    STATE.with(|s| s.borrow().roles_of(&principal))
+}
+
+#[query]
+pub fn role_members(role: Role) -> Vec<Principal> {
+    STATE.with(|s| s.borrow().role_members(role))
+}
+
+#[query]
+pub fn multisig_config() -> Option<MultisigConfig> {
+    STATE.with(|s| s.borrow().multisig.clone())
+}
+
+#[query]
+pub fn get_proposal(id: u128) -> Option<Proposal> {
+    STATE.with(|s| s.borrow().get_proposal(id))
+}
+
+// Proposal history, oldest first. Expired proposals are only marked on the next update call.
+#[query]
+pub fn get_proposals(prev: Option<u128>, take: Option<u128>) -> Vec<Proposal> {
+    STATE.with(|s| s.borrow().get_proposals(prev, take))
+}
+
+#[query]
+pub fn get_series(series_id: u128) -> Option<Series> {
+    STATE.with(|s| s.borrow().get_series(series_id))
+}
+
+#[query]
+pub fn list_series(prev: Option<u128>, take: Option<u128>) -> Vec<Series> {
+    STATE.with(|s| s.borrow().list_series(prev, take))
+}
+
+// The token ids of a series ordered by edition number, `prev` being an edition number.
+#[query]
+pub fn series_tokens(series_id: u128, prev: Option<u32>, take: Option<u128>) -> Vec<u128> {
+    STATE.with(|s| s.borrow().series_tokens_of(series_id, prev, take))
+}
+
+// The current users of the tokens, none for the tokens not rented out or whose rental expired.
+#[query]
+pub fn icrc7_user_of(token_ids: Vec<u128>) -> Vec<Option<TokenUser>> {
+    let now = ic_cdk::api::time();
+    STATE.with(|s| {
+        let s = s.borrow();
+        token_ids.iter().map(|token_id| s.user_of(*token_id, now)).collect()
+    })
+}
+
+// The accounts held by the tokens, where assets are deposited to be bound to a token.
+#[query]
+pub fn token_accounts(token_ids: Vec<u128>) -> Vec<Option<Account>> {
+    STATE.with(|s| {
+        let s = s.borrow();
+        token_ids
+            .iter()
+            .map(|id| s.tokens.contains_key(id).then(|| utils::token_account(*id)))
+            .collect()
+    })
+}
+
+// What the tokens hold, asking each ledger for the balance of the token account.
+#[query(composite = true)]
+pub async fn token_balances(args: Vec<TokenBalanceArg>) -> Vec<Result<u128, String>> {
+    let mut balances = Vec::with_capacity(args.len());
+    for arg in args {
+        balances.push(token_account::token_balance_of(arg).await);
+    }
+    balances
+}
+
+// The tokens the given tokens are directly equipped to.
+#[query]
+pub fn parent_of(token_ids: Vec<u128>) -> Vec<Option<u128>> {
+    STATE.with(|s| {
+        let s = s.borrow();
+        token_ids.iter().map(|token_id| s.parents.get(token_id).copied()).collect()
+    })
+}
+
+// The tokens directly equipped to a token.
+#[query]
+pub fn children_of(token_id: u128) -> Vec<u128> {
+    STATE.with(|s| s.borrow().children_of(token_id))
+}
+
+// Serves the token and collection metadata as certified JSON to browsers and off-chain tools.
+#[query]
+pub fn http_request(req: HttpRequest) -> HttpResponse {
+    http::http_request(req)
+}
+
+#[query]
+pub fn get_media(sha256: ByteBuf) -> Option<MediaAsset> {
+    let sha256: [u8; 32] = sha256.into_vec().try_into().ok()?;
+    STATE.with(|s| s.borrow().media.get(&sha256))
+}
+
+// A chunk of stored media, for the clients fetching media larger than a response.
+#[query]
+pub fn get_media_chunk(sha256: ByteBuf, index: u32) -> Option<ByteBuf> {
+    let sha256: [u8; 32] = sha256.into_vec().try_into().ok()?;
+    STATE.with(|s| s.borrow().media_chunks.get(&(sha256, index)).map(ByteBuf::from))
+}
+
+#[query]
+pub fn media_usage() -> MediaUsage {
+    STATE.with(|s| s.borrow().media_usage())
+}
+
+#[query]
+pub fn get_metadata_schema() -> Option<MetadataSchema> {
+    STATE.with(|s| s.borrow().metadata_schema.clone())
+}
+
+#[query]
+pub fn tokens_by_trait(arg: TokensByTraitArg) -> Vec<u128> {
+    STATE.with(|s| s.borrow().tokens_by_trait(arg))
+}
+
+#[query]
+pub fn trait_counts(key: Option<String>) -> Vec<TraitCount> {
+    STATE.with(|s| s.borrow().trait_counts(key))
+}
+
+#[query]
+pub fn rarity_scores(token_ids: Vec<u128>) -> Vec<Option<f64>> {
+    STATE.with(|s| s.borrow().rarity_scores(&token_ids))
+}
+
+#[query]
+pub fn holder_stats() -> HolderStats {
+    STATE.with(|s| s.borrow().holder_stats())
+}
+
+// Holders by descending balance
+#[query]
+pub fn holders(arg: HoldersArg) -> Vec<Holder> {
+    STATE.with(|s| s.borrow().holders(arg))
+}
+
+#[query]
+pub fn holder_balances(accounts: Vec<Account>) -> Vec<u128> {
+    STATE.with(|s| s.borrow().holder_balances(&accounts))
+}
+
+#[query]
+pub fn holder_distribution() -> Vec<BalanceBucket> {
+    STATE.with(|s| s.borrow().holder_distribution())
+}
+
+// The owners of tokens once a block was applied
+#[query]
+pub fn owners_at(token_ids: Vec<u128>, block: u128) -> OwnersAtResult {
+    STATE.with(|s| s.borrow().owners_at(&token_ids, block, ic_cdk::id()))
+}
+
+#[query]
+pub fn ownership_snapshot(arg: OwnershipSnapshotArg) -> OwnershipSnapshotResult {
+    STATE.with(|s| s.borrow().ownership_snapshot(arg, ic_cdk::id()))
+}
+
+// The tokens after `prev`, as `import_tokens` takes them
+#[query]
+pub fn export_tokens(
+    prev: Option<u128>,
+    take: Option<u128>,
+) -> Result<Vec<TokenExport>, String> {
+    export(|s| s.export_tokens(prev, take))
+}
+
+#[query]
+pub fn export_token_approvals(
+    prev: Option<u128>,
+    take: Option<u128>,
+) -> Result<Vec<TokenApprovalsExport>, String> {
+    export(|s| s.export_token_approvals(prev, take))
+}
+
+#[query]
+pub fn export_collection_approvals(
+    prev: Option<Account>,
+    take: Option<u128>,
+) -> Result<Vec<CollectionApprovalsExport>, String> {
+    export(|s| s.export_collection_approvals(prev, take))
+}
+
+#[query]
+pub fn export_config() -> Result<ConfigExport, String> {
+    export(State::export_config)
+}
+
+#[query]
+pub fn export_archives() -> Result<ArchivesExport, String> {
+    export(State::export_archives)
+}
+
+// The blocks not archived yet, after `prev`
+#[query]
+pub fn export_log_blocks(
+    prev: Option<u128>,
+    take: Option<u128>,
+) -> Result<Vec<QueryBlock>, String> {
+    export(|s| s.export_log_blocks(prev, take))
+}
+
+// The stored media after `prev`, as `import_media` takes them
+#[query]
+pub fn export_media(
+    prev: Option<ByteBuf>,
+    take: Option<u128>,
+) -> Result<Vec<MediaAsset>, String> {
+    export(|s| s.export_media(prev, take))
+}
+
+// The `export_*` queries answer once `prepare_export` succeeded
+fn export<T>(f: impl FnOnce(&State) -> T) -> Result<T, String> {
+    STATE.with_borrow(|s| {
+        s.check_exportable()?;
+        Ok(f(s))
+    })
+}
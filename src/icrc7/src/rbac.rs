@@ -0,0 +1,185 @@
+use candid::Principal;
+use icrc7_types::{
+    errors::RoleError,
+    icrc7_types::TransactionType,
+    rbac_types::{Role, RoleArg, RoleResult},
+};
+use crate::{state::State, utils::default_account};
+
+impl State {
+    pub fn has_role(&self, principal: &Principal, role: Role) -> bool {
+        if Some(*principal) == self.owner {
+            return true;
+        }
+        match self.roles.get(principal) {
+            None => false,
+            Some(roles) => {
+                roles.contains(&role) || (role != Role::Owner && roles.contains(&Role::Admin))
+            }
+        }
+    }
+
+    // Admins are managed by the owner, the other roles by admins. Ownership is only
+    // ever handed over through `transfer_ownership` and `accept_ownership`.
+    fn can_manage(&self, caller: &Principal, role: Role) -> bool {
+        match role {
+            Role::Owner => false,
+            Role::Admin => Some(*caller) == self.owner,
+            _ => self.has_role(caller, Role::Admin),
+        }
+    }
+
+    pub fn grant_role(&mut self, caller: &Principal, arg: RoleArg, at: u64) -> RoleResult {
+        if !self.can_manage(caller, arg.role) {
+            return Err(RoleError::Unauthorized);
+        }
+        if !self.roles.entry(arg.principal).or_default().insert(arg.role) {
+            return Err(RoleError::AlreadyGranted);
+        }
+        Ok(self.log_transaction(
+            TransactionType::GrantRole {
+                account: default_account(&arg.principal),
+                role: arg.role,
+                by: default_account(caller),
+            },
+            at,
+            None,
+        ))
+    }
+
+    pub fn revoke_role(&mut self, caller: &Principal, arg: RoleArg, at: u64) -> RoleResult {
+        if !self.can_manage(caller, arg.role) {
+            return Err(RoleError::Unauthorized);
+        }
+        let revoked = match self.roles.get_mut(&arg.principal) {
+            None => false,
+            Some(roles) => {
+                let revoked = roles.remove(&arg.role);
+                if roles.is_empty() {
+                    self.roles.remove(&arg.principal);
+                }
+                revoked
+            }
+        };
+        if !revoked {
+            return Err(RoleError::NotGranted);
+        }
+        Ok(self.log_transaction(
+            TransactionType::RevokeRole {
+                account: default_account(&arg.principal),
+                role: arg.role,
+                by: default_account(caller),
+            },
+            at,
+            None,
+        ))
+    }
+
+    // First step of an ownership transfer, completed once the new owner accepts it
+    pub fn transfer_ownership(
+        &mut self,
+        caller: &Principal,
+        new_owner: Principal,
+        at: u64,
+    ) -> RoleResult {
+        if Some(*caller) != self.owner {
+            return Err(RoleError::Unauthorized);
+        }
+        self.pending_owner = Some(new_owner);
+        Ok(self.log_transaction(
+            TransactionType::TransferOwnership {
+                account: default_account(&new_owner),
+                by: default_account(caller),
+            },
+            at,
+            None,
+        ))
+    }
+
+    pub fn accept_ownership(&mut self, caller: &Principal, at: u64) -> RoleResult {
+        if self.pending_owner.is_none() {
+            return Err(RoleError::NoPendingOwner);
+        }
+        if self.pending_owner != Some(*caller) {
+            return Err(RoleError::Unauthorized);
+        }
+        self.owner = self.pending_owner.take();
+        Ok(self.log_transaction(
+            TransactionType::AcceptOwnership {
+                by: default_account(caller),
+            },
+            at,
+            None,
+        ))
+    }
+
+    pub fn roles_of(&self, principal: &Principal) -> Vec<Role> {
+        let mut roles: Vec<Role> = self
+            .roles
+            .get(principal)
+            .map(|roles| roles.iter().copied().collect())
+            .unwrap_or_default();
+        if Some(*principal) == self.owner {
+            roles.insert(0, Role::Owner);
+        }
+        roles
+    }
+
+    pub fn role_members(&self, role: Role) -> Vec<Principal> {
+        if role == Role::Owner {
+            return self.owner.into_iter().collect();
+        }
+        self.roles
+            .iter()
+            .filter(|(_, roles)| roles.contains(&role))
+            .map(|(principal, _)| *principal)
+            .collect()
+    }
+
+    // Collections created before roles existed are owned by their minting authority
+    pub fn migrate_roles(&mut self) {
+        if self.owner.is_some() {
+            return;
+        }
+        if let Some(minting_authority) = self.minting_authority {
+            self.owner = Some(minting_authority.owner);
+            self.roles
+                .entry(minting_authority.owner)
+                .or_default()
+                .insert(Role::Minter);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owner_and_admins_hold_the_roles_they_manage() {
+        let owner = Principal::from_slice(&[1]);
+        let admin = Principal::from_slice(&[2]);
+        let minter = Principal::from_slice(&[3]);
+        let mut state = State {
+            owner: Some(owner),
+            ..Default::default()
+        };
+        state.roles.entry(admin).or_default().insert(Role::Admin);
+        state.roles.entry(minter).or_default().insert(Role::Minter);
+
+        assert!(state.has_role(&owner, Role::Treasurer));
+        assert!(state.has_role(&admin, Role::Pauser));
+        assert!(!state.has_role(&admin, Role::Owner));
+        assert!(state.has_role(&minter, Role::Minter));
+        assert!(!state.has_role(&minter, Role::MetadataEditor));
+
+        assert!(state.can_manage(&owner, Role::Admin));
+        assert!(!state.can_manage(&admin, Role::Admin));
+        assert!(state.can_manage(&admin, Role::Minter));
+        assert!(!state.can_manage(&minter, Role::Minter));
+        assert!(!state.can_manage(&owner, Role::Owner));
+
+        assert_eq!(state.roles_of(&owner), vec![Role::Owner]);
+        assert_eq!(state.role_members(Role::Minter), vec![minter]);
+    }
+}
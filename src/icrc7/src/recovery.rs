@@ -0,0 +1,397 @@
+use std::collections::BTreeMap;
+use candid::Principal;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::{
+    icrc37_types::{ApprovalInfo, CollectionApprovalInfo, TokenApprovalInfo, UserAccount},
+    icrc3_types::{GetBlocksResult, QueryBlock, TransactionRange},
+    replay_types::{Approvals, ReplayMismatch, ReplayReport, ReplayedState},
+};
+use crate::{
+    state::{Icrc7Token, State, STATE},
+    utils::{burn_subaccount, token_of_subaccount},
+};
+
+// The blocks handing a token to their `to` account
+const OWNER_CHANGE_OPS: [&str; 6] = ["7mint", "7burn", "7xfer", "37xfer", "7equip", "7unequip"];
+const REPLAY_BLOCKS_PER_CALL: u128 = 100;
+const MAX_REPLAY_MISMATCHES: usize = 100;
+
+impl State {
+    // Where the live state and the replayed one disagree. Locked tokens lose their approvals
+    // without a block, so theirs are not compared.
+    pub fn replay_report(&self, replayed: &ReplayedState) -> ReplayReport {
+        let mut mismatches = vec![];
+        for (token_id, token) in self.tokens.iter() {
+            let Some(replayed_token) = replayed.tokens.get(&token_id) else {
+                mismatches.push(ReplayMismatch::UnexpectedToken { token_id });
+                continue;
+            };
+            let replayed_owner = if self.parents.contains_key(&token_id) {
+                // equipped tokens are held by the collection canister, whichever it is
+                Account {
+                    owner: token.token_owner.owner,
+                    ..replayed_token.owner
+                }
+            } else {
+                replayed_token.owner
+            };
+            if token.token_owner != replayed_owner {
+                mismatches.push(ReplayMismatch::Owner {
+                    token_id,
+                    live: token.token_owner,
+                    replayed: replayed_owner,
+                });
+            }
+            // the updates of a series print only record its overrides
+            if self.token_metadata_of(&token) != replayed_token.meta
+                && (token.edition.is_none() || token.token_meta != replayed_token.meta)
+            {
+                mismatches.push(ReplayMismatch::Metadata { token_id });
+            }
+            if !self.locked_tokens.contains(&token_id)
+                && self.live_token_approvals(token_id) != replayed_token.approvals
+            {
+                mismatches.push(ReplayMismatch::TokenApprovals { token_id });
+            }
+        }
+        for token_id in replayed.tokens.keys() {
+            if !self.tokens.contains_key(token_id) {
+                mismatches.push(ReplayMismatch::MissingToken { token_id: *token_id });
+            }
+        }
+        let live_approvals = self.live_collection_approvals();
+        let mut owners: Vec<&Account> = live_approvals
+            .keys()
+            .chain(replayed.collection_approvals.keys())
+            .collect();
+        owners.sort();
+        owners.dedup();
+        for owner in owners {
+            if live_approvals.get(owner) != replayed.collection_approvals.get(owner) {
+                mismatches.push(ReplayMismatch::CollectionApprovals { owner: *owner });
+            }
+        }
+        if self.archive_ledger_info.latest_hash != replayed.last_hash {
+            mismatches.push(ReplayMismatch::LastHash);
+        }
+        let truncated = mismatches.len() > MAX_REPLAY_MISMATCHES;
+        mismatches.truncate(MAX_REPLAY_MISMATCHES);
+        ReplayReport {
+            blocks: replayed.next_block,
+            tokens: replayed.tokens.len() as u64,
+            mismatches,
+            truncated,
+        }
+    }
+
+    // Empty approval lists left by the revocations are dropped
+    fn live_token_approvals(&self, token_id: u128) -> BTreeMap<Account, Approvals> {
+        let approvals = self.token_approvals.get(&token_id).map(|approvals| approvals.into_map());
+        approvals
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(owner, spenders)| (owner, approvals_of(spenders)))
+            .filter(|(_, spenders)| !spenders.is_empty())
+            .collect()
+    }
+
+    fn live_collection_approvals(&self) -> BTreeMap<Account, Approvals> {
+        self.collection_approvals
+            .iter()
+            .map(|(owner, spenders)| (Account::from(owner), approvals_of(spenders.into_map())))
+            .filter(|(_, spenders)| !spenders.is_empty())
+            .collect()
+    }
+
+    // Only a collection that has logged nothing yet can be rebuilt from another's blocks
+    pub fn begin_recovery(&mut self) -> Result<(), String> {
+        if self.recovery.is_some() {
+            return Err("The collection is already being recovered".into());
+        }
+        if self.txn_count > 0 || !self.tokens.is_empty() {
+            return Err("Only a fresh collection can be recovered".into());
+        }
+        self.recovery = Some(ReplayedState::default());
+        Ok(())
+    }
+
+    // Replays the next blocks of the log being recovered, in order, keeping them as the
+    // collection's own log. Returns the next block expected.
+    pub fn import_blocks(
+        &mut self,
+        blocks: Vec<QueryBlock>,
+        canister: Principal,
+    ) -> Result<u128, String> {
+        let mut replay = self
+            .recovery
+            .take()
+            .ok_or_else(|| String::from("The collection is not being recovered"))?;
+        let res = self.import(&mut replay, blocks, canister);
+        self.recovery = Some(replay);
+        res
+    }
+
+    fn import(
+        &mut self,
+        replay: &mut ReplayedState,
+        blocks: Vec<QueryBlock>,
+        canister: Principal,
+    ) -> Result<u128, String> {
+        for QueryBlock { id, block } in blocks {
+            if id != self.txn_count {
+                return Err(format!("Block {id} does not follow the collection log"));
+            }
+            let txn = replay.apply(id, &block)?;
+            if OWNER_CHANGE_OPS.contains(&txn.op.as_str()) {
+                if let Some(to) = txn.to {
+                    let to = local_account(replay, to, canister);
+                    self.ownership_history
+                        .insert((txn.tid, id), UserAccount::new(to));
+                }
+            }
+            self.txn_ledger.insert(id, txn);
+            self.txn_count += 1;
+            self.archive_ledger_info.last_index += 1;
+            self.archive_ledger_info.latest_hash = replay.last_hash;
+            self.archive_ledger_info.local_ledger_size += 1;
+        }
+        Ok(replay.next_block)
+    }
+
+    // Loads the replayed tokens and approvals, leaving the recovery mode. Series, royalties,
+    // rentals and locks are not recorded by the blocks and are set up again separately.
+    pub fn end_recovery(&mut self, canister: Principal) -> Result<ReplayReport, String> {
+        let replay = self
+            .recovery
+            .take()
+            .ok_or_else(|| String::from("The collection is not being recovered"))?;
+        for (token_id, replayed_token) in &replay.tokens {
+            let token_id = *token_id;
+            let owner = local_account(&replay, replayed_token.owner, canister);
+            let burned = owner.subaccount == Some(burn_subaccount());
+            if !burned {
+                self.acquire_media(&replayed_token.meta);
+                self.index_traits(token_id, &replayed_token.meta);
+            }
+            if let Some(parent_id) = parent_of(&replay, &owner) {
+                self.parents.insert(token_id, parent_id);
+            }
+            let token = Icrc7Token {
+                token_id,
+                token_owner: owner,
+                token_meta: replayed_token.meta.clone(),
+                edition: None,
+                token_user: None,
+            };
+            self.tokens.insert(token_id, token);
+            for (from, spenders) in &replayed_token.approvals {
+                for (spender, expires_at) in spenders {
+                    let approval =
+                        ApprovalInfo::new(from.subaccount, *spender, None, *expires_at, None);
+                    let token_approval = match self.token_approvals.get(&token_id) {
+                        None => TokenApprovalInfo::new(*from, approval),
+                        Some(mut token_approval) => {
+                            token_approval.approve(*from, approval);
+                            token_approval
+                        }
+                    };
+                    self.token_approvals.insert(token_id, token_approval);
+                }
+            }
+        }
+        for (from, spenders) in &replay.collection_approvals {
+            for (spender, expires_at) in spenders {
+                let approval =
+                    ApprovalInfo::new(from.subaccount, *spender, None, *expires_at, None);
+                let user = UserAccount::new(*from);
+                let collection_approval = match self.collection_approvals.get(&user) {
+                    None => CollectionApprovalInfo::new(*spender, approval),
+                    Some(mut collection_approval) => {
+                        collection_approval.approve(*spender, approval);
+                        collection_approval
+                    }
+                };
+                self.collection_approvals.insert(user, collection_approval);
+            }
+        }
+        // burns keep the tokens, so the supply counts every mint
+        self.icrc7_total_supply = replay.tokens.len() as u128;
+        self.next_token_id = replay
+            .tokens
+            .keys()
+            .next_back()
+            .map_or(0, |token_id| token_id + 1);
+        self.holdings = None;
+        self.migrate_holdings();
+        Ok(self.replay_report(&replay))
+    }
+}
+
+// The parent token whose account holds an equipped token
+fn parent_of(replay: &ReplayedState, owner: &Account) -> Option<u128> {
+    owner
+        .subaccount
+        .and_then(|subaccount| token_of_subaccount(&subaccount))
+        .filter(|parent_id| replay.tokens.contains_key(parent_id))
+}
+
+// The blocks name the token accounts of the collection they come from, which a collection
+// rebuilt elsewhere is not, so those are moved to this canister
+fn local_account(replay: &ReplayedState, account: Account, canister: Principal) -> Account {
+    match parent_of(replay, &account) {
+        Some(_) => Account {
+            owner: canister,
+            ..account
+        },
+        None => account,
+    }
+}
+
+fn approvals_of(spenders: BTreeMap<Account, ApprovalInfo>) -> Approvals {
+    spenders
+        .into_iter()
+        .map(|(spender, approval)| (spender, approval.expires_at))
+        .collect()
+}
+
+// Replays the whole log of the collection, its archives included, and compares the result
+// with the live state
+pub async fn verify_replay() -> Result<ReplayReport, String> {
+    let mut replay = ReplayedState::default();
+    while replay.next_block < STATE.with_borrow(|s| s.txn_count) {
+        let range = TransactionRange {
+            start: replay.next_block,
+            length: REPLAY_BLOCKS_PER_CALL,
+        };
+        let res = STATE.with_borrow(|s| s.icrc3_get_blocks(vec![range]));
+        let mut blocks = res.blocks;
+        for archived in res.archived_blocks {
+            let callback = archived.callback;
+            let (archived,): (GetBlocksResult,) =
+                ic_cdk::call(callback.canister_id, &callback.method, (archived.args,))
+                    .await
+                    .map_err(|(code, msg)| {
+                        format!("Rejection Code: {:?}, Message: {:?}", code, msg)
+                    })?;
+            blocks.extend(archived.blocks);
+        }
+        blocks.sort_by_key(|block| block.id);
+        let next_block = replay.next_block;
+        for QueryBlock { id, block } in blocks {
+            if id >= replay.next_block {
+                replay.apply(id, &block)?;
+            }
+        }
+        if replay.next_block == next_block {
+            return Err(format!("Block {next_block} could not be fetched"));
+        }
+    }
+    Ok(STATE.with_borrow(|s| s.replay_report(&replay)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        market::tests::account,
+        utils::{canister_id, token_subaccount},
+    };
+    use icrc_ledger_types::icrc::generic_value::Value;
+    use icrc7_types::{
+        icrc3_types::Block,
+        icrc7_types::{Icrc7TokenMetadata, Transaction},
+        replay_types::replay,
+    };
+
+    fn chain(txns: Vec<Transaction>) -> Vec<QueryBlock> {
+        let mut phash = None;
+        let mut blocks = vec![];
+        for (id, txn) in txns.into_iter().enumerate() {
+            let block = Block::new(phash, txn).into_inner();
+            phash = Some(block.hash());
+            blocks.push(QueryBlock {
+                id: id as u128,
+                block,
+            });
+        }
+        blocks
+    }
+
+    #[test]
+    fn blocks_import_into_a_fresh_collection() {
+        let (alice, bob) = (account(1), account(2));
+        let meta = Icrc7TokenMetadata::from([("name".to_string(), Value::text("Rose"))]);
+        let blocks = chain(vec![
+            Transaction::mint(1, 1, Some(alice), alice, meta.clone(), None),
+            Transaction::mint(2, 4, Some(alice), alice, meta.clone(), None),
+            Transaction::transfer(3, 4, alice, bob, None),
+            Transaction::approve(4, 1, alice, bob, None, None),
+            Transaction::approve_collection(5, bob, alice, Some(9), None),
+        ]);
+
+        let mut state = State::default();
+        state.begin_recovery().unwrap();
+        let canister = canister_id();
+        assert_eq!(state.import_blocks(blocks[..2].to_vec(), canister), Ok(2));
+        assert!(state.import_blocks(blocks[3..].to_vec(), canister).is_err());
+        assert_eq!(state.import_blocks(blocks[2..].to_vec(), canister), Ok(5));
+        let report = state.end_recovery(canister).unwrap();
+        assert!(report.mismatches.is_empty(), "{:?}", report.mismatches);
+        assert!(state.begin_recovery().is_err());
+
+        assert_eq!(state.icrc7_owner_of(&[1, 4]), vec![Some(alice), Some(bob)]);
+        assert_eq!(state.icrc7_total_supply, 2);
+        assert_eq!(state.next_token_id, 5);
+        assert_eq!(state.txn_count, 5);
+        assert_eq!(state.archive_ledger_info.latest_hash, Some(blocks[4].block.hash()));
+        assert!(state.is_approved_by_token(&1, &alice, &bob, 0));
+        assert_eq!(state.holder_stats().holders, 2);
+
+        let mut replayed = replay(blocks).unwrap();
+        replayed.tokens.get_mut(&4).unwrap().owner = alice;
+        replayed.tokens.remove(&1);
+        let report = state.replay_report(&replayed);
+        assert_eq!(
+            report.mismatches,
+            vec![
+                ReplayMismatch::UnexpectedToken { token_id: 1 },
+                ReplayMismatch::Owner {
+                    token_id: 4,
+                    live: bob,
+                    replayed: alice,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn equipped_tokens_are_held_by_the_recovering_canister() {
+        let alice = account(1);
+        let old_parent_account = Account {
+            owner: Principal::from_slice(&[7]),
+            subaccount: Some(token_subaccount(1)),
+        };
+        let meta = Icrc7TokenMetadata::from([("name".to_string(), Value::text("Rose"))]);
+        let blocks = chain(vec![
+            Transaction::mint(1, 1, Some(alice), alice, meta.clone(), None),
+            Transaction::mint(2, 2, Some(alice), alice, meta, None),
+            Transaction::equipment_change(3, "7equip", 2, alice, old_parent_account, None, None),
+        ]);
+
+        let mut state = State::default();
+        let canister = canister_id();
+        state.begin_recovery().unwrap();
+        state.import_blocks(blocks, canister).unwrap();
+        let report = state.end_recovery(canister).unwrap();
+        assert!(report.mismatches.is_empty(), "{:?}", report.mismatches);
+
+        let parent_account = Account {
+            owner: canister,
+            ..old_parent_account
+        };
+        let token = state.tokens.get(&2).unwrap();
+        assert_eq!(token.token_owner, parent_account);
+        assert_eq!(state.root_owner_of(&token), alice);
+        assert_eq!(state.owner_at(2, 2, canister), Some(alice));
+    }
+}
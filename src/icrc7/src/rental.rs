@@ -0,0 +1,146 @@
+use std::time::Duration;
+use candid::Principal;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::{
+    errors::SetUserError,
+    icrc7_types::TransactionType,
+    rental_types::{SetUserArg, SetUserResult, TokenUser},
+};
+use crate::{
+    state::{State, STATE},
+    utils::account_transformer,
+};
+
+impl State {
+    // Assigns or, with no user, removes the user of a token. Only the token owner can do so.
+    pub fn set_user(&mut self, caller: &Principal, arg: SetUserArg, now: u64) -> SetUserResult {
+        let caller = account_transformer(Account {
+            owner: *caller,
+            subaccount: arg.from_subaccount,
+        });
+        let mut token = self
+            .tokens
+            .get(&arg.token_id)
+            .ok_or(SetUserError::NonExistingTokenId)?;
+        if token.token_owner != caller {
+            return Err(SetUserError::Unauthorized);
+        }
+        let user = arg.user.map(account_transformer);
+        if let Some(ref user) = user {
+            if arg.expires_at <= now {
+                return Err(SetUserError::InvalidExpiry);
+            }
+            if self.is_frozen(&arg.token_id, &[&caller, user]) {
+                return Err(SetUserError::Frozen);
+            }
+        }
+        if let Some(ref memo) = arg.memo {
+            let max_memo_size = self.icrc7_max_memo_size.unwrap_or(State::DEFAULT_MAX_MEMO_SIZE);
+            if memo.len() as u32 > max_memo_size {
+                return Err(SetUserError::GenericError {
+                    error_code: 1,
+                    message: "Exceeds Max Memo Length".into(),
+                });
+            }
+        }
+
+        token.token_user = user.map(|user| TokenUser {
+            user,
+            expires_at: arg.expires_at,
+        });
+        self.tokens.insert(arg.token_id, token);
+        if user.is_some() {
+            self.rentals.insert(arg.token_id, arg.expires_at);
+        } else {
+            self.rentals.remove(&arg.token_id);
+        }
+        Ok(self.log_transaction(
+            TransactionType::SetUser {
+                tid: arg.token_id,
+                from: caller,
+                user,
+                expires_at: user.map(|_| arg.expires_at),
+            },
+            now,
+            arg.memo,
+        ))
+    }
+
+    // The current user of a token, none once the rental has expired
+    pub fn user_of(&self, token_id: u128, now: u64) -> Option<TokenUser> {
+        self.tokens
+            .get(&token_id)
+            .and_then(|token| token.token_user)
+            .filter(|token_user| token_user.expires_at > now)
+    }
+
+    // Takes out the rental of a token if it is due, returning the user it ends for. Rentals
+    // renewed or already cleared by a transfer are left alone.
+    pub fn take_expired_user(&mut self, token_id: u128, now: u64) -> Option<Account> {
+        let expires_at = *self.rentals.get(&token_id)?;
+        if expires_at > now {
+            return None;
+        }
+        self.rentals.remove(&token_id);
+        let mut token = self.tokens.get(&token_id)?;
+        let token_user = token.token_user.take().filter(|user| user.expires_at <= now)?;
+        self.tokens.insert(token_id, token);
+        Some(token_user.user)
+    }
+}
+
+pub fn schedule_user_expiry(token_id: u128, expires_at: u64) {
+    let delay = Duration::from_nanos(expires_at.saturating_sub(ic_cdk::api::time()));
+    ic_cdk_timers::set_timer(delay, move || {
+        STATE.with_borrow_mut(|s| {
+            let now = ic_cdk::api::time();
+            if let Some(user) = s.take_expired_user(token_id, now) {
+                s.log_transaction(TransactionType::UserExpired { tid: token_id, user }, now, None);
+            }
+        });
+    });
+}
+
+// Timers do not survive upgrades, so they are re-armed for every ongoing rental
+pub fn schedule_rental_timers() {
+    let rentals: Vec<(u128, u64)> =
+        STATE.with_borrow(|s| s.rentals.iter().map(|(id, at)| (*id, *at)).collect());
+    for (token_id, expires_at) in rentals {
+        schedule_user_expiry(token_id, expires_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::tests::account;
+    use crate::state::Icrc7Token;
+
+    #[test]
+    fn rentals_expire_and_end_on_transfer() {
+        let mut state = State::default();
+        let renter = account(2);
+        let mut token = Icrc7Token {
+            token_id: 1,
+            token_owner: account(1),
+            token_meta: Default::default(),
+            edition: None,
+            token_user: Some(TokenUser {
+                user: renter,
+                expires_at: 100,
+            }),
+        };
+        state.tokens.insert(1, token.clone());
+        state.rentals.insert(1, 100);
+
+        assert_eq!(state.user_of(1, 50).map(|user| user.user), Some(renter));
+        assert_eq!(state.user_of(1, 100), None);
+        assert_eq!(state.take_expired_user(1, 50), None);
+        assert_eq!(state.take_expired_user(1, 100), Some(renter));
+        assert!(state.tokens.get(&1).unwrap().token_user.is_none());
+        assert!(state.rentals.is_empty());
+
+        token.transfer(account(3));
+        assert!(token.token_user.is_none());
+    }
+}
@@ -0,0 +1,272 @@
+use candid::Principal;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::{
+    errors::RevenueError,
+    revenue_types::{
+        DepositRevenueArg, DepositRevenueResult, RevenueEntry, RevenueEntryKind, Stakeholder,
+        WithdrawArg, WithdrawResult,
+    },
+};
+use crate::{
+    ledger::{send, IcrcLedger, Ledger},
+    market::{collect_payment, split_proceeds, MAX_BPS},
+    state::{State, STATE},
+    utils::{account_transformer, revenue_account, revenue_subaccount},
+};
+
+impl State {
+    pub fn set_revenue_split(&mut self, split: Vec<Stakeholder>) -> Result<(), String> {
+        if self.is_finalized() {
+            return Err("The collection is finalized".into());
+        }
+        validate_revenue_split(&split)?;
+        self.revenue_split = split;
+        Ok(())
+    }
+
+    // Where the seller share of a sale goes: primary sales, made by the minting authority,
+    // fund the revenue split instead
+    pub fn proceeds_recipient(&self, seller: Account) -> Account {
+        if !self.revenue_split.is_empty() && Some(seller) == self.minting_authority {
+            revenue_account()
+        } else {
+            seller
+        }
+    }
+
+    // Splits revenue already held in the revenue account among the stakeholders,
+    // the rounding remainder going to the first one
+    pub fn credit_revenue(&mut self, ledger: Principal, amount: u128, at: u64) -> u128 {
+        let split: Vec<(Account, u16)> = if self.revenue_split.is_empty() {
+            self.minting_authority
+                .map(|account| vec![(account, MAX_BPS)])
+                .unwrap_or_default()
+        } else {
+            self.revenue_split
+                .iter()
+                .map(|stakeholder| (stakeholder.account, stakeholder.bps))
+                .collect()
+        };
+        let shares = match split.split_first() {
+            Some(((first, _), rest)) => split_proceeds(amount, rest, *first),
+            None => vec![],
+        };
+        for (account, share) in &shares {
+            *self
+                .revenue_balances
+                .entry(*account)
+                .or_default()
+                .entry(ledger)
+                .or_default() += share;
+        }
+        self.push_revenue_entry(ledger, amount, RevenueEntryKind::Credit { shares }, at)
+    }
+
+    pub fn revenue_balances_of(&self, account: &Account) -> Vec<(Principal, u128)> {
+        self.revenue_balances
+            .get(account)
+            .map(|balances| balances.iter().map(|(ledger, amount)| (*ledger, *amount)).collect())
+            .unwrap_or_default()
+    }
+
+    // Zeroes a claimable balance while it is being withdrawn
+    pub fn take_revenue_balance(&mut self, account: &Account, ledger: Principal) -> u128 {
+        self.revenue_balances
+            .get_mut(account)
+            .and_then(|balances| balances.remove(&ledger))
+            .unwrap_or_default()
+    }
+
+    pub fn restore_revenue_balance(&mut self, account: Account, ledger: Principal, amount: u128) {
+        *self
+            .revenue_balances
+            .entry(account)
+            .or_default()
+            .entry(ledger)
+            .or_default() += amount;
+    }
+
+    pub fn get_revenue_history(&self, prev: Option<u128>, take: Option<u128>) -> Vec<RevenueEntry> {
+        let take = self.get_current_take(take);
+        let start = prev.map_or(0, |prev| prev + 1);
+        self.revenue_history
+            .range(start..)
+            .map(|(_, entry)| entry)
+            .take(take as usize)
+            .collect()
+    }
+
+    pub fn push_revenue_entry(
+        &mut self,
+        ledger: Principal,
+        amount: u128,
+        kind: RevenueEntryKind,
+        at: u64,
+    ) -> u128 {
+        let id = self.revenue_history.len() as u128;
+        self.revenue_history.insert(
+            id,
+            RevenueEntry {
+                id,
+                ledger,
+                amount,
+                kind,
+                at,
+            },
+        );
+        id
+    }
+}
+
+pub fn validate_revenue_split(split: &[Stakeholder]) -> Result<(), String> {
+    if split.is_empty() {
+        return Ok(());
+    }
+    let total: u32 = split.iter().map(|stakeholder| stakeholder.bps as u32).sum();
+    if total != MAX_BPS as u32 {
+        return Err("Revenue split must add up to 10000 basis points".into());
+    }
+    Ok(())
+}
+
+pub async fn deposit_revenue(caller: Principal, arg: DepositRevenueArg) -> DepositRevenueResult {
+    let from = account_transformer(Account {
+        owner: caller,
+        subaccount: arg.from_subaccount,
+    });
+    if STATE.with_borrow(|s| s.revenue_split.is_empty()) {
+        return Err(RevenueError::NoRevenueSplit);
+    }
+    if arg.amount == 0 {
+        return Err(RevenueError::InvalidAmount);
+    }
+    let ledger = IcrcLedger(arg.ledger);
+    let fee = ledger
+        .fee()
+        .await
+        .map_err(|message| RevenueError::TransferFailed { message })?;
+    collect_payment(&ledger, from, revenue_account(), arg.amount, fee)
+        .await
+        .map_err(|message| RevenueError::TransferFailed { message })?;
+    Ok(STATE.with_borrow_mut(|s| s.credit_revenue(arg.ledger, arg.amount, ic_cdk::api::time())))
+}
+
+pub async fn withdraw(caller: Principal, arg: WithdrawArg) -> WithdrawResult {
+    let account = account_transformer(Account {
+        owner: caller,
+        subaccount: arg.from_subaccount,
+    });
+    let to = account_transformer(arg.to.unwrap_or(account));
+    let ledger = IcrcLedger(arg.ledger);
+    let fee = ledger
+        .fee()
+        .await
+        .map_err(|message| RevenueError::TransferFailed { message })?;
+
+    let balance = STATE.with_borrow_mut(|s| s.take_revenue_balance(&account, arg.ledger));
+    if balance <= fee {
+        STATE.with_borrow_mut(|s| s.restore_revenue_balance(account, arg.ledger, balance));
+        return Err(RevenueError::InsufficientBalance { balance });
+    }
+    match send(&ledger, Some(revenue_subaccount()), to, balance, fee).await {
+        Ok(block_index) => {
+            let block_index = block_index.unwrap_or_default();
+            STATE.with_borrow_mut(|s| {
+                s.push_revenue_entry(
+                    arg.ledger,
+                    balance,
+                    RevenueEntryKind::Withdrawal {
+                        account,
+                        to,
+                        block_index,
+                    },
+                    ic_cdk::api::time(),
+                )
+            });
+            Ok(block_index)
+        }
+        Err(message) => {
+            STATE.with_borrow_mut(|s| s.restore_revenue_balance(account, arg.ledger, balance));
+            Err(RevenueError::TransferFailed { message })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use icrc7_types::market_types::Payout;
+    use crate::{
+        market::{
+            pay_out,
+            tests::{account, block_on, LocalLedger},
+        },
+        utils::{canister_id, market_subaccount},
+    };
+
+    #[test]
+    fn credit_revenue_splits_among_stakeholders() {
+        let ledger = Principal::from_slice(&[42]);
+        let mut state = State::default();
+        let stakeholder = |name: &str, n: u8, bps: u16| Stakeholder {
+            name: name.into(),
+            account: account(n),
+            bps,
+        };
+        assert!(state
+            .set_revenue_split(vec![
+                stakeholder("artist", 1, 7_000),
+                stakeholder("launchpad", 2, 2_000),
+            ])
+            .is_err());
+        state
+            .set_revenue_split(vec![
+                stakeholder("artist", 1, 7_000),
+                stakeholder("launchpad", 2, 2_000),
+                stakeholder("developers", 3, 1_000),
+            ])
+            .unwrap();
+
+        state.credit_revenue(ledger, 10_003, 1);
+        assert_eq!(state.revenue_balances_of(&account(1)), vec![(ledger, 7_003)]);
+        assert_eq!(state.revenue_balances_of(&account(2)), vec![(ledger, 2_000)]);
+        assert_eq!(state.revenue_balances_of(&account(3)), vec![(ledger, 1_000)]);
+
+        assert_eq!(state.take_revenue_balance(&account(2), ledger), 2_000);
+        assert_eq!(state.take_revenue_balance(&account(2), ledger), 0);
+        state.restore_revenue_balance(account(2), ledger, 2_000);
+        assert_eq!(state.revenue_balances_of(&account(2)), vec![(ledger, 2_000)]);
+        assert_eq!(state.get_revenue_history(None, None).len(), 1);
+    }
+
+    #[test]
+    fn proceeds_paid_to_the_revenue_account_are_credited() {
+        let ledger = LocalLedger::new(canister_id(), 10);
+        let escrow = Account {
+            owner: canister_id(),
+            subaccount: Some(market_subaccount()),
+        };
+        ledger.mint(escrow, 2_020);
+        STATE.with_borrow_mut(|s| {
+            let split = vec![Stakeholder {
+                name: "artist".into(),
+                account: account(1),
+                bps: 10_000,
+            }];
+            s.set_revenue_split(split).unwrap();
+        });
+        let payout = |to| Payout {
+            ledger: ledger.canister,
+            from_subaccount: Some(market_subaccount()),
+            to,
+            amount: 1_010,
+        };
+        let payouts = vec![payout(revenue_account()), payout(account(2))];
+        assert!(block_on(pay_out(&ledger, payouts, 10)).is_empty());
+
+        let balances = STATE.with_borrow(|s| s.revenue_balances_of(&account(1)));
+        assert_eq!(balances, vec![(ledger.canister, 1_000)]);
+        assert_eq!(ledger.balance(&revenue_account()), 1_000);
+        assert_eq!(ledger.balance(&account(2)), 1_000);
+    }
+}
@@ -0,0 +1,117 @@
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::icrc7_types::RoyaltyRecipient;
+use crate::{market::MAX_BPS, state::State};
+
+impl State {
+    pub fn set_royalties(&mut self, royalties: Vec<RoyaltyRecipient>) -> Result<(), String> {
+        if self.is_finalized() {
+            return Err("The collection is finalized".into());
+        }
+        validate_royalties(&royalties)?;
+        self.royalties = royalties;
+        Ok(())
+    }
+
+    // Overrides the collection royalties of a token, `None` falling back to them again
+    pub fn set_token_royalties(
+        &mut self,
+        token_id: u128,
+        royalties: Option<Vec<RoyaltyRecipient>>,
+    ) -> Result<(), String> {
+        if self.is_finalized() {
+            return Err("The collection is finalized".into());
+        }
+        if !self.tokens.contains_key(&token_id) {
+            return Err("Non existing token id".into());
+        }
+        match royalties {
+            Some(royalties) => {
+                validate_royalties(&royalties)?;
+                self.token_royalties.insert(token_id, royalties);
+            }
+            None => {
+                self.token_royalties.remove(&token_id);
+            }
+        }
+        Ok(())
+    }
+
+    // The royalties due on a sale of the token
+    pub fn royalties_of(&self, token_id: u128) -> &[RoyaltyRecipient] {
+        self.token_royalties
+            .get(&token_id)
+            .unwrap_or(&self.royalties)
+    }
+
+    pub fn icrc7_royalties(&self, token_ids: &[u128]) -> Vec<Option<Vec<RoyaltyRecipient>>> {
+        token_ids
+            .iter()
+            .map(|token_id| {
+                self.tokens
+                    .contains_key(token_id)
+                    .then(|| self.royalties_of(*token_id).to_vec())
+            })
+            .collect()
+    }
+
+    pub fn royalty_shares(&self, token_id: u128) -> Vec<(Account, u16)> {
+        self.royalties_of(token_id)
+            .iter()
+            .filter(|recipient| recipient.bps > 0)
+            .map(|recipient| (recipient.account, recipient.bps))
+            .collect()
+    }
+}
+
+pub fn validate_royalties(royalties: &[RoyaltyRecipient]) -> Result<(), String> {
+    let total: u32 = royalties.iter().map(|recipient| recipient.bps as u32).sum();
+    if total > MAX_BPS as u32 {
+        return Err("Royalties exceed 10000 basis points".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{market::tests::account, state::Icrc7Token};
+    use icrc7_types::market_types::MarketFee;
+
+    fn recipient(n: u8, bps: u16) -> RoyaltyRecipient {
+        RoyaltyRecipient {
+            account: account(n),
+            bps,
+        }
+    }
+
+    #[test]
+    fn sale_shares_include_token_or_collection_royalties() {
+        let mut state = State::default();
+        for token_id in [1, 2] {
+            state.tokens.insert(
+                token_id,
+                Icrc7Token {
+                    token_id,
+                    token_owner: account(9),
+                    token_meta: Default::default(),
+                    edition: None,
+                    token_user: None,
+                },
+            );
+        }
+        state.market_fee = Some(MarketFee {
+            fee_bps: 250,
+            fee_account: account(1),
+        });
+        state.set_royalties(vec![recipient(2, 500), recipient(3, 0)]).unwrap();
+        state.set_token_royalties(2, Some(vec![recipient(4, 1_000)])).unwrap();
+
+        assert_eq!(state.sale_shares(1), vec![(account(1), 250), (account(2), 500)]);
+        assert_eq!(state.sale_shares(2), vec![(account(1), 250), (account(4), 1_000)]);
+
+        state.set_token_royalties(2, None).unwrap();
+        assert_eq!(state.sale_shares(2), state.sale_shares(1));
+        assert!(state.set_token_royalties(3, None).is_err());
+        assert!(state.set_royalties(vec![recipient(2, 6_000), recipient(3, 4_001)]).is_err());
+    }
+}
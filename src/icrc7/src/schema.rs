@@ -0,0 +1,190 @@
+use icrc_ledger_types::icrc::generic_value::Value;
+use icrc7_types::{
+    icrc7_types::Icrc7TokenMetadata,
+    schema_types::{FieldSchema, MetadataSchema, SchemaViolation, ValueType},
+    series_types::Edition,
+};
+use crate::state::State;
+
+impl State {
+    // `None` removes the schema. Tokens minted before are not checked again.
+    pub fn set_metadata_schema(&mut self, schema: Option<MetadataSchema>) -> Result<(), String> {
+        if self.is_finalized() {
+            return Err("The collection is finalized".into());
+        }
+        if let Some(ref schema) = schema {
+            for (key, field) in &schema.fields {
+                let mismatched = field
+                    .allowed_values
+                    .iter()
+                    .flatten()
+                    .any(|value| ValueType::of(value) != field.value_type);
+                if mismatched {
+                    return Err(format!("The allowed values of {key} are not all of its type"));
+                }
+            }
+        }
+        self.metadata_schema = schema;
+        Ok(())
+    }
+
+    // Checks the metadata a token would have once minted or updated with `meta`
+    pub fn check_metadata_schema(
+        &self,
+        meta: &Icrc7TokenMetadata,
+        edition: Option<Edition>,
+    ) -> Result<(), Vec<SchemaViolation>> {
+        let Some(ref schema) = self.metadata_schema else {
+            return Ok(());
+        };
+        let series_meta = edition.and_then(|edition| self.series.get(&edition.series_id));
+        let violations = match series_meta {
+            Some(series) => {
+                let mut merged = series.meta;
+                merged.extend(meta.clone());
+                schema_violations(schema, &merged)
+            }
+            None => schema_violations(schema, meta),
+        };
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+pub fn schema_violations(
+    schema: &MetadataSchema,
+    meta: &Icrc7TokenMetadata,
+) -> Vec<SchemaViolation> {
+    let mut violations = vec![];
+    for (key, field) in &schema.fields {
+        match meta.get(key) {
+            Some(value) => violations.extend(field_violation(key, field, value)),
+            None if field.required => violations.push(SchemaViolation::MissingKey {
+                key: key.clone(),
+            }),
+            None => {}
+        }
+    }
+    if !schema.allow_extra_keys {
+        violations.extend(
+            meta.keys()
+                .filter(|key| !schema.fields.contains_key(*key))
+                .map(|key| SchemaViolation::UnexpectedKey { key: key.clone() }),
+        );
+    }
+    violations
+}
+
+fn field_violation(key: &str, field: &FieldSchema, value: &Value) -> Option<SchemaViolation> {
+    if ValueType::of(value) != field.value_type {
+        return Some(SchemaViolation::WrongType {
+            key: key.into(),
+            expected: field.value_type,
+        });
+    }
+    if let Some(ref allowed_values) = field.allowed_values {
+        if !allowed_values.contains(value) {
+            return Some(SchemaViolation::ValueNotAllowed { key: key.into() });
+        }
+    }
+    let size = match value {
+        Value::Text(text) => text.len(),
+        Value::Blob(bytes) => bytes.len(),
+        Value::Array(values) => values.len(),
+        Value::Map(map) => map.len(),
+        _ => 0,
+    };
+    match field.max_size {
+        Some(max_size) if size as u64 > max_size => Some(SchemaViolation::TooLarge {
+            key: key.into(),
+            max_size,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn metadata_is_checked_against_the_schema() {
+        let field = |value_type, required| FieldSchema {
+            value_type,
+            required,
+            allowed_values: None,
+            max_size: None,
+        };
+        let schema = MetadataSchema {
+            fields: BTreeMap::from([
+                ("name".to_string(), FieldSchema {
+                    max_size: Some(8),
+                    ..field(ValueType::Text, true)
+                }),
+                ("level".to_string(), field(ValueType::Nat, false)),
+                ("rarity".to_string(), FieldSchema {
+                    allowed_values: Some(vec![Value::text("common"), Value::text("rare")]),
+                    ..field(ValueType::Text, true)
+                }),
+            ]),
+            allow_extra_keys: false,
+        };
+        let mut state = State::default();
+        assert!(state
+            .set_metadata_schema(Some(MetadataSchema {
+                fields: BTreeMap::from([("rarity".to_string(), FieldSchema {
+                    allowed_values: Some(vec![Value::Nat(1u32.into())]),
+                    ..field(ValueType::Text, true)
+                })]),
+                allow_extra_keys: false,
+            }))
+            .is_err());
+        state.set_metadata_schema(Some(schema.clone())).unwrap();
+
+        let valid = Icrc7TokenMetadata::from([
+            ("name".to_string(), Value::text("Rose")),
+            ("level".to_string(), Value::Nat64(3)),
+            ("rarity".to_string(), Value::text("rare")),
+        ]);
+        assert_eq!(state.check_metadata_schema(&valid, None), Ok(()));
+
+        let invalid = Icrc7TokenMetadata::from([
+            ("nmae".to_string(), Value::text("Rose")),
+            ("level".to_string(), Value::text("3")),
+            ("rarity".to_string(), Value::text("legendary")),
+        ]);
+        assert_eq!(
+            state.check_metadata_schema(&invalid, None),
+            Err(vec![
+                SchemaViolation::WrongType {
+                    key: "level".into(),
+                    expected: ValueType::Nat,
+                },
+                SchemaViolation::MissingKey { key: "name".into() },
+                SchemaViolation::ValueNotAllowed { key: "rarity".into() },
+                SchemaViolation::UnexpectedKey { key: "nmae".into() },
+            ])
+        );
+        let long_name = Icrc7TokenMetadata::from([
+            ("name".to_string(), Value::text("Rose of Sharon")),
+            ("rarity".to_string(), Value::text("common")),
+        ]);
+        assert_eq!(
+            schema_violations(&schema, &long_name),
+            vec![SchemaViolation::TooLarge {
+                key: "name".into(),
+                max_size: 8,
+            }]
+        );
+
+        // the schema survives upgrades with the rest of the heap state
+        let mut bytes = vec![];
+        ciborium::ser::into_writer(&schema, &mut bytes).unwrap();
+        let restored: MetadataSchema = ciborium::de::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(restored, schema);
+    }
+}
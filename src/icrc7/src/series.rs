@@ -0,0 +1,190 @@
+use candid::{Nat, Principal};
+use icrc_ledger_types::{icrc::generic_value::Value, icrc1::account::Account};
+use icrc7_types::{
+    errors::MintError,
+    icrc7_types::{Icrc7TokenMetadata, MintArg, MintResult},
+    series_types::{
+        CreateSeriesArg, CreateSeriesResult, Edition, MintEditionArg, Series, METADATA_EDITION,
+        METADATA_MAX_EDITIONS, METADATA_SERIES,
+    },
+};
+use crate::{
+    state::{Icrc7Token, State},
+    utils::account_transformer,
+};
+
+impl State {
+    pub fn create_series(&mut self, arg: CreateSeriesArg, at: u64) -> CreateSeriesResult {
+        if self.is_finalized() {
+            return Err("The collection is finalized".into());
+        }
+        if arg.name.trim().is_empty() {
+            return Err("The series name cannot be empty".into());
+        }
+        if arg.max_editions == Some(0) {
+            return Err("The edition cap must be greater than zero".into());
+        }
+        if self.check_media(&arg.meta).is_err() {
+            return Err("The master metadata references missing media".into());
+        }
+        self.acquire_media(&arg.meta);
+        let id = self.series.len() as u128;
+        self.series.insert(
+            id,
+            Series {
+                id,
+                name: arg.name,
+                meta: arg.meta,
+                max_editions: arg.max_editions,
+                minted: 0,
+                created_at: at,
+            },
+        );
+        Ok(id)
+    }
+
+    pub fn mint_edition(&mut self, caller: &Principal, arg: MintEditionArg) -> MintResult {
+        if self.multisig.is_some() {
+            return Err(MintError::GenericError {
+                error_code: 9,
+                message: "Minting Requires A Multisig Proposal".into(),
+            });
+        }
+        let caller = account_transformer(Account {
+            owner: *caller,
+            subaccount: arg.from_subaccount,
+        });
+        self.mint_edition_as(caller, arg)
+    }
+
+    // Mints the next edition of a series, numbered from 1
+    pub fn mint_edition_as(&mut self, caller: Account, arg: MintEditionArg) -> MintResult {
+        let mut series = self.series.get(&arg.series_id).ok_or(MintError::GenericError {
+            error_code: 11,
+            message: "Series Not Found".into(),
+        })?;
+        if series.max_editions.is_some_and(|max| series.minted >= max) {
+            return Err(MintError::GenericError {
+                error_code: 12,
+                message: "Edition Cap Reached".into(),
+            });
+        }
+        let edition = Edition {
+            series_id: series.id,
+            number: series.minted + 1,
+        };
+        let (txn_id, token_id) = self.mint_token(
+            caller,
+            MintArg {
+                from_subaccount: arg.from_subaccount,
+                to: arg.to,
+                token_id: arg.token_id,
+                memo: arg.memo,
+                meta: arg.overrides.unwrap_or_default(),
+                royalties: None,
+                locked: None,
+            },
+            Some(edition),
+        )?;
+        series.minted = edition.number;
+        self.series.insert(series.id, series);
+        self.series_tokens.insert((edition.series_id, edition.number), token_id);
+        Ok((txn_id, token_id))
+    }
+
+    // The metadata wallets see: for editions, the master metadata with the edition overrides
+    // and numbering on top
+    pub fn token_metadata_of(&self, token: &Icrc7Token) -> Icrc7TokenMetadata {
+        let Some(edition) = token.edition else {
+            return token.token_meta.clone();
+        };
+        let Some(series) = self.series.get(&edition.series_id) else {
+            return token.token_meta.clone();
+        };
+        let mut meta = series.meta;
+        meta.extend(token.token_meta.clone());
+        meta.insert(METADATA_SERIES.into(), Value::Nat(Nat::from(series.id)));
+        meta.insert(METADATA_EDITION.into(), Value::Nat(Nat::from(edition.number)));
+        if let Some(max_editions) = series.max_editions {
+            meta.insert(METADATA_MAX_EDITIONS.into(), Value::Nat(Nat::from(max_editions)));
+        }
+        meta
+    }
+
+    pub fn get_series(&self, series_id: u128) -> Option<Series> {
+        self.series.get(&series_id)
+    }
+
+    pub fn list_series(&self, prev: Option<u128>, take: Option<u128>) -> Vec<Series> {
+        let take = self.get_current_take(take);
+        let start = prev.map_or(0, |prev| prev + 1);
+        self.series
+            .range(start..)
+            .map(|(_, series)| series)
+            .take(take as usize)
+            .collect()
+    }
+
+    // The token ids of a series, by edition number
+    pub fn series_tokens_of(
+        &self,
+        series_id: u128,
+        prev: Option<u32>,
+        take: Option<u128>,
+    ) -> Vec<u128> {
+        let take = self.get_current_take(take);
+        let start = prev.map_or(1, |prev| prev.saturating_add(1));
+        self.series_tokens
+            .range((series_id, start)..=(series_id, u32::MAX))
+            .map(|(_, token_id)| token_id)
+            .take(take as usize)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::tests::account;
+
+    #[test]
+    fn editions_merge_the_master_metadata() {
+        let mut state = State::default();
+        let series_id = state
+            .create_series(
+                CreateSeriesArg {
+                    name: "Sunset".into(),
+                    meta: Icrc7TokenMetadata::from([
+                        ("icrc7:name".to_string(), Value::text("Sunset")),
+                        ("image".to_string(), Value::text("ipfs://master")),
+                    ]),
+                    max_editions: Some(100),
+                },
+                1,
+            )
+            .unwrap();
+        let edition = Edition {
+            series_id,
+            number: 7,
+        };
+        let token = Icrc7Token {
+            token_id: 42,
+            token_owner: account(1),
+            token_meta: Icrc7TokenMetadata::from([(
+                "image".to_string(),
+                Value::text("ipfs://print-7"),
+            )]),
+            edition: Some(edition),
+            token_user: None,
+        };
+        state.series_tokens.insert((series_id, 7), 42);
+
+        let meta = state.token_metadata_of(&token);
+        assert_eq!(meta.get("icrc7:name"), Some(&Value::text("Sunset")));
+        assert_eq!(meta.get("image"), Some(&Value::text("ipfs://print-7")));
+        assert_eq!(meta.get(METADATA_EDITION), Some(&Value::Nat(Nat::from(7u32))));
+        assert_eq!(meta.get(METADATA_MAX_EDITIONS), Some(&Value::Nat(Nat::from(100u32))));
+        assert_eq!(state.series_tokens_of(series_id, None, None), vec![42]);
+        assert!(state.series_tokens_of(series_id, Some(7), None).is_empty());
+    }
+}
@@ -1,7 +1,11 @@
-use std::{cell::RefCell, collections::BTreeMap, time::Duration};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
 use icrc7_types::{
     icrc37_types::{
-        ApproveCollectionArg, ApproveCollectionResult, ApproveTokenArg, ApproveTokenResult,
+        ApprovalInfo, ApproveCollectionArg, ApproveCollectionResult, ApproveTokenArg, ApproveTokenResult,
         CollectionApproval, CollectionApprovalInfo, IsApprovedArg, LedgerInfo, Metadata,
         RevokeCollectionApprovalArg, RevokeCollectionApprovalResult, RevokeTokenApprovalArg,
         RevokeTokenApprovalResult, TokenApproval, TokenApprovalInfo, TransferFromArg,
@@ -13,27 +17,53 @@ use icrc7_types::{
         Tip, TransactionRange,
     },
     icrc7_types::{
-        BurnResult, Icrc7TokenMetadata, MintArg, MintResult, Transaction, TransactionType,
-        TransferArg, TransferResult,
+        assets_url, royalties_value, BurnResult, Finalization, Icrc7TokenMetadata, MintArg, MintResult, RoyaltyRecipient,
+        Transaction, TransactionType, TransferArg, TransferPolicy, TransferResult,
+        UpdateTokenMetadataArg, UpdateTokenMetadataResult,
+        METADATA_FINALIZED, METADATA_FINALIZED_BLOCK, METADATA_FROZEN, METADATA_LAUNCHPAD_REMOVED,
+        METADATA_ROYALTIES,
+        METADATA_TRANSFERABLE, METADATA_ASSETS_CANISTER, METADATA_ASSETS_URL,
         BurnArg, SyncReceipt, TRANSACTION_TRANSFER_FROM_OP, TRANSACTION_TRANSFER_OP,
     },
     errors::{
         ApproveCollectionError, ApproveTokenError, BurnError, InsertTransactionError, MintError,
         RevokeCollectionApprovalError, RevokeTokenApprovalError, TransferError, TransferFromError,
     },
+    market_types::{Auction, Listing, MarketFee, Offer, Payout},
+    revenue_types::{RevenueEntry, Stakeholder},
+    compliance_types::FreezeRecord,
+    rbac_types::Role,
+    multisig_types::{MultisigConfig, Proposal},
+    series_types::{Edition, Series},
+    rental_types::TokenUser,
+    media_types::{MediaAsset, MediaQuota, Upload},
+    schema_types::MetadataSchema,
+    replay_types::ReplayedState,
 };
 use crate::{
     archive::create_archive_canister,
+    history::owner_change,
+    holders::Holdings,
+    http::{changed_token, CertifiedEntry, RESPONSES_VERSION},
+    migration::Migration,
     memory::{
-        get_collection_approvals_memory, get_log_memory, get_token_approvals_memory,
+        get_auctions_memory, get_collection_approvals_memory, get_frozen_accounts_memory,
+        get_frozen_tokens_memory, get_listings_memory, get_media_chunks_memory, get_media_memory,
+        get_upload_chunks_memory, get_trait_index_memory, get_ownership_history_memory,
+        get_response_hashes_memory,
+        get_log_memory, get_offers_memory, get_proposals_memory, get_revenue_history_memory,
+        get_series_memory, get_series_tokens_memory, get_token_approvals_memory,
         get_token_map_memory, Memory,
     },
-    utils::{account_transformer, burn_account, hash_icrc_value},
+    royalty::validate_royalties,
+    utils::{
+        account_transformer, burn_account, canister_id, default_account, hash_icrc_value, time,
+    },
     
 };
 use candid::{CandidType, Decode, Encode, Principal};
 use ic_cdk_timers::TimerId;
-use ic_certified_map::{leaf_hash, AsHashTree, Hash, RbTree};
+use ic_certified_map::{leaf_hash, AsHashTree, RbTree};
 use ic_stable_structures::{
     memory_manager::MemoryManager, storable::Bound, DefaultMemoryImpl, StableBTreeMap, Storable,
 };
@@ -48,6 +78,11 @@ pub struct Icrc7Token {
     pub token_id: u128,
     pub token_owner: Account,
     pub token_meta: Icrc7TokenMetadata,
+    // set for the prints of a series, `token_meta` then only holding the edition overrides
+    #[serde(default)]
+    pub edition: Option<Edition>,
+    #[serde(default)]
+    pub token_user: Option<TokenUser>,
 }
 
 impl Storable for Icrc7Token {
@@ -66,31 +101,130 @@ impl Icrc7Token {
     fn new(
         token_id: u128,
         token_owner: Account,
-        meta: Icrc7TokenMetadata
+        meta: Icrc7TokenMetadata,
+        edition: Option<Edition>,
     ) -> Self {
         Self {
             token_id,
             token_owner,
             token_meta: meta,
+            edition,
+            token_user: None,
         }
     }
 
-    fn transfer(&mut self, to: Account) {
+    // A rental does not survive a change of owner
+    pub(crate) fn transfer(&mut self, to: Account) {
         self.token_owner = to;
-    }
-
-    fn token_metadata(&self) -> Icrc7TokenMetadata {
-        self.token_meta.clone()
+        self.token_user = None;
     }
 
     fn burn(&mut self, burn_address: Account) {
         self.token_owner = burn_address;
+        self.token_user = None;
     }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct State {
+    #[serde(default)]
+    pub owner: Option<Principal>,
+    #[serde(default)]
+    pub pending_owner: Option<Principal>,
+    #[serde(default)]
+    pub roles: BTreeMap<Principal, BTreeSet<Role>>,
+    #[serde(default)]
+    pub multisig: Option<MultisigConfig>,
+    #[serde(skip, default = "get_proposals_memory")]
+    pub proposals: StableBTreeMap<u128, Proposal, Memory>,
+    #[serde(default)]
+    pub open_proposals: BTreeSet<u128>,
+    #[serde(default)]
+    pub installer: Option<Principal>,
+    #[serde(default)]
+    pub finalization: Option<Finalization>,
+    #[serde(skip, default = "get_series_memory")]
+    pub series: StableBTreeMap<u128, Series, Memory>,
+    // (series id, edition number) -> token id
+    #[serde(skip, default = "get_series_tokens_memory")]
+    pub series_tokens: StableBTreeMap<(u128, u32), u128, Memory>,
+    // token id -> rental expiry, to re-arm the expiry timers after an upgrade
+    #[serde(default)]
+    pub rentals: BTreeMap<u128, u64>,
+    // equipped token id -> parent token id, the equipped token being owned by the parent's account
+    #[serde(default)]
+    pub parents: BTreeMap<u128, u128>,
+    // sha256 -> media, its content being split in `media_chunks`
+    #[serde(skip, default = "get_media_memory")]
+    pub media: StableBTreeMap<[u8; 32], MediaAsset, Memory>,
+    #[serde(skip, default = "get_media_chunks_memory")]
+    pub media_chunks: StableBTreeMap<([u8; 32], u32), Vec<u8>, Memory>,
+    #[serde(default)]
+    pub uploads: BTreeMap<u128, Upload>,
+    #[serde(skip, default = "get_upload_chunks_memory")]
+    pub upload_chunks: StableBTreeMap<(u128, u32), Vec<u8>, Memory>,
+    #[serde(default)]
+    pub next_upload_id: u128,
+    #[serde(default)]
+    pub media_quota: MediaQuota,
+    // bytes of committed media
+    #[serde(default)]
+    pub media_size: u64,
+    #[serde(default)]
+    pub metadata_schema: Option<MetadataSchema>,
+    // the metadata keys whose values are indexed
+    #[serde(default)]
+    pub trait_keys: BTreeSet<String>,
+    // (key, value, token id) for the live tokens
+    #[serde(skip, default = "get_trait_index_memory")]
+    pub trait_index: StableBTreeMap<(String, String, u128), (), Memory>,
+    #[serde(default)]
+    pub trait_counts: BTreeMap<(String, String), u64>,
+    // (token id, block index) -> owner after that block
+    #[serde(skip, default = "get_ownership_history_memory")]
+    pub ownership_history: StableBTreeMap<(u128, u128), UserAccount, Memory>,
+    // the first block the history covers, `None` until migrated
+    #[serde(default)]
+    pub ownership_history_start: Option<u128>,
+    // `None` until migrated, for collections created before the holder statistics
+    #[serde(default)]
+    pub holdings: Option<Holdings>,
+    // live tokens in the index, the supply rarity scores are computed against
+    #[serde(default)]
+    pub indexed_tokens: u64,
+    // path -> sha256 of the certified HTTP response, to rebuild the certified tree on upgrades
+    #[serde(skip, default = "get_response_hashes_memory")]
+    pub response_hashes: StableBTreeMap<String, [u8; 32], Memory>,
+    // the `http::RESPONSES_VERSION` the hashes were computed with
+    #[serde(default)]
+    pub responses_version: u32,
+    // the asset canister the launchpad deployed next to the collection
+    #[serde(default)]
+    pub assets_canister: Option<Principal>,
+    // the replay of the blocks imported so far, set while the collection is being recovered
+    #[serde(default)]
+    pub recovery: Option<ReplayedState>,
+    // the exported collection being imported, set while the collection is being migrated
+    #[serde(default)]
+    pub migration: Option<Migration>,
+    // set by `prepare_export`, the `export_*` queries being refused until then
+    #[serde(default)]
+    pub export_prepared: bool,
+    // archives that take no more blocks, such as those of the collection this one migrated from,
+    // which only accept blocks from the canister that created them
+    #[serde(default)]
+    pub closed_archives: BTreeSet<Principal>,
+    // sha256 of the wasm the launchpad was last authorized to upgrade the collection to
+    #[serde(default)]
+    pub authorized_upgrade: Option<Vec<u8>>,
+    // the account primary sales and minting approvals are made for
     pub minting_authority: Option<Account>,
+    #[serde(default)]
+    pub revenue_split: Vec<Stakeholder>,
+    #[serde(default)]
+    pub revenue_balances: BTreeMap<Account, BTreeMap<Principal, u128>>,
+    #[serde(skip, default = "get_revenue_history_memory")]
+    pub revenue_history: StableBTreeMap<u128, RevenueEntry, Memory>,
     pub icrc7_symbol: String,
     pub icrc7_name: String,
     pub icrc7_description: Option<String>,
@@ -122,12 +256,86 @@ pub struct State {
     pub archive_log_canister: Option<Principal>,
     pub sync_pending_txn_ids: Option<Vec<u128>>,
     pub archive_txn_count: u128,
+
+    #[serde(skip, default = "get_listings_memory")]
+    pub listings: StableBTreeMap<u128, Listing, Memory>,
+    #[serde(default)]
+    pub market_fee: Option<MarketFee>,
+    #[serde(default)]
+    pub failed_payouts: Vec<Payout>,
+    #[serde(skip)]
+    pub settling_tokens: BTreeSet<u128>,
+
+    #[serde(skip, default = "get_auctions_memory")]
+    pub auctions: StableBTreeMap<u128, Auction, Memory>,
+    #[serde(default)]
+    pub next_auction_id: u128,
+
+    #[serde(skip, default = "get_offers_memory")]
+    pub offers: StableBTreeMap<u128, Offer, Memory>,
+    #[serde(default)]
+    pub next_offer_id: u128,
+
+    #[serde(default)]
+    pub royalties: Vec<RoyaltyRecipient>,
+    #[serde(default)]
+    pub token_royalties: BTreeMap<u128, Vec<RoyaltyRecipient>>,
+
+    #[serde(default)]
+    pub transfer_policy: TransferPolicy,
+    #[serde(default)]
+    pub locked_tokens: BTreeSet<u128>,
+
+    #[serde(default)]
+    pub paused: bool,
+    #[serde(skip, default = "get_frozen_tokens_memory")]
+    pub frozen_tokens: StableBTreeMap<u128, FreezeRecord, Memory>,
+    #[serde(skip, default = "get_frozen_accounts_memory")]
+    pub frozen_accounts: StableBTreeMap<UserAccount, FreezeRecord, Memory>,
 }
 
 impl Default for State {
     fn default() -> Self {
         Self {
+            owner: None,
+            pending_owner: None,
+            roles: BTreeMap::new(),
+            multisig: None,
+            proposals: get_proposals_memory(),
+            open_proposals: BTreeSet::new(),
+            installer: None,
+            finalization: None,
+            series: get_series_memory(),
+            series_tokens: get_series_tokens_memory(),
+            rentals: BTreeMap::new(),
+            parents: BTreeMap::new(),
+            media: get_media_memory(),
+            media_chunks: get_media_chunks_memory(),
+            uploads: BTreeMap::new(),
+            upload_chunks: get_upload_chunks_memory(),
+            next_upload_id: 0,
+            media_quota: MediaQuota::default(),
+            media_size: 0,
+            metadata_schema: None,
+            trait_keys: BTreeSet::new(),
+            trait_index: get_trait_index_memory(),
+            trait_counts: BTreeMap::new(),
+            indexed_tokens: 0,
+            holdings: Some(Holdings::default()),
+            ownership_history: get_ownership_history_memory(),
+            ownership_history_start: Some(0),
+            response_hashes: get_response_hashes_memory(),
+            responses_version: RESPONSES_VERSION,
+            assets_canister: None,
+            recovery: None,
+            migration: None,
+            export_prepared: false,
+            closed_archives: BTreeSet::new(),
+            authorized_upgrade: None,
             minting_authority: None,
+            revenue_split: vec![],
+            revenue_balances: BTreeMap::new(),
+            revenue_history: get_revenue_history_memory(),
             icrc7_symbol: "ICRC7".into(),
             icrc7_name: "ICRC7 Collection".into(),
             icrc7_description: None,
@@ -153,6 +361,21 @@ impl Default for State {
             token_approvals: get_token_approvals_memory(),
             collection_approvals: get_collection_approvals_memory(),
             archive_ledger_info: ArchiveLedgerInfo::default(),
+            listings: get_listings_memory(),
+            market_fee: None,
+            failed_payouts: vec![],
+            settling_tokens: BTreeSet::new(),
+            auctions: get_auctions_memory(),
+            next_auction_id: 0,
+            offers: get_offers_memory(),
+            next_offer_id: 0,
+            royalties: vec![],
+            token_royalties: BTreeMap::new(),
+            transfer_policy: TransferPolicy::default(),
+            locked_tokens: BTreeSet::new(),
+            paused: false,
+            frozen_tokens: get_frozen_tokens_memory(),
+            frozen_accounts: get_frozen_accounts_memory(),
         }
     }
 }
@@ -218,11 +441,80 @@ impl State {
         self.icrc7_atomic_batch_transfers
     }
 
+    pub fn icrc7_collection_metadata(&self) -> Vec<(String, Value)> {
+        let mut metadata = vec![
+            ("icrc7:symbol".to_string(), Value::text(&self.icrc7_symbol)),
+            ("icrc7:name".to_string(), Value::text(&self.icrc7_name)),
+            (
+                "icrc7:total_supply".to_string(),
+                Value::Nat(self.icrc7_total_supply.into()),
+            ),
+        ];
+        if let Some(ref description) = self.icrc7_description {
+            metadata.push(("icrc7:description".into(), Value::text(description)));
+        }
+        if let Some(ref logo) = self.icrc7_logo {
+            metadata.push(("icrc7:logo".into(), Value::text(logo)));
+        }
+        if let Some(supply_cap) = self.icrc7_supply_cap {
+            metadata.push(("icrc7:supply_cap".into(), Value::Nat(supply_cap.into())));
+        }
+        if let Some(size) = self.icrc7_max_query_batch_size {
+            metadata.push(("icrc7:max_query_batch_size".into(), Value::Nat(size.into())));
+        }
+        if let Some(size) = self.icrc7_max_update_batch_size {
+            metadata.push(("icrc7:max_update_batch_size".into(), Value::Nat(size.into())));
+        }
+        if let Some(take) = self.icrc7_default_take_value {
+            metadata.push(("icrc7:default_take_value".into(), Value::Nat(take.into())));
+        }
+        if let Some(take) = self.icrc7_max_take_value {
+            metadata.push(("icrc7:max_take_value".into(), Value::Nat(take.into())));
+        }
+        if let Some(size) = self.icrc7_max_memo_size {
+            metadata.push(("icrc7:max_memo_size".into(), Value::Nat(size.into())));
+        }
+        if let Some(atomic) = self.icrc7_atomic_batch_transfers {
+            metadata.push(("icrc7:atomic_batch_transfers".into(), Value::text(atomic)));
+        }
+        if let Some(window) = self.tx_window {
+            metadata.push(("icrc7:tx_window".into(), Value::Nat(window.into())));
+        }
+        if let Some(drift) = self.permitted_drift {
+            metadata.push(("icrc7:permitted_drift".into(), Value::Nat(drift.into())));
+        }
+        metadata.push((
+            METADATA_TRANSFERABLE.into(),
+            Value::text(self.transfer_policy.as_str()),
+        ));
+        if let Some(assets_canister) = self.assets_canister {
+            metadata.push((METADATA_ASSETS_CANISTER.into(), Value::text(assets_canister)));
+            metadata.push((METADATA_ASSETS_URL.into(), Value::text(assets_url(assets_canister))));
+        }
+        if !self.royalties.is_empty() {
+            metadata.push((METADATA_ROYALTIES.into(), royalties_value(&self.royalties)));
+        }
+        metadata.push((METADATA_FINALIZED.into(), Value::text(self.is_finalized())));
+        if let Some(ref finalization) = self.finalization {
+            metadata.push((
+                METADATA_FINALIZED_BLOCK.into(),
+                Value::Nat(finalization.block_index.into()),
+            ));
+            metadata.push((METADATA_FROZEN.into(), Value::text(finalization.metadata_frozen)));
+            // neither the creator nor the launchpad can change the code once removed
+            metadata.push((
+                METADATA_LAUNCHPAD_REMOVED.into(),
+                Value::text(finalization.launchpad_removed),
+            ));
+        }
+        metadata
+    }
+
     pub fn icrc7_owner_of(&self, token_id: &[u128]) -> Vec<Option<Account>> {
         let mut res = vec![None; token_id.len()];
         for (index, id) in token_id.iter().enumerate() {
             if let Some(ref token) = self.tokens.get(id) {
-                res[index] = Some(token.token_owner);
+                res[index] = Some(self.root_owner_of(token));
             }
         }
         res
@@ -313,7 +605,7 @@ impl State {
         tx_id
     }
 
-    fn log_transaction(
+    pub(crate) fn log_transaction(
         &mut self,
         txn_type: TransactionType,
         at: u64,
@@ -329,6 +621,11 @@ impl State {
         //     self.txn_ledger.get(&(txn_id - 1))
         // };
 
+        let changed_token = changed_token(&txn_type);
+        if let Some((token_id, owner)) = owner_change(&txn_type) {
+            self.ownership_history
+                .insert((token_id, txn_id), UserAccount::new(owner));
+        }
         let mut txn = Transaction::new(txn_id, txn_type, at, memo);
         let phash = self.archive_ledger_info.latest_hash;
 
@@ -344,12 +641,9 @@ impl State {
         // set certified data
         TREE.with(|tree| {
             let mut tree = tree.borrow_mut();
-            tree.insert(
-                "last_block_index",
-                leaf_hash(&self.archive_ledger_info.last_index.to_le_bytes()),
-            );
-            tree.insert("last_block_hash", leaf_hash(&block_hash));
-            ic_cdk::api::set_certified_data(&tree.root_hash());
+            self.certify_tip(&mut tree);
+            self.certify_responses(&mut tree, changed_token.as_slice());
+            crate::utils::set_certified_data(&tree.root_hash());
         });
 
         if self.archive_ledger_info.local_ledger_size
@@ -361,11 +655,22 @@ impl State {
         txn_id
     }
 
+    // The ICRC-3 tip leaves of the certified tree
+    pub(crate) fn certify_tip(&self, tree: &mut RbTree<&'static str, CertifiedEntry>) {
+        if let Some(block_hash) = self.archive_ledger_info.latest_hash {
+            tree.insert(
+                "last_block_index",
+                CertifiedEntry::Leaf(leaf_hash(&self.archive_ledger_info.last_index.to_le_bytes())),
+            );
+            tree.insert("last_block_hash", CertifiedEntry::Leaf(leaf_hash(&block_hash)));
+        }
+    }
+
     fn get_current_txn_count(&self) -> u128 {
         self.txn_count - self.archive_txn_count
     }
 
-    fn get_current_take(&self, take: Option<u128>) -> u128 {
+    pub(crate) fn get_current_take(&self, take: Option<u128>) -> u128 {
         self.icrc7_max_take_value
             .map_or(self::State::DEFAULT_TAKE_VALUE, |max_take| {
                 take.map_or(max_take, |t| t.min(max_take))
@@ -389,7 +694,7 @@ impl State {
         false
     }
 
-    fn is_approved_by_token(
+    pub(crate) fn is_approved_by_token(
         &self,
         token_id: &u128,
         from: &Account,
@@ -413,10 +718,35 @@ impl State {
         false
     }
 
-    fn token_approvals_clean(&mut self, token_id: &u128) {
+    pub(crate) fn token_approvals_clean(&mut self, token_id: &u128) {
         self.token_approvals.remove(token_id);
     }
 
+    // Moves a token on behalf of the canister itself (escrows, settlements), logged as a transfer
+    pub(crate) fn move_token(&mut self, token_id: u128, from: Account, to: Account, at: u64) -> u128 {
+        let mut token = self.tokens.get(&token_id).unwrap();
+        token.transfer(to);
+        self.token_approvals_clean(&token_id);
+        self.tokens.insert(token_id, token);
+        self.token_moved(&token_id);
+        self.record_transfer(token_id, &from, &to);
+        self.log_transaction(
+            TransactionType::Transfer {
+                tid: token_id,
+                from,
+                to,
+            },
+            at,
+            None,
+        )
+    }
+
+    // Invalidates everything bound to the previous owner of a token that changed hands
+    pub(crate) fn token_moved(&mut self, token_id: &u128) {
+        self.token_approvals_clean(token_id);
+        self.listings.remove(token_id);
+    }
+
     fn mock_transfer(
         &self,
         current_time: &u64,
@@ -461,11 +791,22 @@ impl State {
         if arg.to == *caller {
             return Err(TransferError::InvalidRecipient);
         }
+        // the collection's own accounts are escrows and token accounts, reached only through
+        // the market and `equip`
+        if arg.to.owner == canister_id() {
+            return Err(TransferError::InvalidRecipient);
+        }
         let token = self.tokens.get(&arg.token_id).unwrap();
         // checking if the caller is authorized or is approve to make transaction
         if token.token_owner != *caller {
             return Err(TransferError::Unauthorized);
         }
+        if self.is_frozen(&arg.token_id, &[caller, &arg.to]) {
+            return Err(TransferError::Frozen);
+        }
+        if !self.is_transferable_by(&arg.token_id, caller) {
+            return Err(TransferError::NonTransferable);
+        }
         Ok(())
     }
 
@@ -499,7 +840,7 @@ impl State {
             }));
             return txn_results;
         }
-        let current_time = ic_cdk::api::time();
+        let current_time = time();
         for (index, arg) in args.iter_mut().enumerate() {
             let caller_account = account_transformer(Account {
                 owner: caller.clone(),
@@ -536,6 +877,8 @@ impl State {
             let mut token = self.tokens.get(&arg.token_id).unwrap();
             token.transfer(arg.to.clone());
             self.tokens.insert(arg.token_id, token);
+            self.token_moved(&arg.token_id);
+            self.record_transfer(arg.token_id, &caller_account, &arg.to);
             let txn_id = self.log_transaction(
                 TransactionType::Transfer {
                     tid: arg.token_id,
@@ -550,7 +893,24 @@ impl State {
         txn_results
     }
 
-    fn mock_mint(&self, caller: &Account, arg: &MintArg) -> Result<(), MintError> {
+    fn mock_mint(
+        &self,
+        caller: &Account,
+        arg: &MintArg,
+        edition: Option<Edition>,
+    ) -> Result<(), MintError> {
+        if self.is_finalized() {
+            return Err(MintError::GenericError {
+                error_code: 10,
+                message: "Collection Finalized".into(),
+            });
+        }
+        if self.migration.is_some() {
+            return Err(MintError::GenericError {
+                error_code: 14,
+                message: "Collection Being Migrated".into(),
+            });
+        }
         if let Some(cap) = self.icrc7_supply_cap {
             if cap == self.icrc7_total_supply {
                 return Err(MintError::SupplyCapReached);
@@ -562,7 +922,7 @@ impl State {
                 message: "Minting Authority Not Set".into(),
             });
         }
-        if Some(*caller) != self.minting_authority {
+        if !self.has_role(&caller.owner, Role::Minter) {
             return Err(MintError::Unauthorized);
         }
         if let Some(ref memo) = arg.memo {
@@ -576,6 +936,22 @@ impl State {
                 });
             }
         }
+        if let Some(ref royalties) = arg.royalties {
+            if let Err(message) = validate_royalties(royalties) {
+                return Err(MintError::GenericError {
+                    error_code: 8,
+                    message,
+                });
+            }
+        }
+        if self.check_media(&arg.meta).is_err() {
+            return Err(MintError::GenericError {
+                error_code: 13,
+                message: "Media Not Found".into(),
+            });
+        }
+        self.check_metadata_schema(&arg.meta, edition)
+            .map_err(|violations| MintError::InvalidMetadata { violations })?;
         if let Some(token_id) = arg.token_id {
             if token_id < self.next_token_id {
                 return Err(MintError::TokenIdMinimumLimit);
@@ -587,20 +963,101 @@ impl State {
         Ok(())
     }
 
-    pub fn mint(&mut self, caller: &Principal, mut arg: MintArg) -> MintResult {
+    pub fn update_token_metadata(
+        &mut self,
+        caller: &Principal,
+        arg: UpdateTokenMetadataArg,
+        at: u64,
+    ) -> UpdateTokenMetadataResult {
+        if self.is_metadata_frozen() {
+            return Err("The token metadata is frozen".into());
+        }
+        if self.migration.is_some() {
+            return Err("The collection is being migrated".into());
+        }
+        if let Some(ref memo) = arg.memo {
+            if memo.len() as u32
+                > self
+                    .icrc7_max_memo_size
+                    .unwrap_or(State::DEFAULT_MAX_MEMO_SIZE)
+            {
+                return Err("Exceeds Max Memo Length".into());
+            }
+        }
+        let mut token = self
+            .tokens
+            .get(&arg.token_id)
+            .ok_or_else(|| String::from("Non existing token id"))?;
+        self.check_media(&arg.meta)
+            .map_err(|_| String::from("The metadata references missing media"))?;
+        self.check_metadata_schema(&arg.meta, token.edition)
+            .map_err(|violations| format!("The metadata violates the schema: {violations:?}"))?;
+        self.acquire_media(&arg.meta);
+        self.release_media(&token.token_meta);
+        let old_meta = self.token_metadata_of(&token);
+        self.unindex_traits(arg.token_id, &old_meta);
+        token.token_meta = arg.meta.clone();
+        let new_meta = self.token_metadata_of(&token);
+        self.index_traits(arg.token_id, &new_meta);
+        self.tokens.insert(arg.token_id, token);
+        Ok(self.log_transaction(
+            TransactionType::Update {
+                tid: arg.token_id,
+                from: default_account(caller),
+                meta: arg.meta,
+            },
+            at,
+            arg.memo,
+        ))
+    }
+
+    pub fn mint(&mut self, caller: &Principal, arg: MintArg) -> MintResult {
+        if self.multisig.is_some() {
+            return Err(MintError::GenericError {
+                error_code: 9,
+                message: "Minting Requires A Multisig Proposal".into(),
+            });
+        }
         let caller = account_transformer(Account {
             owner: caller.clone(),
             subaccount: arg.from_subaccount,
         });
+        self.mint_as(caller, arg)
+    }
+
+    // Mints on behalf of `caller`, also used to execute the mint proposals
+    pub fn mint_as(&mut self, caller: Account, arg: MintArg) -> MintResult {
+        self.mint_token(caller, arg, None)
+    }
+
+    // Mints a token, an edition of a series when `edition` is set. The block records the
+    // merged metadata.
+    pub fn mint_token(
+        &mut self,
+        caller: Account,
+        mut arg: MintArg,
+        edition: Option<Edition>,
+    ) -> MintResult {
         arg.to = account_transformer(arg.to);
-        self.mock_mint(&caller, &arg)?;
+        self.mock_mint(&caller, &arg, edition)?;
+        self.acquire_media(&arg.meta);
         let token_id = arg.token_id.unwrap_or(self.next_token_id);
         let token = Icrc7Token::new(
             token_id,
             arg.to.clone(),
-            arg.meta.clone()
+            arg.meta,
+            edition,
         );
+        let meta = self.token_metadata_of(&token);
         self.tokens.insert(token_id, token);
+        self.index_traits(token_id, &meta);
+        self.record_mint(&arg.to);
+        if let Some(royalties) = arg.royalties {
+            self.token_royalties.insert(token_id, royalties);
+        }
+        if let Some(true) = arg.locked {
+            self.locked_tokens.insert(token_id);
+        }
         self.icrc7_total_supply += 1;
         self.next_token_id = token_id + 1;
 
@@ -609,9 +1066,9 @@ impl State {
                 tid: token_id,
                 from: caller,
                 to: arg.to,
-                meta: arg.meta,
+                meta,
             },
-            ic_cdk::api::time(),
+            time(),
             arg.memo,
         );
         Ok((txn_id, token_id))
@@ -636,6 +1093,15 @@ impl State {
                 if token.token_owner != *caller {
                     return Err(BurnError::Unauthorized);
                 }
+                if self.is_frozen(&arg.token_id, &[caller]) {
+                    return Err(BurnError::Frozen);
+                }
+                if self.parents.values().any(|parent_id| *parent_id == arg.token_id) {
+                    return Err(BurnError::GenericError {
+                        error_code: 4,
+                        message: "Token Has Equipped Tokens".into(),
+                    });
+                }
                 Ok(())
             }
         }
@@ -689,15 +1155,20 @@ impl State {
                 }
             }
             let mut token = self.tokens.get(&arg.token_id).unwrap();
+            self.release_media(&token.token_meta);
+            let meta = self.token_metadata_of(&token);
+            self.unindex_traits(arg.token_id, &meta);
             token.burn(burn_address.clone());
             self.tokens.insert(arg.token_id, token);
+            self.token_moved(&arg.token_id);
+            self.record_burn(&caller);
             let tid = self.log_transaction(
                 TransactionType::Burn {
                     tid: arg.token_id,
                     from: caller,
                     to: burn_address,
                 },
-                ic_cdk::api::time(),
+                time(),
                 arg.memo.clone(),
             );
             txn_results.insert(index, Some(Ok(tid)))
@@ -730,6 +1201,12 @@ impl State {
                 if token.token_owner != *caller {
                     return Err(ApproveTokenError::NonExistingTokenId);
                 }
+                if self.is_frozen(&arg.token_id, &[caller, &arg.approval_info.spender]) {
+                    return Err(ApproveTokenError::Frozen);
+                }
+                if !self.is_transferable_by(&arg.token_id, &arg.approval_info.spender) {
+                    return Err(ApproveTokenError::NonTransferable);
+                }
                 Ok(())
             }
         }
@@ -798,6 +1275,7 @@ impl State {
                 }
                 Some(mut token_approval) => {
                     token_approval.approve(caller, arg.approval_info.clone());
+                    self.token_approvals.insert(arg.token_id, token_approval);
                 }
             }
 
@@ -808,7 +1286,7 @@ impl State {
                     to: arg.approval_info.spender,
                     exp_sec: arg.approval_info.expires_at,
                 },
-                ic_cdk::api::time(),
+                time(),
                 arg.approval_info.memo.clone(),
             );
             txn_results.insert(index, Some(Ok(tid)))
@@ -816,6 +1294,61 @@ impl State {
         txn_results
     }
 
+    // Grants a token-level approval on behalf of `owner` without the batch checks of `approve`
+    pub(crate) fn approve_token_internal(
+        &mut self,
+        owner: Account,
+        token_id: u128,
+        approval_info: ApprovalInfo,
+        at: u64,
+    ) -> u128 {
+        let spender = approval_info.spender;
+        let expires_at = approval_info.expires_at;
+        let memo = approval_info.memo.clone();
+        let token_approval = match self.token_approvals.get(&token_id) {
+            None => TokenApprovalInfo::new(owner, approval_info),
+            Some(mut token_approval) => {
+                token_approval.approve(owner, approval_info);
+                token_approval
+            }
+        };
+        self.token_approvals.insert(token_id, token_approval);
+
+        self.log_transaction(
+            TransactionType::Approval {
+                tid: token_id,
+                from: owner,
+                to: spender,
+                exp_sec: expires_at,
+            },
+            at,
+            memo,
+        )
+    }
+
+    pub(crate) fn revoke_token_internal(
+        &mut self,
+        owner: Account,
+        token_id: u128,
+        spender: Account,
+        at: u64,
+    ) -> u128 {
+        if let Some(mut token_approval) = self.token_approvals.get(&token_id) {
+            token_approval.remove_approve(owner, Some(spender));
+            self.token_approvals.insert(token_id, token_approval);
+        }
+
+        self.log_transaction(
+            TransactionType::Revoke {
+                tid: token_id,
+                from: owner,
+                to: Some(spender),
+            },
+            at,
+            None,
+        )
+    }
+
     fn mock_collection_approve(
         &self,
         caller: &Account,
@@ -825,6 +1358,12 @@ impl State {
         if arg.approval_info.spender == *caller {
             return Err(ApproveCollectionError::InvalidSpender);
         };
+        if self.is_any_account_frozen(&[caller, &arg.approval_info.spender]) {
+            return Err(ApproveCollectionError::Frozen);
+        }
+        if !self.policy_allows(&arg.approval_info.spender) {
+            return Err(ApproveCollectionError::NonTransferable);
+        }
         if let Some(expires_at) = arg.approval_info.expires_at {
             if expires_at < *current_time {
                 return Err(ApproveCollectionError::TooOld);
@@ -867,7 +1406,7 @@ impl State {
         }
 
         let mut txn_results: Vec<Option<ApproveCollectionResult>> = vec![None; args.len()];
-        let current_time = ic_cdk::api::time();
+        let current_time = time();
 
         for (index, arg) in args.iter_mut().enumerate() {
             let caller = account_transformer(Account {
@@ -915,6 +1454,8 @@ impl State {
                 Some(mut collection_approval) => {
                     collection_approval
                         .approve(arg.approval_info.spender, arg.approval_info.clone());
+                    self.collection_approvals
+                        .insert(user_account, collection_approval);
                 }
             }
 
@@ -924,7 +1465,7 @@ impl State {
                     to: arg.approval_info.spender,
                     exp_sec: arg.approval_info.expires_at,
                 },
-                ic_cdk::api::time(),
+                time(),
                 arg.approval_info.memo.clone(),
             );
             txn_results.insert(index, Some(Ok(tid)))
@@ -1032,6 +1573,7 @@ impl State {
                 }
                 Some(mut token_approval) => {
                     token_approval.remove_approve(caller, arg.spender);
+                    self.token_approvals.insert(arg.token_id, token_approval);
                 }
             }
 
@@ -1041,7 +1583,7 @@ impl State {
                     from: caller,
                     to: arg.spender,
                 },
-                ic_cdk::api::time(),
+                time(),
                 arg.memo.clone(),
             );
             txn_results.insert(index, Some(Ok(tid)))
@@ -1110,7 +1652,7 @@ impl State {
         }
 
         let mut txn_results: Vec<Option<RevokeCollectionApprovalResult>> = vec![None; args.len()];
-        let current_time = ic_cdk::api::time();
+        let current_time = time();
 
         for (index, arg) in args.iter_mut().enumerate() {
             let caller = account_transformer(Account {
@@ -1154,6 +1696,8 @@ impl State {
                     }
                     Some(spender) => {
                         collection_approval.remove_approve(spender);
+                        self.collection_approvals
+                            .insert(user_account.clone(), collection_approval);
                     }
                 },
             }
@@ -1163,7 +1707,7 @@ impl State {
                     from: caller,
                     to: arg.spender,
                 },
-                ic_cdk::api::time(),
+                time(),
                 arg.memo.clone(),
             );
             txn_results.insert(index, Some(Ok(tid)))
@@ -1183,6 +1727,15 @@ impl State {
                 message: "Spender cannot be caller".into(),
             });
         }
+        if arg.to.owner == canister_id() {
+            return Err(TransferFromError::InvalidRecipient);
+        }
+        if self.is_frozen(&arg.token_id, &[caller, &arg.from, &arg.to]) {
+            return Err(TransferFromError::Frozen);
+        }
+        if !self.is_transferable_by(&arg.token_id, caller) {
+            return Err(TransferFromError::NonTransferable);
+        }
 
         if let Some(time) = arg.created_at_time {
             let allowed_past_time = *current_time
@@ -1256,7 +1809,7 @@ impl State {
         }
 
         let mut txn_results: Vec<Option<TransferFromResult>> = vec![None; args.len()];
-        let current_time = ic_cdk::api::time();
+        let current_time = time();
 
         for (index, arg) in args.iter_mut().enumerate() {
             let caller = account_transformer(Account {
@@ -1295,6 +1848,8 @@ impl State {
             token.transfer(arg.to.clone());
             self.token_approvals_clean(&arg.token_id);
             self.tokens.insert(arg.token_id, token);
+            self.token_moved(&arg.token_id);
+            self.record_transfer(arg.token_id, &arg.from, &arg.to);
             let txn_id = self.log_transaction(
                 TransactionType::TransferFrom {
                     tid: arg.token_id,
@@ -1388,7 +1943,7 @@ impl State {
         }
 
         let caller = ic_cdk::caller();
-        let current_time = ic_cdk::api::time();
+        let current_time = time();
 
         if caller == Principal::anonymous() {
             return vec![false; args.len()];
@@ -1428,7 +1983,7 @@ impl State {
         let mut metadata_list = vec![None; token_ids.len()];
         for (index, tid) in token_ids.iter().enumerate() {
             if let Some(ref token) = self.tokens.get(tid) {
-                metadata_list[index] = Some(token.token_metadata());
+                metadata_list[index] = Some(self.token_metadata_of(token));
             }
         }
         metadata_list
@@ -1438,7 +1993,7 @@ impl State {
         let mut count_list = vec![0; accounts.len()];
         accounts.iter().enumerate().for_each(|(index, account)| {
             self.tokens.iter().for_each(|(_id, ref token)| {
-                if token.token_owner == *account {
+                if self.root_owner_of(token) == *account {
                     let current_count = count_list[index];
                     count_list[index] = current_count + 1;
                 }
@@ -1484,7 +2039,7 @@ impl State {
         }
         let mut owned_tokens = vec![];
         for (id, token) in self.tokens.iter() {
-            if token.token_owner == account {
+            if self.root_owner_of(&token) == account {
                 owned_tokens.push(id);
             }
         }
@@ -1632,7 +2187,7 @@ impl State {
                                 }],
                                 callback: QueryTransactionsFn {
                                     canister_id: *key,
-                                    method: "get_transactions".to_string(),
+                                    method: "icrc3_get_blocks".to_string(),
                                     _marker: std::marker::PhantomData,
                                 },
                             },
@@ -1756,6 +2311,17 @@ impl State {
         return to_archive;
     }
 
+    // The archive the next blocks are appended to: the one holding the latest blocks, unless
+    // it is closed
+    pub fn writable_archive(&self) -> Option<(Principal, TransactionRange)> {
+        self.archive_ledger_info
+            .archives
+            .iter()
+            .max_by_key(|(_, range)| range.start)
+            .filter(|(canister_id, _)| !self.closed_archives.contains(canister_id))
+            .map(|(canister_id, range)| (*canister_id, range.clone()))
+    }
+
     pub fn add_archive(&mut self, canister_id: Principal, range: TransactionRange) -> bool {
         self.archive_ledger_info.archives.insert(canister_id, range);
         return true;
@@ -1765,7 +2331,7 @@ impl State {
 thread_local! {
     pub static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
     pub static STATE: RefCell<State> = RefCell::default();
-    pub static TREE: RefCell<RbTree<&'static str, Hash>> = RefCell::new(RbTree::new());
+    pub static TREE: RefCell<RbTree<&'static str, CertifiedEntry>> = const { RefCell::new(RbTree::new()) };
     pub static TIMER_IDS: RefCell<Vec<TimerId>> = RefCell::new(Vec::new());
 }
 
@@ -1835,7 +2401,7 @@ async fn clean_local_ledger_task() {
 
     let mut is_recall_at_end = false;
 
-    let archive_count = STATE.with(|s| s.borrow().archive_ledger_info.archives.len());
+    let writable_archive = STATE.with(|s| s.borrow().writable_archive());
 
     if txn_ledger_size < max_active_records as u64 {
         ic_cdk::println!("clean_local_ledger_task: txn_ledger_size < max_active_records, don't clean if not necessary");
@@ -1853,12 +2419,12 @@ async fn clean_local_ledger_task() {
     let mut last_archive: Option<(Principal, TransactionRange)> = None;
     let mut capacity: u128 = 0;
 
-    if archive_count == 0 {
+    if writable_archive.is_none() {
         ic_cdk::println!("clean_local_ledger_task: create a new archive canister");
         let create_args: ArchiveCreateArgs = ArchiveCreateArgs {
             max_pages: max_archive_pages,
             max_records: max_active_records,
-            first_index: 0,
+            first_index: local_first_index,
             controllers: archive_controllers,
         };
         // ic_cdk::println!("local_cycles: {}", local_cycles);
@@ -1870,7 +2436,7 @@ async fn clean_local_ledger_task() {
             match archive_canister {
                 Ok(canister_id) => {
                     let range = TransactionRange {
-                        start: 0,
+                        start: local_first_index,
                         length: 0,
                     };
                     STATE.with(|s: &RefCell<State>| {
@@ -1894,16 +2460,7 @@ async fn clean_local_ledger_task() {
             return;
         }
     } else {
-        let current_last_archive = STATE.with(|s| {
-            s.borrow()
-                .archive_ledger_info
-                .archives
-                .clone()
-                .into_iter()
-                .last()
-        });
-
-        if let Some(current_last_archive) = current_last_archive {
+        if let Some(current_last_archive) = writable_archive {
             if current_last_archive.1.length >= max_records_in_archive_instance {
                 ic_cdk::println!(
                     "clean_local_ledger_task: old archive is full, create a new archive canister"
@@ -2018,3 +2575,60 @@ async fn clean_local_ledger_task() {
         set_clean_up_timer()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::tests::account;
+
+    #[test]
+    fn revoking_one_spender_keeps_the_others() {
+        let (alice, bob, carol) = (default_account(&account(1).owner), account(2), account(3));
+        let mut state = State {
+            icrc7_max_update_batch_size: Some(10),
+            ..Default::default()
+        };
+        let token = Icrc7Token {
+            token_id: 1,
+            token_owner: alice,
+            token_meta: Default::default(),
+            edition: None,
+            token_user: None,
+        };
+        state.tokens.insert(1, token);
+        for spender in [bob, carol] {
+            let approval_info = ApprovalInfo::new(None, spender, None, None, None);
+            let arg = ApproveTokenArg {
+                token_id: 1,
+                approval_info: approval_info.clone(),
+            };
+            assert!(matches!(state.approve(&alice.owner, vec![arg])[0], Some(Ok(_))));
+            let arg = ApproveCollectionArg { approval_info };
+            assert!(matches!(state.collection_approve(&alice.owner, vec![arg])[0], Some(Ok(_))));
+        }
+        assert!(state.is_approved_by_token(&1, &alice, &bob, 0));
+        assert!(state.is_approved_by_collection(&alice, &carol, 0));
+
+        let arg = RevokeTokenApprovalArg {
+            token_id: 1,
+            from_subaccount: None,
+            spender: Some(bob),
+            memo: None,
+            created_at_time: None,
+        };
+        assert!(matches!(state.revoke_approve(&alice.owner, vec![arg])[0], Some(Ok(_))));
+        let arg = RevokeCollectionApprovalArg {
+            from_subaccount: None,
+            spender: Some(carol),
+            memo: None,
+            created_at_time: None,
+        };
+        let res = state.revoke_collection_approve(&alice.owner, vec![arg]);
+        assert!(matches!(res[0], Some(Ok(_))));
+
+        assert!(!state.is_approved_by_token(&1, &alice, &bob, 0));
+        assert!(state.is_approved_by_token(&1, &alice, &carol, 0));
+        assert!(state.is_approved_by_collection(&alice, &bob, 0));
+        assert!(!state.is_approved_by_collection(&alice, &carol, 0));
+    }
+}
@@ -0,0 +1,133 @@
+use candid::Principal;
+use icrc_ledger_types::icrc1::{account::Account, transfer::TransferArg};
+use icrc7_types::{
+    errors::TokenAccountError,
+    token_account_types::{TokenBalanceArg, TokenWithdrawArg, TokenWithdrawResult},
+};
+use crate::{
+    ledger::{IcrcLedger, Ledger},
+    state::{State, STATE},
+    utils::{account_transformer, token_account, token_subaccount},
+};
+
+impl State {
+    // Only the current owner of a token moves what its account holds
+    pub fn check_token_withdrawal(
+        &self,
+        owner: &Account,
+        token_id: u128,
+    ) -> Result<(), TokenAccountError> {
+        let token = self
+            .tokens
+            .get(&token_id)
+            .ok_or(TokenAccountError::NonExistingTokenId)?;
+        if token.token_owner != *owner {
+            return Err(TokenAccountError::Unauthorized);
+        }
+        if self.is_frozen(&token_id, &[owner]) {
+            return Err(TokenAccountError::Frozen);
+        }
+        if self.listings.contains_key(&token_id) || self.settling_tokens.contains(&token_id) {
+            return Err(TokenAccountError::Listed);
+        }
+        Ok(())
+    }
+}
+
+pub async fn withdraw_from_token(caller: Principal, arg: TokenWithdrawArg) -> TokenWithdrawResult {
+    let owner = account_transformer(Account {
+        owner: caller,
+        subaccount: arg.from_subaccount,
+    });
+    STATE.with_borrow(|s| s.check_token_withdrawal(&owner, arg.token_id))?;
+    transfer_from_token(&IcrcLedger(arg.ledger), arg).await
+}
+
+// Forwards an `icrc1_transfer` out of the token account, the ledger charging its fee
+pub(crate) async fn transfer_from_token<L: Ledger>(
+    ledger: &L,
+    arg: TokenWithdrawArg,
+) -> TokenWithdrawResult {
+    if arg.amount == 0 {
+        return Err(TokenAccountError::GenericError {
+            error_code: 1,
+            message: "Amount must be greater than zero".into(),
+        });
+    }
+    ledger
+        .transfer(TransferArg {
+            from_subaccount: Some(token_subaccount(arg.token_id)),
+            to: arg.to,
+            fee: None,
+            created_at_time: None,
+            memo: arg.memo.map(Into::into),
+            amount: arg.amount.into(),
+        })
+        .await
+        .map_err(|message| TokenAccountError::TransferFailed { message })
+}
+
+pub async fn token_balance_of(arg: TokenBalanceArg) -> Result<u128, String> {
+    if STATE.with_borrow(|s| !s.tokens.contains_key(&arg.token_id)) {
+        return Err("Non existing token id".into());
+    }
+    IcrcLedger(arg.ledger).balance_of(token_account(arg.token_id)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        market::tests::{account, block_on, LocalLedger},
+        state::Icrc7Token,
+    };
+
+    #[test]
+    fn token_accounts_follow_the_token_owner() {
+        let canister = Principal::from_slice(&[9]);
+        let ledger = LocalLedger::new(canister, 10);
+        let held = Account {
+            owner: canister,
+            subaccount: Some(token_subaccount(1)),
+        };
+        ledger.mint(held, 1_000);
+        assert_ne!(token_subaccount(1), token_subaccount(2));
+
+        let mut state = State::default();
+        let mut token = Icrc7Token {
+            token_id: 1,
+            token_owner: account(1),
+            token_meta: Default::default(),
+            edition: None,
+            token_user: None,
+        };
+        state.tokens.insert(1, token.clone());
+        assert_eq!(state.check_token_withdrawal(&account(1), 1), Ok(()));
+        assert_eq!(
+            state.check_token_withdrawal(&account(2), 1),
+            Err(TokenAccountError::Unauthorized)
+        );
+
+        let withdrawal = |amount| TokenWithdrawArg {
+            from_subaccount: None,
+            token_id: 1,
+            ledger: canister,
+            to: account(1),
+            amount,
+            memo: None,
+        };
+        assert_eq!(block_on(transfer_from_token(&ledger, withdrawal(400))), Ok(0));
+        assert_eq!(block_on(ledger.balance_of(held)), Ok(590));
+        assert_eq!(ledger.balance(&account(1)), 400);
+        assert!(block_on(transfer_from_token(&ledger, withdrawal(590))).is_err());
+
+        // selling the token hands over what it holds
+        token.transfer(account(2));
+        state.tokens.insert(1, token);
+        assert_eq!(
+            state.check_token_withdrawal(&account(1), 1),
+            Err(TokenAccountError::Unauthorized)
+        );
+        assert_eq!(state.check_token_withdrawal(&account(2), 1), Ok(()));
+    }
+}
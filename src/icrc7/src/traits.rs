@@ -0,0 +1,221 @@
+use std::collections::BTreeSet;
+use icrc7_types::{
+    icrc7_types::Icrc7TokenMetadata,
+    trait_types::{trait_value, TokensByTraitArg, Trait, TraitCount, TraitFilter},
+};
+use crate::{state::State, utils::burn_subaccount};
+
+impl State {
+    pub const MAX_TRAIT_KEYS: usize = 32;
+
+    // Replaces the indexed keys and rebuilds the index from every live token, which for large
+    // collections is best done before minting
+    pub fn set_trait_keys(&mut self, keys: Vec<String>) -> Result<(), String> {
+        if self.is_finalized() {
+            return Err("The collection is finalized".into());
+        }
+        if keys.len() > State::MAX_TRAIT_KEYS {
+            return Err(format!("At most {} trait keys", State::MAX_TRAIT_KEYS));
+        }
+        if keys.iter().any(|key| key.trim().is_empty()) {
+            return Err("Trait keys cannot be empty".into());
+        }
+        let entries: Vec<(String, String, u128)> =
+            self.trait_index.iter().map(|(entry, _)| entry).collect();
+        for entry in entries {
+            self.trait_index.remove(&entry);
+        }
+        self.trait_counts.clear();
+        self.indexed_tokens = 0;
+        self.trait_keys = keys.into_iter().collect();
+
+        let tokens: Vec<_> = self
+            .tokens
+            .iter()
+            .filter(|(_, token)| token.token_owner.subaccount != Some(burn_subaccount()))
+            .map(|(token_id, token)| (token_id, self.token_metadata_of(&token)))
+            .collect();
+        for (token_id, meta) in tokens {
+            self.index_traits(token_id, &meta);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn index_traits(&mut self, token_id: u128, meta: &Icrc7TokenMetadata) {
+        for (key, value) in self.indexed_traits(meta) {
+            self.trait_index.insert((key.clone(), value.clone(), token_id), ());
+            *self.trait_counts.entry((key, value)).or_default() += 1;
+        }
+        self.indexed_tokens += 1;
+    }
+
+    pub(crate) fn unindex_traits(&mut self, token_id: u128, meta: &Icrc7TokenMetadata) {
+        for (key, value) in self.indexed_traits(meta) {
+            self.trait_index.remove(&(key.clone(), value.clone(), token_id));
+            let entry = (key, value);
+            if let Some(count) = self.trait_counts.get_mut(&entry) {
+                *count -= 1;
+                if *count == 0 {
+                    self.trait_counts.remove(&entry);
+                }
+            }
+        }
+        self.indexed_tokens = self.indexed_tokens.saturating_sub(1);
+    }
+
+    fn indexed_traits(&self, meta: &Icrc7TokenMetadata) -> Vec<(String, String)> {
+        self.trait_keys
+            .iter()
+            .filter_map(|key| Some((key.clone(), trait_value(meta.get(key)?)?)))
+            .collect()
+    }
+
+    fn tokens_with(&self, filter: &TraitFilter) -> BTreeSet<u128> {
+        match filter {
+            TraitFilter::Trait(Trait { key, value }) => self
+                .trait_index
+                .range((key.clone(), value.clone(), 0)..=(key.clone(), value.clone(), u128::MAX))
+                .map(|((_, _, token_id), _)| token_id)
+                .collect(),
+            TraitFilter::And(filters) => {
+                let mut sets = filters.iter().map(|filter| self.tokens_with(filter));
+                let first = sets.next().unwrap_or_default();
+                sets.fold(first, |acc, set| acc.intersection(&set).copied().collect())
+            }
+            TraitFilter::Or(filters) => filters
+                .iter()
+                .flat_map(|filter| self.tokens_with(filter))
+                .collect(),
+        }
+    }
+
+    // The matching token ids in ascending order, paginated like `icrc7_tokens`
+    pub fn tokens_by_trait(&self, arg: TokensByTraitArg) -> Vec<u128> {
+        let take = self.get_current_take(arg.take) as usize;
+        self.tokens_with(&arg.filter)
+            .into_iter()
+            .filter(|token_id| arg.prev.is_none_or(|prev| *token_id > prev))
+            .take(take)
+            .collect()
+    }
+
+    // How many live tokens have each value, of one key or of all the indexed keys
+    pub fn trait_counts(&self, key: Option<String>) -> Vec<TraitCount> {
+        self.trait_counts
+            .iter()
+            .filter(|((trait_key, _), _)| key.as_ref().is_none_or(|key| key == trait_key))
+            .map(|((key, value), count)| TraitCount {
+                key: key.clone(),
+                value: value.clone(),
+                count: *count,
+            })
+            .collect()
+    }
+
+    // The statistical rarity: the sum, over the indexed traits of a token, of the supply
+    // divided by the number of tokens sharing the trait
+    pub fn rarity_scores(&self, token_ids: &[u128]) -> Vec<Option<f64>> {
+        token_ids
+            .iter()
+            .map(|token_id| {
+                let token = self.tokens.get(token_id)?;
+                if token.token_owner.subaccount == Some(burn_subaccount()) {
+                    return None;
+                }
+                let score = self
+                    .indexed_traits(&self.token_metadata_of(&token))
+                    .into_iter()
+                    .filter_map(|entry| self.trait_counts.get(&entry))
+                    .map(|count| self.indexed_tokens as f64 / *count as f64)
+                    .sum();
+                Some(score)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{market::tests::account, state::Icrc7Token};
+    use icrc_ledger_types::icrc::generic_value::Value;
+
+    #[test]
+    fn tokens_are_found_by_trait() {
+        let mut state = State {
+            icrc7_max_take_value: Some(100),
+            ..Default::default()
+        };
+        let traits = [("Gold", 1u32), ("Gold", 2), ("Blue", 1), ("Gold", 1)];
+        for (index, (background, level)) in traits.into_iter().enumerate() {
+            let token_id = index as u128 + 1;
+            state.tokens.insert(
+                token_id,
+                Icrc7Token {
+                    token_id,
+                    token_owner: account(1),
+                    token_meta: Icrc7TokenMetadata::from([
+                        ("Background".to_string(), Value::text(background)),
+                        ("Level".to_string(), Value::Nat(level.into())),
+                        ("image".to_string(), Value::text("https://example.com")),
+                    ]),
+                    edition: None,
+                    token_user: None,
+                },
+            );
+        }
+        assert!(state.set_trait_keys(vec![" ".into()]).is_err());
+        state
+            .set_trait_keys(vec!["Background".into(), "Level".into()])
+            .unwrap();
+        let has = |key: &str, value: &str| {
+            TraitFilter::Trait(Trait {
+                key: key.into(),
+                value: value.into(),
+            })
+        };
+        let query = |state: &State, filter, prev| {
+            state.tokens_by_trait(TokensByTraitArg {
+                filter,
+                prev,
+                take: Some(2),
+            })
+        };
+
+        assert_eq!(query(&state, has("Background", "Gold"), None), vec![1, 2]);
+        assert_eq!(query(&state, has("Background", "Gold"), Some(2)), vec![4]);
+        let gold_level_1 = TraitFilter::And(vec![has("Background", "Gold"), has("Level", "1")]);
+        assert_eq!(query(&state, gold_level_1, None), vec![1, 4]);
+        let blue_or_level_2 = TraitFilter::Or(vec![has("Background", "Blue"), has("Level", "2")]);
+        assert_eq!(query(&state, blue_or_level_2, None), vec![2, 3]);
+        assert!(query(&state, has("image", "https://example.com"), None).is_empty());
+
+        assert_eq!(
+            state.trait_counts(Some("Background".into())),
+            vec![
+                TraitCount {
+                    key: "Background".into(),
+                    value: "Blue".into(),
+                    count: 1,
+                },
+                TraitCount {
+                    key: "Background".into(),
+                    value: "Gold".into(),
+                    count: 3,
+                },
+            ]
+        );
+        // 4/3 + 4/3 for the common token, 4/1 + 4/3 for the only blue one
+        let scores = state.rarity_scores(&[1, 3, 9]);
+        assert!((scores[0].unwrap() - 8.0 / 3.0).abs() < 1e-9);
+        assert!((scores[1].unwrap() - 16.0 / 3.0).abs() < 1e-9);
+        assert_eq!(scores[2], None);
+
+        // burning takes the token out of the index
+        let token = state.tokens.get(&1).unwrap();
+        state.unindex_traits(1, &token.token_meta);
+        assert_eq!(query(&state, has("Background", "Gold"), None), vec![2, 4]);
+        assert_eq!(state.trait_counts.get(&("Level".into(), "1".into())), Some(&2));
+        assert_eq!(state.indexed_tokens, 3);
+    }
+}
@@ -0,0 +1,93 @@
+use icrc_ledger_types::icrc1::account::Account;
+use icrc7_types::icrc7_types::TransferPolicy;
+use crate::state::State;
+
+impl State {
+    // Whether `initiator` (the owner, or the spender of an approval) may move the token
+    pub fn is_transferable_by(&self, token_id: &u128, initiator: &Account) -> bool {
+        if self.locked_tokens.contains(token_id) {
+            return false;
+        }
+        self.policy_allows(initiator)
+    }
+
+    pub fn policy_allows(&self, initiator: &Account) -> bool {
+        match self.transfer_policy {
+            TransferPolicy::Always => true,
+            TransferPolicy::Never => false,
+            TransferPolicy::MintingAuthorityOnly => Some(*initiator) == self.minting_authority,
+        }
+    }
+
+    pub fn lock_tokens(&mut self, token_ids: Vec<u128>) -> Vec<bool> {
+        token_ids
+            .into_iter()
+            .map(|token_id| {
+                if !self.tokens.contains_key(&token_id) {
+                    return false;
+                }
+                self.locked_tokens.insert(token_id);
+                // a locked token can neither stay listed nor keep its approvals
+                self.token_approvals_clean(&token_id);
+                self.listings.remove(&token_id);
+                true
+            })
+            .collect()
+    }
+
+    pub fn unlock_tokens(&mut self, token_ids: Vec<u128>) -> Vec<bool> {
+        token_ids
+            .into_iter()
+            .map(|token_id| self.locked_tokens.remove(&token_id))
+            .collect()
+    }
+
+    pub fn icrc7_locked(&self, token_ids: &[u128]) -> Vec<Option<bool>> {
+        token_ids
+            .iter()
+            .map(|token_id| {
+                self.tokens
+                    .contains_key(token_id)
+                    .then(|| self.locked_tokens.contains(token_id))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{market::tests::account, state::Icrc7Token};
+
+    #[test]
+    fn policy_and_locks_restrict_transfers() {
+        let mut state = State::default();
+        let authority = account(1);
+        let holder = account(2);
+        state.minting_authority = Some(authority);
+        state.tokens.insert(
+            7,
+            Icrc7Token {
+                token_id: 7,
+                token_owner: holder,
+                token_meta: Default::default(),
+                edition: None,
+                token_user: None,
+            },
+        );
+
+        assert!(state.is_transferable_by(&7, &holder));
+        state.transfer_policy = TransferPolicy::MintingAuthorityOnly;
+        assert!(!state.is_transferable_by(&7, &holder));
+        assert!(state.is_transferable_by(&7, &authority));
+        state.transfer_policy = TransferPolicy::Never;
+        assert!(!state.is_transferable_by(&7, &authority));
+
+        state.transfer_policy = TransferPolicy::Always;
+        assert_eq!(state.lock_tokens(vec![7, 8]), vec![true, false]);
+        assert!(!state.is_transferable_by(&7, &authority));
+        assert_eq!(state.icrc7_locked(&[7, 8]), vec![Some(true), None]);
+        assert_eq!(state.unlock_tokens(vec![7]), vec![true]);
+        assert!(state.is_transferable_by(&7, &holder));
+    }
+}
@@ -1,22 +1,52 @@
 use candid::Principal;
 use ic_cdk_macros::update;
+use serde_bytes::ByteBuf;
+use icrc_ledger_types::icrc1::account::Account;
 use icrc7_types::{
     icrc37_types::{
         ApprovalInfo, ApproveTokenArg, 
         TransferFromArg, TransferFromResult
     }, 
     icrc7_types::{
-        BurnArg, BurnResult, MintArg, MintResult, 
-        TransferArg, TransferResult
+        BurnArg, BurnResult, FinalizeArg, FinalizeResult, MintArg, MintResult, 
+        TransferArg, TransferResult, UpdateCollectionConfigArg, UpdateCollectionConfigResult,
+        UpdateTokenMetadataArg, UpdateTokenMetadataResult,
     },
-    errors::TransferFromError, 
+    compliance_types::{ComplianceResult, FreezeArg, FreezeRecord, PauseArg},
+    errors::{MediaError, MultisigError, TransferFromError},
+    multisig_types::{ApproveProposalResult, MultisigConfig, ProposalAction, ProposeResult},
+    series_types::{CreateSeriesArg, CreateSeriesResult, MintEditionArg},
+    token_account_types::{TokenWithdrawArg, TokenWithdrawResult},
+    composable_types::{EquipArg, EquipResult, UnequipArg},
+    media_types::{
+        CommitUploadResult, CreateUploadArg, CreateUploadResult, MediaAsset, MediaQuota,
+        UploadChunkArg, UploadChunkResult,
+    },
+    rbac_types::{RoleArg, RoleResult},
+    rental_types::{SetUserArg, SetUserResult},
+    schema_types::MetadataSchema,
+    icrc3_types::QueryBlock,
+    replay_types::ReplayReport,
+    migration_types::{
+        ArchivesExport, CollectionApprovalsExport, ConfigExport, TokenApprovalsExport, TokenExport,
+    },
+    revenue_types::{DepositRevenueArg, DepositRevenueResult, Stakeholder, WithdrawArg, WithdrawResult},
 };
 use crate::{
-    guards::authenticated_guard, 
+    guards::{
+        admin_guard, admin_operation_guard, authenticated_guard, installer_guard,
+        metadata_editor_guard, minter_guard, minter_operation_guard, not_paused_guard, owner_guard,
+        pauser_guard, signer_guard, treasurer_guard,
+    },
+    finalize,
+    http::{certify_all, certify_collection},
+    ledger::IcrcLedger,
+    migration, multisig, recovery, rental, revenue, token_account,
     state::STATE, 
+    utils::default_account,
 };
 
-#[update(guard = "authenticated_guard")]
+#[update(guard = "not_paused_guard")]
 pub fn icrc7_transfer(
     args: Vec<TransferArg>
 ) -> Vec<Option<TransferResult>> {
@@ -24,7 +54,7 @@ pub fn icrc7_transfer(
     STATE.with(|s| s.borrow_mut().icrc7_transfer(&caller, args))
 }
 
-#[update(guard = "authenticated_guard")]
+#[update(guard = "not_paused_guard")]
 pub fn mint_and_grant_transfer_approval(
     arg: MintArg
 ) -> MintResult {
@@ -45,7 +75,24 @@ pub fn mint_and_grant_transfer_approval(
     Ok((tx_id, token_id))
 }
 
-#[update(guard = "authenticated_guard")]
+// Creates a series whose master metadata is shared by all its editions.
+#[update(guard = "minter_guard")]
+pub fn create_series(arg: CreateSeriesArg) -> CreateSeriesResult {
+    STATE.with(|s| s.borrow_mut().create_series(arg, ic_cdk::api::time()))
+}
+
+// Mints the next edition of a series, granting the minting authority a transfer approval
+// like `mint_and_grant_transfer_approval` does.
+#[update(guard = "not_paused_guard")]
+pub fn mint_edition(arg: MintEditionArg) -> MintResult {
+    let caller = ic_cdk::caller();
+    let owner = arg.to.owner;
+    let (tx_id, token_id) = STATE.with_borrow_mut(|s| s.mint_edition(&caller, arg))?;
+    grant_minting_authority_transfer_approval(&owner, vec![token_id]);
+    Ok((tx_id, token_id))
+}
+
+#[update(guard = "not_paused_guard")]
 pub fn transfer_from_and_grant_transfer_approval(
     arg: TransferFromArg
 ) -> TransferFromResult {
@@ -82,7 +129,7 @@ pub fn transfer_from_and_grant_transfer_approval(
     Ok(tx_id)
 }
 
-#[update(guard = "authenticated_guard")]
+#[update(guard = "not_paused_guard")]
 pub fn burn(
     args: Vec<BurnArg>
 ) -> Vec<Option<BurnResult>> {
@@ -90,7 +137,355 @@ pub fn burn(
     STATE.with(|s| s.borrow_mut().burn(&caller, args))
 }
 
-fn grant_minting_authority_transfer_approval(
+// Rents a token out until `expires_at` without transferring it. Transfers end the rental.
+#[update(guard = "not_paused_guard")]
+pub fn set_user(arg: SetUserArg) -> SetUserResult {
+    let caller = ic_cdk::caller();
+    let token_id = arg.token_id;
+    let rental = arg.user.map(|_| arg.expires_at);
+    let now = ic_cdk::api::time();
+    let block_index = STATE.with(|s| s.borrow_mut().set_user(&caller, arg, now))?;
+    if let Some(expires_at) = rental {
+        rental::schedule_user_expiry(token_id, expires_at);
+    }
+    Ok(block_index)
+}
+
+// Replaces the metadata of a token, logged as a 7update block.
+#[update(guard = "metadata_editor_guard")]
+pub fn update_token_metadata(arg: UpdateTokenMetadataArg) -> UpdateTokenMetadataResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().update_token_metadata(&caller, arg, ic_cdk::api::time()))
+}
+
+// Changes the collection limits and descriptive metadata, leaving out the fields not set.
+#[update(guard = "admin_guard")]
+pub fn update_collection_config(arg: UpdateCollectionConfigArg) -> UpdateCollectionConfigResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| {
+        s.borrow_mut()
+            .update_collection_config(default_account(&caller), arg, ic_cdk::api::time())
+    })
+}
+
+// Irreversibly locks the supply, and optionally the metadata and the launchpad's control.
+#[update(guard = "owner_guard")]
+pub async fn finalize(arg: FinalizeArg) -> FinalizeResult {
+    let caller = ic_cdk::caller();
+    let block_index = STATE.with(|s| {
+        s.borrow_mut()
+            .finalize(default_account(&caller), &arg, ic_cdk::api::time())
+    })?;
+    if arg.remove_launchpad {
+        finalize::remove_launchpad().await.map_err(|message| {
+            format!("Finalized at block {}, but the launchpad was kept: {}", block_index, message)
+        })?;
+    }
+    Ok(block_index)
+}
+
+// Retries dropping the launchpad's control of a finalized collection, when `finalize` could not.
+#[update(guard = "owner_guard")]
+pub async fn remove_launchpad() -> Result<(), String> {
+    finalize::remove_launchpad().await
+}
+
+// Grants a role: admins are granted by the owner, the other roles by admins.
+#[update(guard = "admin_guard")]
+pub fn grant_role(arg: RoleArg) -> RoleResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().grant_role(&caller, arg, ic_cdk::api::time()))
+}
+
+#[update(guard = "admin_guard")]
+pub fn revoke_role(arg: RoleArg) -> RoleResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().revoke_role(&caller, arg, ic_cdk::api::time()))
+}
+
+// Nominates the next owner, who takes over once they call `accept_ownership`.
+#[update(guard = "owner_guard")]
+pub fn transfer_ownership(new_owner: Principal) -> RoleResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().transfer_ownership(&caller, new_owner, ic_cdk::api::time()))
+}
+
+#[update(guard = "authenticated_guard")]
+pub fn accept_ownership() -> RoleResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().accept_ownership(&caller, ic_cdk::api::time()))
+}
+
+// Locks tokens against transfers and approvals. Burns by the owner stay allowed.
+#[update(guard = "admin_guard")]
+pub fn lock_tokens(token_ids: Vec<u128>) -> Vec<bool> {
+    STATE.with(|s| s.borrow_mut().lock_tokens(token_ids))
+}
+
+#[update(guard = "admin_guard")]
+pub fn unlock_tokens(token_ids: Vec<u128>) -> Vec<bool> {
+    STATE.with(|s| s.borrow_mut().unlock_tokens(token_ids))
+}
+
+// Stops every transfer, burn and approval change until unpaused.
+#[update(guard = "pauser_guard")]
+pub fn pause(arg: PauseArg) -> ComplianceResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().pause(default_account(&caller), arg.reason, ic_cdk::api::time()))
+}
+
+#[update(guard = "pauser_guard")]
+pub fn unpause(arg: PauseArg) -> ComplianceResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().unpause(default_account(&caller), arg.reason, ic_cdk::api::time()))
+}
+
+// Freezes a token or an account, e.g. after a theft report.
+#[update(guard = "pauser_guard")]
+pub fn freeze(arg: FreezeArg) -> ComplianceResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().freeze(default_account(&caller), arg, ic_cdk::api::time()))
+}
+
+#[update(guard = "pauser_guard")]
+pub fn unfreeze(arg: FreezeArg) -> ComplianceResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().unfreeze(default_account(&caller), arg, ic_cdk::api::time()))
+}
+
+// Credits mint revenue to the stakeholders. The caller must have approved this canister
+// on the ledger for the amount plus the ledger fee.
+#[update(guard = "authenticated_guard")]
+pub async fn deposit_revenue(arg: DepositRevenueArg) -> DepositRevenueResult {
+    let caller = ic_cdk::caller();
+    revenue::deposit_revenue(caller, arg).await
+}
+
+// Transfers the caller's whole claimable balance on a ledger, less the ledger fee.
+#[update(guard = "authenticated_guard")]
+pub async fn withdraw(arg: WithdrawArg) -> WithdrawResult {
+    let caller = ic_cdk::caller();
+    revenue::withdraw(caller, arg).await
+}
+
+// Sends assets held by a token's account. Only the token owner can, and not while it is listed.
+#[update(guard = "not_paused_guard")]
+pub async fn withdraw_from_token(arg: TokenWithdrawArg) -> TokenWithdrawResult {
+    let caller = ic_cdk::caller();
+    token_account::withdraw_from_token(caller, arg).await
+}
+
+// Moves a token into another token of the caller, which then carries it along.
+#[update(guard = "not_paused_guard")]
+pub fn equip(arg: EquipArg) -> EquipResult {
+    let caller = ic_cdk::caller();
+    let canister = ic_cdk::api::id();
+    STATE.with(|s| s.borrow_mut().equip(&caller, arg, canister, ic_cdk::api::time()))
+}
+
+// Gives an equipped token back to the owner of its outermost parent.
+#[update(guard = "not_paused_guard")]
+pub fn unequip(arg: UnequipArg) -> EquipResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().unequip(&caller, arg, ic_cdk::api::time()))
+}
+
+// Changes how future revenue is split; balances already credited are kept.
+#[update(guard = "treasurer_guard")]
+pub fn set_revenue_split(split: Vec<Stakeholder>) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().set_revenue_split(split))
+}
+
+// Enters the multisig mode, after which the privileged endpoints only run through proposals.
+#[update(guard = "owner_guard")]
+pub fn set_multisig(config: MultisigConfig) -> Result<(), MultisigError> {
+    STATE.with(|s| s.borrow_mut().set_multisig(Some(config)))
+}
+
+// Opens a proposal, executed right away when the proposer's approval reaches the threshold.
+#[update(guard = "signer_guard")]
+pub async fn propose(action: ProposalAction) -> ProposeResult {
+    let caller = ic_cdk::caller();
+    let id = STATE.with(|s| s.borrow_mut().propose(&caller, action, ic_cdk::api::time()))?;
+    multisig::execute_if_approved(id).await?;
+    Ok(id)
+}
+
+#[update(guard = "signer_guard")]
+pub async fn approve_proposal(id: u128) -> ApproveProposalResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().approve_proposal(&caller, id, ic_cdk::api::time()))?;
+    multisig::execute_if_approved(id).await
+}
+
+// Called by the launchpad before upgrading the collection on behalf of `requester`.
+#[update(guard = "installer_guard")]
+pub fn authorize_upgrade(requester: Principal, wasm_hash: Vec<u8>) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().consume_upgrade(&requester, &wasm_hash))
+}
+
+// Starts a chunked media upload, stored once committed under the sha256 of its content.
+#[update(guard = "minter_operation_guard")]
+pub fn create_upload(arg: CreateUploadArg) -> CreateUploadResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().create_upload(caller, arg, ic_cdk::api::time()))
+}
+
+// Appends the next chunk of one of the caller's uploads.
+#[update(guard = "minter_operation_guard")]
+pub fn upload_chunk(arg: UploadChunkArg) -> UploadChunkResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().upload_chunk(caller, arg))
+}
+
+// Stores an upload. Metadata then references it as `sha256:<hex>`.
+#[update(guard = "minter_operation_guard")]
+pub fn commit_upload(upload_id: u128) -> CommitUploadResult {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().commit_upload(caller, upload_id, ic_cdk::api::time()))
+}
+
+#[update(guard = "minter_operation_guard")]
+pub fn cancel_upload(upload_id: u128) -> Result<(), MediaError> {
+    let caller = ic_cdk::caller();
+    STATE.with(|s| s.borrow_mut().cancel_upload(caller, upload_id))
+}
+
+// Deletes stored media no token references. Referenced media goes with its last token.
+#[update(guard = "minter_guard")]
+pub fn delete_media(sha256: ByteBuf) -> Result<(), MediaError> {
+    let sha256: [u8; 32] = sha256
+        .into_vec()
+        .try_into()
+        .map_err(|_| MediaError::MediaNotFound)?;
+    STATE.with(|s| s.borrow_mut().delete_media(&sha256))
+}
+
+#[update(guard = "admin_guard")]
+pub fn set_media_quota(quota: MediaQuota) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().set_media_quota(quota))
+}
+
+// Checked on every later mint and metadata update, `None` removing it
+#[update(guard = "admin_guard")]
+pub fn set_metadata_schema(schema: Option<MetadataSchema>) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().set_metadata_schema(schema))
+}
+
+// Rebuilds the trait index for the given metadata keys
+#[update(guard = "admin_guard")]
+pub fn set_trait_keys(keys: Vec<String>) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().set_trait_keys(keys))
+}
+
+// Replays the whole block log, archives included, and reports where the live state differs
+#[update(guard = "admin_operation_guard")]
+pub async fn verify_replay() -> Result<ReplayReport, String> {
+    recovery::verify_replay().await
+}
+
+// Puts a fresh collection in recovery mode, to be rebuilt from another collection's blocks
+#[update(guard = "admin_operation_guard")]
+pub fn begin_recovery() -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().begin_recovery())
+}
+
+// Imports the next blocks of the log being recovered, returning the next block expected
+#[update(guard = "admin_operation_guard")]
+pub fn import_blocks(blocks: Vec<QueryBlock>) -> Result<u128, String> {
+    let res = STATE.with(|s| s.borrow_mut().import_blocks(blocks, ic_cdk::id()));
+    certify_collection();
+    res
+}
+
+// Loads the tokens and approvals the imported blocks lead to, and leaves the recovery mode
+#[update(guard = "admin_operation_guard")]
+pub fn end_recovery() -> Result<ReplayReport, String> {
+    let res = STATE.with(|s| s.borrow_mut().end_recovery(ic_cdk::id()));
+    certify_all();
+    res
+}
+
+// Checks the paused collection leaves no funds behind, the token accounts being read on
+// `ledgers`, and allows its `export_*` queries until it is unpaused
+#[update(guard = "admin_operation_guard")]
+pub async fn prepare_export(ledgers: Vec<Principal>) -> Result<(), String> {
+    let ledgers: Vec<IcrcLedger> = ledgers.into_iter().map(IcrcLedger).collect();
+    migration::prepare_export(&ledgers).await
+}
+
+// The `import_*` endpoints fill a collection installed in migration mode from another
+// collection's `export_*` queries
+#[update(guard = "admin_operation_guard")]
+pub fn import_config(config: ConfigExport) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().import_config(config))
+}
+
+#[update(guard = "admin_operation_guard")]
+pub fn import_tokens(tokens: Vec<TokenExport>) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().import_tokens(tokens, ic_cdk::id()))
+}
+
+#[update(guard = "admin_operation_guard")]
+pub fn import_token_approvals(approvals: Vec<TokenApprovalsExport>) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().import_token_approvals(approvals))
+}
+
+#[update(guard = "admin_operation_guard")]
+pub fn import_collection_approvals(
+    approvals: Vec<CollectionApprovalsExport>,
+) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().import_collection_approvals(approvals))
+}
+
+#[update(guard = "admin_operation_guard")]
+pub async fn import_archives(log: ArchivesExport) -> Result<(), String> {
+    let archived_tip = migration::archived_tip(&log).await?;
+    STATE.with(|s| s.borrow_mut().import_archives(log, archived_tip))
+}
+
+// Takes the pages of `get_frozen_tokens`
+#[update(guard = "admin_operation_guard")]
+pub fn import_frozen_tokens(freezes: Vec<(u128, FreezeRecord)>) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().import_frozen_tokens(freezes))
+}
+
+// Takes the pages of `get_frozen_accounts`
+#[update(guard = "admin_operation_guard")]
+pub fn import_frozen_accounts(freezes: Vec<(Account, FreezeRecord)>) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().import_frozen_accounts(freezes))
+}
+
+#[update(guard = "admin_operation_guard")]
+pub fn import_media(media: Vec<MediaAsset>) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().import_media(media))
+}
+
+// Takes the chunks of `get_media_chunk`, in any order
+#[update(guard = "admin_operation_guard")]
+pub fn import_media_chunk(sha256: ByteBuf, index: u32, content: ByteBuf) -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().import_media_chunk(sha256, index, content))
+}
+
+// Imports the next blocks the previous collection did not archive, returning the next block
+// expected
+#[update(guard = "admin_operation_guard")]
+pub fn import_log_blocks(blocks: Vec<QueryBlock>) -> Result<u128, String> {
+    let res = STATE.with(|s| s.borrow_mut().import_log_blocks(blocks));
+    certify_collection();
+    res
+}
+
+// Checks the whole collection was imported and opens it to normal traffic
+#[update(guard = "admin_operation_guard")]
+pub fn finalize_migration() -> Result<(), String> {
+    STATE.with(|s| s.borrow_mut().finalize_migration())?;
+    rental::schedule_rental_timers();
+    certify_all();
+    Ok(())
+}
+
+pub fn grant_minting_authority_transfer_approval(
     owner: &Principal,
     token_ids: Vec<u128>
 ) {
@@ -114,4 +509,4 @@ fn grant_minting_authority_transfer_approval(
 
         s.approve(owner, args)
     });
-}
\ No newline at end of file
+}
@@ -2,6 +2,39 @@ use candid::Principal;
 use icrc_ledger_types::icrc::generic_value::{self, Value};
 use icrc_ledger_types::icrc1::account::{Account, Subaccount, DEFAULT_SUBACCOUNT};
 
+// The system API only exists inside a canister, so unit tests get a fixed canister id, a clock
+// of their own and no certified data
+pub fn canister_id() -> Principal {
+    #[cfg(not(test))]
+    return ic_cdk::api::id();
+    #[cfg(test)]
+    return Principal::from_slice(&[9]);
+}
+
+#[cfg(test)]
+thread_local! {
+    static TEST_TIME: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+}
+
+pub fn time() -> u64 {
+    #[cfg(not(test))]
+    return ic_cdk::api::time();
+    #[cfg(test)]
+    return TEST_TIME.with(|time| time.get());
+}
+
+#[cfg(test)]
+pub fn set_time(now: u64) {
+    TEST_TIME.with(|time| time.set(now));
+}
+
+pub fn set_certified_data(data: &[u8]) {
+    #[cfg(not(test))]
+    ic_cdk::api::set_certified_data(data);
+    #[cfg(test)]
+    let _ = data;
+}
+
 pub fn account_transformer(account: Account) -> Account {
     if let Some(_) = account.subaccount {
         account
@@ -29,7 +62,7 @@ pub fn burn_subaccount() -> Subaccount {
 
 pub fn burn_account() -> Account {
     Account {
-        owner: ic_cdk::api::id(),
+        owner: canister_id(),
         subaccount: Some(burn_subaccount()),
     }
 }
@@ -37,3 +70,85 @@ pub fn burn_account() -> Account {
 pub fn hash_icrc_value(value: &Value) -> generic_value::Hash {
     return value.hash();
 }
+
+pub fn market_subaccount() -> Subaccount {
+    let mut bytes = [0; 32];
+    let slice = b"MARKET SUBACCOUNT";
+    bytes[0..17].copy_from_slice(slice);
+    bytes
+}
+
+pub fn market_account() -> Account {
+    Account {
+        owner: canister_id(),
+        subaccount: Some(market_subaccount()),
+    }
+}
+
+pub fn auction_subaccount(auction_id: u128) -> Subaccount {
+    let mut bytes = [0; 32];
+    let slice = b"AUCTION ESCROW";
+    bytes[0..14].copy_from_slice(slice);
+    bytes[16..32].copy_from_slice(&auction_id.to_be_bytes());
+    bytes
+}
+
+pub fn auction_account(auction_id: u128) -> Account {
+    Account {
+        owner: canister_id(),
+        subaccount: Some(auction_subaccount(auction_id)),
+    }
+}
+
+pub fn offer_subaccount(offer_id: u128) -> Subaccount {
+    let mut bytes = [0; 32];
+    let slice = b"OFFER ESCROW";
+    bytes[0..12].copy_from_slice(slice);
+    bytes[16..32].copy_from_slice(&offer_id.to_be_bytes());
+    bytes
+}
+
+pub fn offer_account(offer_id: u128) -> Account {
+    Account {
+        owner: canister_id(),
+        subaccount: Some(offer_subaccount(offer_id)),
+    }
+}
+
+pub fn revenue_subaccount() -> Subaccount {
+    let mut bytes = [0; 32];
+    let slice = b"REVENUE SUBACCOUNT";
+    bytes[0..18].copy_from_slice(slice);
+    bytes
+}
+
+pub fn revenue_account() -> Account {
+    Account {
+        owner: canister_id(),
+        subaccount: Some(revenue_subaccount()),
+    }
+}
+
+// The subaccount owned by a token: whoever owns the token controls what it holds
+pub fn token_subaccount(token_id: u128) -> Subaccount {
+    let mut bytes = [0; 32];
+    let slice = b"TOKEN ACCOUNT";
+    bytes[0..13].copy_from_slice(slice);
+    bytes[16..32].copy_from_slice(&token_id.to_be_bytes());
+    bytes
+}
+
+// The token owning a subaccount, if it is a token subaccount
+pub fn token_of_subaccount(subaccount: &Subaccount) -> Option<u128> {
+    if subaccount[0..16] != token_subaccount(0)[0..16] {
+        return None;
+    }
+    Some(u128::from_be_bytes(subaccount[16..32].try_into().unwrap()))
+}
+
+pub fn token_account(token_id: u128) -> Account {
+    Account {
+        owner: canister_id(),
+        subaccount: Some(token_subaccount(token_id)),
+    }
+}
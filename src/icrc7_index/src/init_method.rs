@@ -0,0 +1,50 @@
+use ic_cdk_macros::{init, post_upgrade, pre_upgrade};
+use ic_stable_structures::{writer::Writer, Memory};
+use crate::{
+    state::{State, DEFAULT_POLL_INTERVAL_SECS, STATE},
+    sync::schedule_sync,
+    types::IndexInitArg,
+};
+
+#[init]
+pub fn init(arg: IndexInitArg) {
+    STATE.with_borrow_mut(|s| {
+        *s = State {
+            collection: arg.collection,
+            poll_interval_secs: arg.poll_interval_secs.unwrap_or(DEFAULT_POLL_INTERVAL_SECS),
+            ..Default::default()
+        }
+    });
+    schedule_sync();
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let mut state_bytes = vec![];
+    STATE
+        .with(|s| ciborium::ser::into_writer(&*s.borrow(), &mut state_bytes))
+        .expect("failed to encode state");
+
+    // The length of the serialized bytes, followed by the bytes themselves
+    let len = state_bytes.len() as u32;
+    let mut memory = crate::memory::get_upgrades_memory();
+    let mut writer = Writer::new(&mut memory, 0);
+    writer.write(&len.to_le_bytes()).unwrap();
+    writer.write(&state_bytes).unwrap();
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let memory = crate::memory::get_upgrades_memory();
+
+    let mut state_len_bytes = [0; 4];
+    memory.read(0, &mut state_len_bytes);
+    let state_len = u32::from_le_bytes(state_len_bytes) as usize;
+
+    let mut state_bytes = vec![0; state_len];
+    memory.read(4, &mut state_bytes);
+
+    let state: State = ciborium::de::from_reader(&*state_bytes).expect("failed to decode state");
+    STATE.with(|s| *s.borrow_mut() = state);
+    schedule_sync();
+}
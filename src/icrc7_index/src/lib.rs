@@ -0,0 +1,7 @@
+pub mod types;
+pub mod memory;
+pub mod state;
+pub mod sync;
+pub mod init_method;
+pub mod query_method;
+pub mod candid_file_generator;
@@ -0,0 +1,30 @@
+use candid::Principal;
+use ic_stable_structures::{
+    memory_manager::{MemoryId, VirtualMemory},
+    DefaultMemoryImpl, StableBTreeMap,
+};
+use crate::{state::MEMORY_MANAGER, types::IndexedTransaction};
+
+// A memory for upgrades, where data from the heap can be serialized/deserialized.
+const UPGRADES: MemoryId = MemoryId::new(0);
+
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+pub fn get_upgrades_memory() -> Memory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(UPGRADES))
+}
+
+pub fn get_transactions_memory() -> StableBTreeMap<u128, IndexedTransaction, Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))))
+}
+
+// (owner, subaccount, transaction id)
+pub fn get_account_transactions_memory(
+) -> StableBTreeMap<(Principal, [u8; 32], u128), (), Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))))
+}
+
+// (token id, transaction id)
+pub fn get_token_transactions_memory() -> StableBTreeMap<(u128, u128), (), Memory> {
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))))
+}
@@ -0,0 +1,20 @@
+use ic_cdk_macros::query;
+use crate::{
+    state::STATE,
+    types::{GetAccountTransactionsArgs, GetTokenTransactionsArgs, IndexStatus, IndexedTransactions},
+};
+
+#[query]
+pub fn get_account_transactions(args: GetAccountTransactionsArgs) -> IndexedTransactions {
+    STATE.with(|s| s.borrow().get_account_transactions(args))
+}
+
+#[query]
+pub fn get_token_transactions(args: GetTokenTransactionsArgs) -> IndexedTransactions {
+    STATE.with(|s| s.borrow().get_token_transactions(args))
+}
+
+#[query]
+pub fn status() -> IndexStatus {
+    STATE.with(|s| s.borrow().status())
+}
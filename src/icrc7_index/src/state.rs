@@ -0,0 +1,346 @@
+use std::cell::RefCell;
+use candid::{Nat, Principal};
+use ic_stable_structures::{memory_manager::MemoryManager, DefaultMemoryImpl, StableBTreeMap};
+use icrc_ledger_types::{
+    icrc::generic_value::{Hash, Value},
+    icrc1::account::{Account, DEFAULT_SUBACCOUNT},
+};
+use icrc7_types::icrc3_types::QueryBlock;
+use serde::{Deserialize, Serialize};
+use crate::{
+    memory::{
+        get_account_transactions_memory, get_token_transactions_memory, get_transactions_memory,
+        Memory,
+    },
+    types::{
+        GetAccountTransactionsArgs, GetTokenTransactionsArgs, IndexStatus, IndexedTransaction,
+        IndexedTransactions,
+    },
+};
+
+pub const DEFAULT_POLL_INTERVAL_SECS: u64 = 10;
+pub const MAX_RESULTS: u128 = 500;
+
+// Every block carries a `tid`, only these are about the token it names
+const TOKEN_BLOCK_TYPES: [&str; 13] = [
+    "7mint",
+    "7burn",
+    "7xfer",
+    "7update",
+    "37appr",
+    "37revoke",
+    "37xfer",
+    "7freeze_token",
+    "7unfreeze_token",
+    "7set_user",
+    "7user_expired",
+    "7equip",
+    "7unequip",
+];
+
+#[derive(Serialize, Deserialize)]
+pub struct State {
+    pub collection: Principal,
+    pub poll_interval_secs: u64,
+    pub next_block: u128,
+    // the hash of block `next_block - 1`, the `phash` of the next block
+    pub last_hash: Option<Hash>,
+    pub log_length: u128,
+    pub last_synced_at: u64,
+    pub error: Option<String>,
+    #[serde(skip)]
+    pub syncing: bool,
+    #[serde(skip, default = "get_transactions_memory")]
+    pub transactions: StableBTreeMap<u128, IndexedTransaction, Memory>,
+    #[serde(skip, default = "get_account_transactions_memory")]
+    pub account_transactions: StableBTreeMap<(Principal, [u8; 32], u128), (), Memory>,
+    #[serde(skip, default = "get_token_transactions_memory")]
+    pub token_transactions: StableBTreeMap<(u128, u128), (), Memory>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            collection: Principal::anonymous(),
+            poll_interval_secs: DEFAULT_POLL_INTERVAL_SECS,
+            next_block: 0,
+            last_hash: None,
+            log_length: 0,
+            last_synced_at: 0,
+            error: None,
+            syncing: false,
+            transactions: get_transactions_memory(),
+            account_transactions: get_account_transactions_memory(),
+            token_transactions: get_token_transactions_memory(),
+        }
+    }
+}
+
+thread_local! {
+    // The memory manager is used for simulating multiple memories. Given a `MemoryId` it can
+    // return a memory that can be used by stable structures.
+    pub static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+
+    pub static STATE: RefCell<State> = RefCell::default();
+}
+
+impl State {
+    // Indexes the blocks that follow the indexed ones, in order, checking each one links to the
+    // previous one. Returns how many were indexed; blocks after a gap wait for the next sync.
+    pub fn ingest(&mut self, mut blocks: Vec<QueryBlock>) -> Result<usize, String> {
+        blocks.sort_by_key(|block| block.id);
+        let mut ingested = 0;
+        for QueryBlock { id, block } in blocks {
+            if id < self.next_block {
+                continue;
+            }
+            if id > self.next_block {
+                break;
+            }
+            let phash = match field(&block, "phash") {
+                Some(Value::Blob(phash)) => Some(
+                    Hash::try_from(phash.as_slice())
+                        .map_err(|_| format!("Block {id} has an invalid phash"))?,
+                ),
+                Some(_) => return Err(format!("Block {id} has an invalid phash")),
+                None => None,
+            };
+            if phash != self.last_hash {
+                return Err(format!("The phash of block {id} breaks the chain"));
+            }
+            self.index(decode_block(id, &block)?);
+            self.last_hash = Some(block.hash());
+            self.next_block += 1;
+            ingested += 1;
+        }
+        Ok(ingested)
+    }
+
+    fn index(&mut self, txn: IndexedTransaction) {
+        let mut accounts: Vec<Account> =
+            [txn.from, txn.to, txn.spender, txn.account].into_iter().flatten().collect();
+        accounts.sort();
+        accounts.dedup();
+        for account in accounts {
+            self.account_transactions
+                .insert((account.owner, subaccount_of(&account), txn.id), ());
+        }
+        if let Some(tid) = txn.tid {
+            self.token_transactions.insert((tid, txn.id), ());
+        }
+        self.transactions.insert(txn.id, txn);
+    }
+
+    pub fn get_account_transactions(
+        &self,
+        args: GetAccountTransactionsArgs,
+    ) -> IndexedTransactions {
+        let owner = args.account.owner;
+        let subaccount = subaccount_of(&args.account);
+        let end = args.start.unwrap_or(u128::MAX);
+        let ids = self
+            .account_transactions
+            .range((owner, subaccount, 0)..(owner, subaccount, end))
+            .map(|((_, _, id), _)| id);
+        let oldest_tx_id = self
+            .account_transactions
+            .range((owner, subaccount, 0)..=(owner, subaccount, u128::MAX))
+            .next()
+            .map(|((_, _, id), _)| id);
+        self.page(ids.rev(), args.max_results, oldest_tx_id)
+    }
+
+    pub fn get_token_transactions(&self, args: GetTokenTransactionsArgs) -> IndexedTransactions {
+        let end = args.start.unwrap_or(u128::MAX);
+        let ids = self
+            .token_transactions
+            .range((args.token_id, 0)..(args.token_id, end))
+            .map(|((_, id), _)| id);
+        let oldest_tx_id = self
+            .token_transactions
+            .range((args.token_id, 0)..=(args.token_id, u128::MAX))
+            .next()
+            .map(|((_, id), _)| id);
+        self.page(ids.rev(), args.max_results, oldest_tx_id)
+    }
+
+    fn page(
+        &self,
+        ids: impl Iterator<Item = u128>,
+        max_results: u128,
+        oldest_tx_id: Option<u128>,
+    ) -> IndexedTransactions {
+        IndexedTransactions {
+            transactions: ids
+                .take(max_results.min(MAX_RESULTS) as usize)
+                .filter_map(|id| self.transactions.get(&id))
+                .collect(),
+            oldest_tx_id,
+        }
+    }
+
+    pub fn status(&self) -> IndexStatus {
+        IndexStatus {
+            collection: self.collection,
+            next_block: self.next_block,
+            log_length: self.log_length,
+            last_synced_at: self.last_synced_at,
+            error: self.error.clone(),
+        }
+    }
+}
+
+fn subaccount_of(account: &Account) -> [u8; 32] {
+    account.subaccount.unwrap_or(*DEFAULT_SUBACCOUNT)
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Map(map) => map.get(key),
+        _ => None,
+    }
+}
+
+// Reads a block laid out as by `Block::new`: `btype`, `ts` and the `tx` map
+pub fn decode_block(id: u128, block: &Value) -> Result<IndexedTransaction, String> {
+    let invalid = |what: &str| format!("Block {id} has an invalid {what}");
+    let btype = match field(block, "btype") {
+        Some(Value::Text(btype)) => btype.clone(),
+        _ => return Err(invalid("btype")),
+    };
+    let tx = field(block, "tx").ok_or_else(|| invalid("tx"))?;
+    let ts = match field(block, "ts").or_else(|| field(tx, "ts")) {
+        Some(Value::Nat(ts)) => nat_to_u128(ts).ok_or_else(|| invalid("ts"))? as u64,
+        Some(Value::Nat64(ts)) => *ts,
+        _ => return Err(invalid("ts")),
+    };
+    let tid = match field(tx, "tid") {
+        Some(Value::Nat(tid)) => Some(nat_to_u128(tid).ok_or_else(|| invalid("tid"))?),
+        Some(_) => return Err(invalid("tid")),
+        None => None,
+    }
+    .filter(|_| TOKEN_BLOCK_TYPES.contains(&btype.as_str()));
+    let account = |key: &str| match field(tx, key) {
+        Some(value) => value_to_account(value).map(Some).ok_or_else(|| invalid(key)),
+        None => Ok(None),
+    };
+    let memo = match field(tx, "memo") {
+        Some(Value::Blob(memo)) => Some(memo.clone()),
+        Some(_) => return Err(invalid("memo")),
+        None => None,
+    };
+    Ok(IndexedTransaction {
+        id,
+        btype,
+        ts,
+        tid,
+        from: account("from")?,
+        to: account("to")?,
+        spender: account("spender")?,
+        account: account("account")?,
+        memo,
+    })
+}
+
+fn nat_to_u128(nat: &Nat) -> Option<u128> {
+    u128::try_from(nat.0.clone()).ok()
+}
+
+// An account encoded as `[owner]` or `[owner, subaccount]` blobs
+fn value_to_account(value: &Value) -> Option<Account> {
+    let Value::Array(parts) = value else {
+        return None;
+    };
+    let owner = match parts.first()? {
+        Value::Blob(owner) => Principal::try_from_slice(owner).ok()?,
+        _ => return None,
+    };
+    let subaccount = match parts.get(1) {
+        Some(Value::Blob(subaccount)) => Some(subaccount.as_slice().try_into().ok()?),
+        Some(_) => return None,
+        None => None,
+    };
+    Some(Account { owner, subaccount })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use icrc7_types::{icrc3_types::Block, icrc7_types::Transaction};
+    use serde_bytes::ByteBuf;
+
+    fn account(n: u8) -> Account {
+        Account {
+            owner: Principal::from_slice(&[n]),
+            subaccount: None,
+        }
+    }
+
+    fn chain(txns: Vec<Transaction>) -> Vec<QueryBlock> {
+        let mut phash = None;
+        let mut blocks = vec![];
+        for (id, txn) in txns.into_iter().enumerate() {
+            let block = Block::new(phash, txn).into_inner();
+            phash = Some(block.hash());
+            blocks.push(QueryBlock {
+                id: id as u128,
+                block,
+            });
+        }
+        blocks
+    }
+
+    #[test]
+    fn blocks_are_indexed_by_account_and_token() {
+        let mut state = State::default();
+        let (alice, bob) = (account(1), account(2));
+        let mut blocks = chain(vec![
+            Transaction::mint(1, 1, None, alice, Default::default(), None),
+            Transaction::mint(2, 2, None, alice, Default::default(), None),
+            Transaction::transfer(3, 1, alice, bob, Some(b"gift".to_vec())),
+            Transaction::approve_collection(4, alice, bob, None, None),
+            Transaction::burn(5, 1, bob, None, None),
+        ]);
+        let tampered = blocks.pop().unwrap();
+
+        // out of order and with a gap, only the blocks up to the gap are indexed
+        let later = blocks.split_off(3);
+        assert_eq!(state.ingest(vec![blocks[2].clone(), blocks[0].clone()]), Ok(1));
+        assert_eq!(state.ingest(blocks.clone()), Ok(2));
+        assert_eq!(state.ingest(later), Ok(1));
+
+        let mut tampered_block = tampered.clone();
+        if let Value::Map(map) = &mut tampered_block.block {
+            map.insert("phash".into(), Value::Blob(vec![0; 32].into()));
+        }
+        assert!(state.ingest(vec![tampered_block]).is_err());
+        assert_eq!(state.ingest(vec![tampered]), Ok(1));
+        assert_eq!(state.next_block, 5);
+
+        let ids = |txns: IndexedTransactions| -> Vec<u128> {
+            txns.transactions.iter().map(|txn| txn.id).collect()
+        };
+        let alice_txns = |start, max_results| {
+            state.get_account_transactions(GetAccountTransactionsArgs {
+                account: alice,
+                start,
+                max_results,
+            })
+        };
+        assert_eq!(ids(alice_txns(None, 10)), vec![3, 2, 1, 0]);
+        assert_eq!(ids(alice_txns(None, 2)), vec![3, 2]);
+        assert_eq!(ids(alice_txns(Some(2), 2)), vec![1, 0]);
+        assert_eq!(alice_txns(Some(2), 2).oldest_tx_id, Some(0));
+
+        let token = state.get_token_transactions(GetTokenTransactionsArgs {
+            token_id: 1,
+            start: None,
+            max_results: 10,
+        });
+        assert_eq!(ids(token.clone()), vec![4, 2, 0]);
+        assert_eq!(token.transactions[1].memo, Some(ByteBuf::from(b"gift".to_vec())));
+        // the collection approval names no token
+        assert_eq!(state.transactions.get(&3).unwrap().tid, None);
+    }
+}
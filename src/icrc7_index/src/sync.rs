@@ -0,0 +1,64 @@
+use std::time::Duration;
+use candid::Principal;
+use icrc7_types::icrc3_types::{GetBlocksResult, TransactionRange};
+use crate::state::STATE;
+
+const BLOCKS_PER_CALL: u128 = 100;
+// bounds the work of one timer tick, the next tick resuming from there
+const MAX_CALLS_PER_SYNC: usize = 10;
+
+pub fn schedule_sync() {
+    let interval = STATE.with_borrow(|s| s.poll_interval_secs);
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(interval), || ic_cdk::spawn(sync()));
+}
+
+pub async fn sync() {
+    if STATE.with_borrow(|s| s.syncing) {
+        return;
+    }
+    STATE.with_borrow_mut(|s| s.syncing = true);
+    let res = sync_blocks().await;
+    STATE.with_borrow_mut(|s| {
+        s.syncing = false;
+        s.error = res.err();
+        s.last_synced_at = ic_cdk::api::time();
+    });
+}
+
+// Fetches the blocks after the indexed ones, following the archive callbacks for those the
+// collection no longer holds
+async fn sync_blocks() -> Result<(), String> {
+    for _ in 0..MAX_CALLS_PER_SYNC {
+        let (collection, start) = STATE.with_borrow(|s| (s.collection, s.next_block));
+        let range = TransactionRange {
+            start,
+            length: BLOCKS_PER_CALL,
+        };
+        let res = get_blocks(collection, "icrc3_get_blocks", vec![range]).await?;
+        let mut blocks = res.blocks;
+        for archived in res.archived_blocks {
+            let callback = archived.callback;
+            let archived = get_blocks(callback.canister_id, &callback.method, archived.args).await?;
+            blocks.extend(archived.blocks);
+        }
+        let ingested = STATE.with_borrow_mut(|s| {
+            s.log_length = res.log_length;
+            s.ingest(blocks)
+        })?;
+        if ingested == 0 || STATE.with_borrow(|s| s.next_block >= s.log_length) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+async fn get_blocks(
+    canister_id: Principal,
+    method: &str,
+    args: Vec<TransactionRange>,
+) -> Result<GetBlocksResult, String> {
+    let (res,): (GetBlocksResult,) = ic_cdk::call(canister_id, method, (args,))
+        .await
+        .map_err(|(code, msg)| format!("Rejection Code: {:?}, Message: {:?}", code, msg))?;
+    Ok(res)
+}
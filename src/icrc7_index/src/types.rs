@@ -0,0 +1,76 @@
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::{storable::Bound, Storable};
+use icrc_ledger_types::icrc1::account::Account;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IndexInitArg {
+    // the ICRC-7 collection to index
+    pub collection: Principal,
+    // defaults to DEFAULT_POLL_INTERVAL_SECS
+    pub poll_interval_secs: Option<u64>,
+}
+
+// A block of the collection, decoded. Collection-wide blocks (pauses, role changes, ...) have
+// no token and are only indexed by the accounts they name.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexedTransaction {
+    pub id: u128,
+    pub btype: String,
+    pub ts: u64,
+    pub tid: Option<u128>,
+    pub from: Option<Account>,
+    pub to: Option<Account>,
+    pub spender: Option<Account>,
+    // the account targeted by a compliance or role block
+    pub account: Option<Account>,
+    pub memo: Option<ByteBuf>,
+}
+
+impl Storable for IndexedTransaction {
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// Newest first: `start` is the oldest transaction id seen so far, the next page holding the
+// transactions before it
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetAccountTransactionsArgs {
+    pub account: Account,
+    pub start: Option<u128>,
+    pub max_results: u128,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetTokenTransactionsArgs {
+    pub token_id: u128,
+    pub start: Option<u128>,
+    pub max_results: u128,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexedTransactions {
+    pub transactions: Vec<IndexedTransaction>,
+    // the oldest transaction of the account or token, where paging stops
+    pub oldest_tx_id: Option<u128>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IndexStatus {
+    pub collection: Principal,
+    // the blocks before it are indexed
+    pub next_block: u128,
+    // the length of the collection log at the last sync
+    pub log_length: u128,
+    pub last_synced_at: u64,
+    // why the last sync stopped, a broken hash chain stalling the index
+    pub error: Option<String>,
+}
@@ -1,4 +1,4 @@
-use crate::update_method::Arg;
+use crate::{state::CollectionRecord, update_method::Arg};
 use candid::{export_service, Principal};
 use ic_cdk_macros::query;
 
@@ -0,0 +1,20 @@
+use ic_cdk::storage;
+use ic_cdk_macros::{post_upgrade, pre_upgrade};
+use std::mem;
+
+use crate::state::{StableState, STATE};
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let state = STATE.with(|state| mem::take(&mut *state.borrow_mut()));
+    let stable_state = StableState { state };
+    storage::stable_save((stable_state,)).unwrap();
+}
+
+// Launchpads installed before the registry existed have nothing saved yet
+#[post_upgrade]
+fn post_upgrade() {
+    if let Ok((StableState { state },)) = storage::stable_restore() {
+        STATE.with(|state0| *state0.borrow_mut() = state);
+    }
+}
@@ -1,5 +1,9 @@
 use ic_cdk_macros::export_candid;
 
+pub mod state;
+pub mod management;
+pub mod init_method;
+pub mod query_method;
 pub mod update_method;
 pub mod candid_file_generator;
 
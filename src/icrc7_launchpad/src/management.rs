@@ -0,0 +1,93 @@
+use candid::Principal;
+use ic_cdk::api::management_canister::{
+    main::{
+        create_canister, delete_canister, install_code, stop_canister, CanisterIdRecord,
+        CanisterInstallMode, CreateCanisterArgument, InstallCodeArgument, LogVisibility,
+    },
+    provisional::CanisterSettings,
+};
+
+// The management canister calls a collection deployment makes
+pub(crate) trait Management {
+    async fn create_canister(
+        &self,
+        controllers: Vec<Principal>,
+        cycles: u128,
+    ) -> Result<Principal, String>;
+
+    async fn install_code(
+        &self,
+        canister_id: Principal,
+        wasm_module: &[u8],
+        arg: Vec<u8>,
+    ) -> Result<(), String>;
+
+    // Makes `admin` an admin of an asset canister
+    async fn set_assets_admin(
+        &self,
+        canister_id: Principal,
+        admin: Principal,
+    ) -> Result<(), String>;
+
+    // Removes a canister created for a deployment that failed midway. This is best effort: a
+    // canister that cannot be deleted stays controlled by the launchpad and its creator.
+    async fn delete_canister(&self, canister_id: Principal);
+}
+
+pub struct IcManagement;
+
+impl Management for IcManagement {
+    async fn create_canister(
+        &self,
+        controllers: Vec<Principal>,
+        cycles: u128,
+    ) -> Result<Principal, String> {
+        let arg = CreateCanisterArgument {
+            settings: Some(CanisterSettings {
+                controllers: Some(controllers),
+                compute_allocation: None,
+                memory_allocation: None,
+                freezing_threshold: None,
+                reserved_cycles_limit: None,
+                wasm_memory_limit: None,
+                log_visibility: Some(LogVisibility::Public),
+            }),
+        };
+        match create_canister(arg, cycles).await {
+            Err((code, msg)) => Err(format!("Rejection Code: {:?}, Message: {:?}", code, msg)),
+            Ok((principal,)) => Ok(principal.canister_id),
+        }
+    }
+
+    async fn install_code(
+        &self,
+        canister_id: Principal,
+        wasm_module: &[u8],
+        arg: Vec<u8>,
+    ) -> Result<(), String> {
+        install_code(InstallCodeArgument {
+            mode: CanisterInstallMode::Install,
+            canister_id,
+            wasm_module: wasm_module.to_vec(),
+            arg,
+        })
+        .await
+        .map_err(|(code, msg)| format!("Code: {:?}, Message: {:?}", code, msg))
+    }
+
+    async fn set_assets_admin(
+        &self,
+        canister_id: Principal,
+        admin: Principal,
+    ) -> Result<(), String> {
+        ic_cdk::call(canister_id, "permission_set_admin", (admin,))
+            .await
+            .map_err(|(code, msg)| format!("Rejection Code: {:?}, Message: {:?}", code, msg))
+    }
+
+    async fn delete_canister(&self, canister_id: Principal) {
+        if stop_canister(CanisterIdRecord { canister_id }).await.is_ok() {
+            let _ = delete_canister(CanisterIdRecord { canister_id }).await;
+        }
+    }
+}
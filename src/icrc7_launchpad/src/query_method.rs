@@ -0,0 +1,14 @@
+use candid::Principal;
+use ic_cdk_macros::query;
+
+use crate::state::{CollectionRecord, STATE};
+
+#[query]
+fn get_collection(collection: Principal) -> Option<CollectionRecord> {
+    STATE.with_borrow(|s| s.collections.get(&collection).cloned())
+}
+
+#[query]
+fn collections_of(creator: Principal) -> Vec<CollectionRecord> {
+    STATE.with_borrow(|s| s.collections_of(creator))
+}
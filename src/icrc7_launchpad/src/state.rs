@@ -0,0 +1,72 @@
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+use std::{cell::RefCell, collections::BTreeMap};
+
+// A collection deployed by the launchpad, with the asset canister deployed next to it
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct CollectionRecord {
+    pub collection: Principal,
+    pub creator: Principal,
+    pub assets_canister: Option<Principal>,
+    pub created_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Default)]
+pub struct State {
+    // collection canister -> record
+    pub collections: BTreeMap<Principal, CollectionRecord>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct StableState {
+    pub state: State,
+}
+
+impl State {
+    pub fn register(&mut self, record: CollectionRecord) {
+        self.collections.insert(record.collection, record);
+    }
+
+    pub fn collections_of(&self, creator: Principal) -> Vec<CollectionRecord> {
+        self.collections
+            .values()
+            .filter(|record| record.creator == creator)
+            .cloned()
+            .collect()
+    }
+}
+
+thread_local! {
+    pub static STATE: RefCell<State> = RefCell::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collections_are_listed_per_creator() {
+        let record = |collection: u8, creator: u8| CollectionRecord {
+            collection: Principal::from_slice(&[collection]),
+            creator: Principal::from_slice(&[creator]),
+            assets_canister: None,
+            created_at: 0,
+        };
+        let mut state = State::default();
+        state.register(record(10, 1));
+        state.register(record(11, 2));
+        state.register(record(12, 1));
+        assert_eq!(
+            state.collections_of(Principal::from_slice(&[1])),
+            vec![record(10, 1), record(12, 1)]
+        );
+        assert!(state.collections_of(Principal::from_slice(&[3])).is_empty());
+
+        // registering a collection again replaces its record
+        let mut updated = record(11, 2);
+        updated.assets_canister = Some(Principal::from_slice(&[20]));
+        state.register(updated.clone());
+        assert_eq!(state.collections.len(), 3);
+        assert_eq!(state.collections_of(Principal::from_slice(&[2])), vec![updated]);
+    }
+}
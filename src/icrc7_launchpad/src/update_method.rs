@@ -1,15 +1,30 @@
 use candid::{CandidType, Encode, Principal};
 use ic_cdk::api::management_canister::{
-    main::{create_canister, install_code, CreateCanisterArgument, InstallCodeArgument, LogVisibility},
+    main::{
+        canister_status, install_code, update_settings, CanisterIdRecord, CanisterInstallMode,
+        InstallCodeArgument, UpdateSettingsArgument,
+    },
     provisional::CanisterSettings,
 };
 use ic_cdk_macros::update;
 use icrc7_types::icrc37_types::InitApprovalsArg;
 use icrc7_types::icrc3_types::InitArchiveArg;
+use icrc7_types::icrc7_types::{RoyaltyRecipient, TransferPolicy};
+use icrc7_types::revenue_types::Stakeholder;
 use icrc_ledger_types::icrc1::account::Account;
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    management::{IcManagement, Management},
+    state::{CollectionRecord, STATE},
+};
 
 pub const ICRC7_WASM: &[u8] = std::include_bytes!("./../../../wasm/icrc7.wasm.gz");
+pub const ASSETS_WASM: &[u8] = std::include_bytes!("./../../../wasm/ic_canister_assets.wasm.gz");
+
+const COLLECTION_CYCLES: u128 = 10_000_000_000_000;
+const ASSETS_CYCLES: u128 = 2_000_000_000_000;
 
 #[derive(CandidType, Deserialize)]
 pub struct InitArg {
@@ -29,6 +44,11 @@ pub struct InitArg {
     pub permitted_drift: Option<u64>,
     pub approval_init: Option<InitApprovalsArg>,
     pub archive_init: Option<InitArchiveArg>,
+    pub royalties: Option<Vec<RoyaltyRecipient>>,
+    pub revenue_split: Option<Vec<Stakeholder>>,
+    pub transferable: Option<TransferPolicy>,
+    pub owner: Option<Principal>,
+    pub assets_canister: Option<Principal>,
 }
 
 #[derive(CandidType, Deserialize)]
@@ -48,6 +68,11 @@ pub struct Arg {
     pub permitted_drift: Option<u64>,
     pub approval_init: Option<InitApprovalsArg>,
     pub archive_init: Option<InitArchiveArg>,
+    pub royalties: Option<Vec<RoyaltyRecipient>>,
+    pub revenue_split: Option<Vec<Stakeholder>>,
+    pub transferable: Option<TransferPolicy>,
+    // also deploys an asset canister for the collection media
+    pub deploy_assets: Option<bool>,
 }
 
 impl From<(Account, Arg)> for InitArg {
@@ -69,50 +94,325 @@ impl From<(Account, Arg)> for InitArg {
             permitted_drift: arg.permitted_drift,
             approval_init: arg.approval_init,
             archive_init: arg.archive_init,
+            royalties: arg.royalties,
+            revenue_split: arg.revenue_split,
+            transferable: arg.transferable,
+            // the creator owns the collection, not the launchpad installing it
+            owner: Some(account.owner),
+            assets_canister: None,
         }
     }
 }
 
+// Deploys a collection owned by the caller. With `deploy_assets`, an asset canister controlled
+// by the caller and the collection is deployed next to it and advertised in its metadata.
 #[update]
 async fn mint_collection_canister(arg: Arg) -> Result<Principal, String> {
     let caller = ic_cdk::caller();
     if caller == Principal::anonymous() {
         return Err("Anonymous Caller".into());
     }
+    let record =
+        deploy_collection(&IcManagement, ic_cdk::id(), caller, arg, ic_cdk::api::time()).await?;
+    let principal = record.collection;
+    STATE.with_borrow_mut(|s| s.register(record));
+    Ok(principal)
+}
+
+// Nothing deployed is left behind when a step fails
+async fn deploy_collection<M: Management>(
+    management: &M,
+    launchpad: Principal,
+    creator: Principal,
+    arg: Arg,
+    now: u64,
+) -> Result<CollectionRecord, String> {
     let account = Account {
-        owner: caller.clone(),
+        owner: creator,
         subaccount: None,
     };
-    let principal = match create_canister(
-        CreateCanisterArgument {
-            settings: Some(CanisterSettings {
-                controllers: Some(vec![ic_cdk::id(), caller.clone()]),
-                compute_allocation: None,
-                memory_allocation: None,
-                freezing_threshold: None,
-                reserved_cycles_limit: None,
-                wasm_memory_limit: None,
-                log_visibility: Some(LogVisibility::Public),
-            }),
-        },
-        10_000_000_000_000,
-    )
-    .await
+    let principal = management
+        .create_canister(vec![launchpad, creator], COLLECTION_CYCLES)
+        .await?;
+    let assets_canister = match install_collection(management, launchpad, principal, account, arg)
+        .await
     {
-        Err((code, msg)) => return Err(format!("Rejection Code: {:?}, Message: {:?}", code, msg)),
-        Ok((principal,)) => principal.canister_id,
+        Ok(assets_canister) => assets_canister,
+        Err(err) => {
+            management.delete_canister(principal).await;
+            return Err(err);
+        }
     };
-    let init_arg = InitArg::from((account, arg));
+    Ok(CollectionRecord {
+        collection: principal,
+        creator,
+        assets_canister,
+        created_at: now,
+    })
+}
+
+// Returns the asset canister deployed along with the collection
+async fn install_collection<M: Management>(
+    management: &M,
+    launchpad: Principal,
+    principal: Principal,
+    account: Account,
+    arg: Arg,
+) -> Result<Option<Principal>, String> {
+    let assets_canister = if arg.deploy_assets.unwrap_or(false) {
+        Some(deploy_assets_canister(management, launchpad, account.owner, principal).await?)
+    } else {
+        None
+    };
+    let mut init_arg = InitArg::from((account, arg));
+    init_arg.assets_canister = assets_canister;
     let init_arg = Encode!(&init_arg).unwrap();
-    match install_code(InstallCodeArgument {
-        mode: ic_cdk::api::management_canister::main::CanisterInstallMode::Install,
-        canister_id: principal,
+    if let Err(err) = management.install_code(principal, ICRC7_WASM, init_arg).await {
+        if let Some(assets_canister) = assets_canister {
+            management.delete_canister(assets_canister).await;
+        }
+        return Err(err);
+    }
+    Ok(assets_canister)
+}
+
+// The creator is made an admin of the asset canister so it can upload right away
+async fn deploy_assets_canister<M: Management>(
+    management: &M,
+    launchpad: Principal,
+    creator: Principal,
+    collection: Principal,
+) -> Result<Principal, String> {
+    let canister_id = management
+        .create_canister(vec![launchpad, creator, collection], ASSETS_CYCLES)
+        .await?;
+    let installed = match management
+        .install_code(canister_id, ASSETS_WASM, Encode!().unwrap())
+        .await
+    {
+        Ok(()) => management.set_assets_admin(canister_id, creator).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = installed {
+        management.delete_canister(canister_id).await;
+        return Err(err);
+    }
+    Ok(canister_id)
+}
+
+// Upgrades a collection to the wasm embedded in this launchpad. The collection decides whether
+// the caller may do so: its owner, or anyone once a multisig proposal authorized that wasm.
+// Collections predating `authorize_upgrade` can only be upgraded by one of their controllers.
+#[update]
+async fn upgrade_collection_canister(canister_id: Principal) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err("Anonymous Caller".into());
+    }
+    let wasm_hash = Sha256::digest(ICRC7_WASM).to_vec();
+    let authorized: Result<(Result<(), String>,), _> =
+        ic_cdk::call(canister_id, "authorize_upgrade", (caller, wasm_hash)).await;
+    match authorized {
+        Ok((authorized,)) => authorized?,
+        Err(_) => {
+            if !controllers_of(canister_id).await?.contains(&caller) {
+                return Err("Only a controller can upgrade the collection".into());
+            }
+        }
+    }
+    install_code(InstallCodeArgument {
+        mode: CanisterInstallMode::Upgrade(None),
+        canister_id,
         wasm_module: ICRC7_WASM.to_vec(),
-        arg: init_arg,
+        arg: Encode!().unwrap(),
     })
     .await
-    {
-        Ok(()) => Ok(principal),
-        Err((code, msg)) => Err(format!("Code: {:?}, Message: {:?}", code, msg)),
+    .map_err(|(code, msg)| format!("Code: {:?}, Message: {:?}", code, msg))
+}
+
+async fn controllers_of(canister_id: Principal) -> Result<Vec<Principal>, String> {
+    let (status,) = canister_status(CanisterIdRecord { canister_id })
+        .await
+        .map_err(|(code, msg)| format!("Code: {:?}, Message: {:?}", code, msg))?;
+    Ok(status.settings.controllers)
+}
+
+// Called by a finalized collection: the launchpad stops being one of its controllers.
+#[update]
+async fn release_collection() -> Result<(), String> {
+    let canister_id = ic_cdk::caller();
+    let controllers = controllers_of(canister_id)
+        .await?
+        .into_iter()
+        .filter(|controller| *controller != ic_cdk::id())
+        .collect();
+    update_settings(UpdateSettingsArgument {
+        canister_id,
+        settings: CanisterSettings {
+            controllers: Some(controllers),
+            ..Default::default()
+        },
+    })
+    .await
+    .map_err(|(code, msg)| format!("Code: {:?}, Message: {:?}", code, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Decode;
+    use std::{
+        cell::{Cell, RefCell},
+        collections::BTreeMap,
+        future::Future,
+        pin::pin,
+        task::Context,
+    };
+
+    // In-memory management canister stand-in
+    #[derive(Default)]
+    struct LocalManagement {
+        next_id: Cell<u8>,
+        // live canister -> controllers
+        canisters: RefCell<BTreeMap<Principal, Vec<Principal>>>,
+        // installed canister -> init arg
+        installed: RefCell<BTreeMap<Principal, Vec<u8>>>,
+        admins: RefCell<BTreeMap<Principal, Principal>>,
+        failing_installs: RefCell<Vec<Principal>>,
+        failing_admin: Cell<bool>,
+    }
+
+    impl Management for LocalManagement {
+        async fn create_canister(
+            &self,
+            controllers: Vec<Principal>,
+            _cycles: u128,
+        ) -> Result<Principal, String> {
+            self.next_id.set(self.next_id.get() + 1);
+            let canister_id = Principal::from_slice(&[100 + self.next_id.get()]);
+            self.canisters.borrow_mut().insert(canister_id, controllers);
+            Ok(canister_id)
+        }
+
+        async fn install_code(
+            &self,
+            canister_id: Principal,
+            _wasm_module: &[u8],
+            arg: Vec<u8>,
+        ) -> Result<(), String> {
+            if self.failing_installs.borrow().contains(&canister_id) {
+                return Err("Install failed".into());
+            }
+            self.installed.borrow_mut().insert(canister_id, arg);
+            Ok(())
+        }
+
+        async fn set_assets_admin(
+            &self,
+            canister_id: Principal,
+            admin: Principal,
+        ) -> Result<(), String> {
+            if self.failing_admin.get() {
+                return Err("permission_set_admin failed".into());
+            }
+            self.admins.borrow_mut().insert(canister_id, admin);
+            Ok(())
+        }
+
+        async fn delete_canister(&self, canister_id: Principal) {
+            self.canisters.borrow_mut().remove(&canister_id);
+            self.installed.borrow_mut().remove(&canister_id);
+        }
+    }
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let mut f = pin!(f);
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        loop {
+            if let std::task::Poll::Ready(res) = f.as_mut().poll(&mut cx) {
+                return res;
+            }
+        }
+    }
+
+    fn arg(deploy_assets: bool) -> Arg {
+        Arg {
+            icrc7_symbol: "ICRC7".into(),
+            icrc7_name: "Collection".into(),
+            icrc7_description: None,
+            icrc7_logo: None,
+            icrc7_supply_cap: None,
+            icrc7_max_query_batch_size: None,
+            icrc7_max_update_batch_size: None,
+            icrc7_max_take_value: None,
+            icrc7_default_take_value: None,
+            icrc7_max_memo_size: None,
+            icrc7_atomic_batch_transfers: None,
+            tx_window: None,
+            permitted_drift: None,
+            approval_init: None,
+            archive_init: None,
+            royalties: None,
+            revenue_split: None,
+            transferable: None,
+            deploy_assets: Some(deploy_assets),
+        }
+    }
+
+    const LAUNCHPAD: Principal = Principal::from_slice(&[1]);
+    const CREATOR: Principal = Principal::from_slice(&[2]);
+    // ids handed out in creation order: the collection first, then its asset canister
+    const COLLECTION: Principal = Principal::from_slice(&[101]);
+    const ASSETS: Principal = Principal::from_slice(&[102]);
+
+    #[test]
+    fn collections_are_deployed_with_their_asset_canister() {
+        let management = LocalManagement::default();
+        let record = block_on(deploy_collection(&management, LAUNCHPAD, CREATOR, arg(true), 7));
+        assert_eq!(
+            record,
+            Ok(CollectionRecord {
+                collection: COLLECTION,
+                creator: CREATOR,
+                assets_canister: Some(ASSETS),
+                created_at: 7,
+            })
+        );
+        let canisters = management.canisters.borrow();
+        assert_eq!(canisters[&COLLECTION], vec![LAUNCHPAD, CREATOR]);
+        assert_eq!(canisters[&ASSETS], vec![LAUNCHPAD, CREATOR, COLLECTION]);
+        assert_eq!(management.admins.borrow()[&ASSETS], CREATOR);
+
+        let installed = management.installed.borrow();
+        let init_arg = Decode!(&installed[&COLLECTION], InitArg).unwrap();
+        assert_eq!(init_arg.owner, Some(CREATOR));
+        assert_eq!(init_arg.minting_account.unwrap().owner, CREATOR);
+        assert_eq!(init_arg.assets_canister, Some(ASSETS));
+
+        let management = LocalManagement::default();
+        let record = block_on(deploy_collection(&management, LAUNCHPAD, CREATOR, arg(false), 7));
+        assert_eq!(record.unwrap().assets_canister, None);
+        assert_eq!(management.canisters.borrow().len(), 1);
+        let installed = management.installed.borrow();
+        let init_arg = Decode!(&installed[&COLLECTION], InitArg).unwrap();
+        assert_eq!(init_arg.assets_canister, None);
+    }
+
+    #[test]
+    fn failed_deployments_leave_no_canister_behind() {
+        for failing in [COLLECTION, ASSETS] {
+            let management = LocalManagement::default();
+            management.failing_installs.borrow_mut().push(failing);
+            let record = block_on(deploy_collection(&management, LAUNCHPAD, CREATOR, arg(true), 7));
+            assert_eq!(record, Err("Install failed".to_string()));
+            assert!(management.canisters.borrow().is_empty());
+            assert!(management.installed.borrow().is_empty());
+        }
+
+        let management = LocalManagement::default();
+        management.failing_admin.set(true);
+        let record = block_on(deploy_collection(&management, LAUNCHPAD, CREATOR, arg(true), 7));
+        assert_eq!(record, Err("permission_set_admin failed".to_string()));
+        assert!(management.canisters.borrow().is_empty());
     }
 }
@@ -0,0 +1,43 @@
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::{storable::Bound, Storable};
+use icrc_ledger_types::icrc1::account::Account;
+use serde::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FreezeTarget {
+    Token(u128),
+    Account(Account),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct FreezeRecord {
+    pub reason: String,
+    pub by: Account,
+    pub at: u64,
+}
+
+impl Storable for FreezeRecord {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct PauseArg {
+    pub reason: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct FreezeArg {
+    pub target: FreezeTarget,
+    pub reason: String,
+}
+
+// The block index of the action
+pub type ComplianceResult = Result<u128, String>;
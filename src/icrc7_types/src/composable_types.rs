@@ -0,0 +1,24 @@
+use candid::CandidType;
+use icrc_ledger_types::icrc1::account::Subaccount;
+use serde::Deserialize;
+
+use crate::errors::EquipError;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct EquipArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    // the token that will own `token_id`, also owned by the caller
+    pub parent_id: u128,
+    pub memo: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct UnequipArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    pub memo: Option<Vec<u8>>,
+}
+
+// The block index of the equip or unequip
+pub type EquipResult = Result<u128, EquipError>;
@@ -1,6 +1,8 @@
-use candid::CandidType;
+use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
 
+use crate::schema_types::SchemaViolation;
+
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub enum TransferError {
     NonExistingTokenId,
@@ -9,6 +11,8 @@ pub enum TransferError {
     TooOld,
     CreatedInFuture { ledger_time: u64 },
     Duplicate { duplicate_of: u128 },
+    NonTransferable,
+    Frozen,
     GenericError { error_code: u128, message: String },
     GenericBatchError { error_code: u128, message: String },
 }
@@ -17,6 +21,7 @@ pub enum TransferError {
 pub enum BurnError {
     Unauthorized,
     NonExistingTokenId,
+    Frozen,
     GenericError { error_code: u128, message: String },
     GenericBatchError { error_code: u128, message: String },
 }
@@ -27,6 +32,7 @@ pub enum MintError {
     Unauthorized,
     TokenIdAlreadyExist,
     TokenIdMinimumLimit,
+    InvalidMetadata { violations: Vec<SchemaViolation> },
     GenericError { error_code: u128, message: String },
     GenericBatchError { error_code: u128, message: String },
 }
@@ -50,6 +56,8 @@ pub enum ApproveTokenError {
     CreatedInFuture { ledger_time: u64 },
     NonExistingTokenId,
     Unauthorized,
+    NonTransferable,
+    Frozen,
     GenericError { error_code: u128, message: String },
     Duplicate { duplicate_of: u128 },
     GenericBatchError { error_code: u128, message: String },
@@ -60,6 +68,8 @@ pub enum ApproveCollectionError {
     InvalidSpender,
     TooOld,
     CreatedInFuture { ledger_time: u64 },
+    NonTransferable,
+    Frozen,
     GenericError { error_code: u128, message: String },
     Duplicate { duplicate_of: u128 },
     GenericBatchError { error_code: u128, message: String },
@@ -96,6 +106,114 @@ pub enum TransferFromError {
     TooOld,
     CreatedInFuture { ledger_time: u64 },
     Duplicate { duplicate_of: u128 },
+    NonTransferable,
+    Frozen,
     GenericError { error_code: u128, message: String },
     GenericBatchError { error_code: u128, message: String },
-}
\ No newline at end of file
+}
+// Marketplace Error
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum MarketError {
+    NonExistingTokenId,
+    Unauthorized,
+    NotListed,
+    ListingExpired,
+    InvalidPrice,
+    PriceMismatch { price: u128 },
+    SettlementInProgress,
+    AuctionNotFound,
+    AuctionNotActive,
+    AuctionNotEnded,
+    InvalidAuction { message: String },
+    BidTooLow { min_bid: u128 },
+    OfferNotFound,
+    OfferExpired,
+    OfferTargetMismatch,
+    OfferMismatch { ledger: Principal, amount: u128 },
+    PaymentFailed { message: String },
+    NonTransferable,
+    Frozen,
+    Paused,
+    GenericError { error_code: u128, message: String },
+}
+
+// Revenue Error
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum RevenueError {
+    NoRevenueSplit,
+    InvalidAmount,
+    InsufficientBalance { balance: u128 },
+    TransferFailed { message: String },
+    GenericError { error_code: u128, message: String },
+}
+
+// Role Error
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum RoleError {
+    Unauthorized,
+    AlreadyGranted,
+    NotGranted,
+    NoPendingOwner,
+    GenericError { error_code: u128, message: String },
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum MultisigError {
+    NotEnabled,
+    NotASigner,
+    InvalidConfig { message: String },
+    ProposalNotFound,
+    ProposalClosed,
+    ProposalExpired,
+    AlreadyApproved,
+    GenericError { error_code: u128, message: String },
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum SetUserError {
+    NonExistingTokenId,
+    Unauthorized,
+    InvalidExpiry,
+    Frozen,
+    GenericError { error_code: u128, message: String },
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum TokenAccountError {
+    NonExistingTokenId,
+    Unauthorized,
+    Frozen,
+    // the account moves with the token, so it can't be drained while a buyer may pay for it
+    Listed,
+    TransferFailed { message: String },
+    GenericError { error_code: u128, message: String },
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum EquipError {
+    NonExistingTokenId,
+    Unauthorized,
+    Frozen,
+    NonTransferable,
+    // the parent is the token itself or one of the tokens it holds
+    CyclicEquip,
+    NotEquipped,
+    SettlementInProgress,
+    GenericError { error_code: u128, message: String },
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum MediaError {
+    Unauthorized,
+    UploadNotFound,
+    MediaNotFound,
+    QuotaExceeded { message: String },
+    // the uploaded content does not hash to the announced sha256
+    HashMismatch { sha256: Vec<u8> },
+    // the media is still referenced by token metadata
+    InUse { refs: u64 },
+    GenericError { error_code: u128, message: String },
+}
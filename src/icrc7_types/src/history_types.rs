@@ -0,0 +1,20 @@
+use candid::CandidType;
+use icrc_ledger_types::icrc1::account::Account;
+use serde::Deserialize;
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct TokenOwner {
+    pub token_id: u128,
+    pub owner: Account,
+}
+
+// The owners of the live tokens once block `block` was applied, by ascending token id
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct OwnershipSnapshotArg {
+    pub block: u128,
+    pub prev: Option<u128>,
+    pub take: Option<u128>,
+}
+
+pub type OwnersAtResult = Result<Vec<Option<Account>>, String>;
+pub type OwnershipSnapshotResult = Result<Vec<TokenOwner>, String>;
@@ -0,0 +1,32 @@
+use candid::CandidType;
+use icrc_ledger_types::icrc1::account::Account;
+use serde::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct HolderStats {
+    // accounts holding at least one live token
+    pub holders: u64,
+    pub minted: u128,
+    pub burned: u128,
+    pub transfers: u128,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct Holder {
+    pub account: Account,
+    pub balance: u128,
+}
+
+// Holders by descending balance, resuming after `prev`
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HoldersArg {
+    pub prev: Option<Account>,
+    pub take: Option<u128>,
+}
+
+// How many holders hold exactly `balance` tokens
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct BalanceBucket {
+    pub balance: u128,
+    pub holders: u64,
+}
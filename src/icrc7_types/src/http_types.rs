@@ -0,0 +1,21 @@
+use candid::CandidType;
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+
+pub type HeaderField = (String, String);
+
+// The HTTP gateway interface boundary nodes call `http_request` with
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<HeaderField>,
+    pub body: ByteBuf,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<HeaderField>,
+    pub body: ByteBuf,
+}
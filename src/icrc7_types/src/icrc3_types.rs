@@ -89,6 +89,12 @@ impl Block {
         if let Some(spender) = tx.spender {
             val.insert("spender".to_string(), account_value(spender));
         }
+        if let Some(account) = tx.account {
+            val.insert("account".to_string(), account_value(account));
+        }
+        if let Some(role) = tx.role {
+            val.insert("role".to_string(), Value::Text(role));
+        }
         if let Some(exp) = tx.exp {
             val.insert("exp".to_string(), Value::Nat(exp.into()));
         }
@@ -103,6 +109,62 @@ impl Block {
         Self(Value::Map(block))
     }
 
+    // The transaction a block was made from by `new`, the block itself included
+    pub fn transaction(&self) -> Result<Transaction, String> {
+        let invalid = |key: &str| format!("The block has an invalid {key}");
+        let Value::Map(block) = &self.0 else {
+            return Err("block must be a map value".to_string());
+        };
+        let op = match block.get("btype") {
+            Some(Value::Text(op)) => op.clone(),
+            _ => return Err(invalid("btype")),
+        };
+        let ts = match block.get("ts") {
+            Some(Value::Nat(ts)) => u64::try_from(ts.0.clone()).map_err(|_| invalid("ts"))?,
+            _ => return Err(invalid("ts")),
+        };
+        let Some(Value::Map(tx)) = block.get("tx") else {
+            return Err(invalid("tx"));
+        };
+        let nat = |key: &str| match tx.get(key) {
+            Some(Value::Nat(nat)) => u128::try_from(nat.0.clone())
+                .map(Some)
+                .map_err(|_| invalid(key)),
+            Some(_) => Err(invalid(key)),
+            None => Ok(None),
+        };
+        let account = |key: &str| match tx.get(key) {
+            Some(value) => value_account(value).map(Some).ok_or_else(|| invalid(key)),
+            None => Ok(None),
+        };
+        Ok(Transaction {
+            ts,
+            op,
+            tid: nat("tid")?.unwrap_or_default(),
+            from: account("from")?,
+            to: account("to")?,
+            spender: account("spender")?,
+            exp: nat("exp")?.map(|exp| exp as u64),
+            meta: match tx.get("meta") {
+                Some(Value::Map(meta)) => Some(meta.clone()),
+                Some(_) => return Err(invalid("meta")),
+                None => None,
+            },
+            memo: match tx.get("memo") {
+                Some(Value::Blob(memo)) => Some(memo.to_vec()),
+                Some(_) => return Err(invalid("memo")),
+                None => None,
+            },
+            block: Some(self.clone()),
+            account: account("account")?,
+            role: match tx.get("role") {
+                Some(Value::Text(role)) => Some(role.clone()),
+                Some(_) => return Err(invalid("role")),
+                None => None,
+            },
+        })
+    }
+
     pub fn into_inner(self) -> Value {
         self.0
     }
@@ -115,6 +177,9 @@ impl Block {
     }
 }
 
+// The README lists each custom block type and the fields of its `tx`
+pub static CUSTOM_BLOCKS_URL: &str = "https://github.com/av1ctor/icrc7_launchpad#custom-blocks";
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct BlockType {
     pub block_type: String,
@@ -129,6 +194,22 @@ fn account_value(Account { owner, subaccount }: Account) -> Value {
     Value::Array(parts)
 }
 
+fn value_account(value: &Value) -> Option<Account> {
+    let Value::Array(parts) = value else {
+        return None;
+    };
+    let owner = match parts.first()? {
+        Value::Blob(owner) => Principal::try_from_slice(owner).ok()?,
+        _ => return None,
+    };
+    let subaccount = match parts.get(1) {
+        Some(Value::Blob(subaccount)) => Some(subaccount.as_slice().try_into().ok()?),
+        Some(_) => return None,
+        None => None,
+    };
+    Some(Account { owner, subaccount })
+}
+
 #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
 pub enum IndexType {
     Managed,
@@ -222,6 +303,70 @@ impl ArchiveLedgerInfo {
                     url: "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-37/ICRC-37.md"
                         .into(),
                 },
+                BlockType {
+                    block_type: "7pause".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7unpause".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7freeze_token".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7unfreeze_token".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7freeze_account".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7unfreeze_account".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7role_grant".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7role_revoke".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7owner_transfer".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7owner_accept".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7update_coll".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7finalize".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7set_user".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7user_expired".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7equip".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
+                BlockType {
+                    block_type: "7unequip".into(),
+                    url: CUSTOM_BLOCKS_URL.into(),
+                },
             ],
         }
     }
@@ -296,7 +441,7 @@ pub struct GetArchiveArgs {
 
 pub type GetBlocksArgs = Vec<TransactionRange>;
 
-#[derive(CandidType, Deserialize, Debug)]
+#[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct QueryBlock {
     pub id: u128,
     pub block: Value,
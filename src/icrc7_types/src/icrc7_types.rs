@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 
-use candid::{CandidType, Decode, Encode};
+use candid::{CandidType, Decode, Encode, Nat, Principal};
 use ic_stable_structures::{storable::Bound, Storable};
 use icrc_ledger_types::{
     icrc::generic_value::Value,
@@ -12,10 +12,28 @@ use crate::{
     errors::{BurnError, InsertTransactionError, MintError, TransferError},
     icrc37_types::InitApprovalsArg,
     icrc3_types::{Block, InitArchiveArg},
+    rbac_types::Role,
+    revenue_types::Stakeholder,
 };
 
 pub static TRANSACTION_TRANSFER_OP: &str = "7xfer";
 pub static TRANSACTION_TRANSFER_FROM_OP: &str = "37xfer";
+pub static TRANSACTION_PAUSE_OP: &str = "7pause";
+pub static TRANSACTION_UNPAUSE_OP: &str = "7unpause";
+pub static TRANSACTION_FREEZE_TOKEN_OP: &str = "7freeze_token";
+pub static TRANSACTION_UNFREEZE_TOKEN_OP: &str = "7unfreeze_token";
+pub static TRANSACTION_FREEZE_ACCOUNT_OP: &str = "7freeze_account";
+pub static TRANSACTION_UNFREEZE_ACCOUNT_OP: &str = "7unfreeze_account";
+pub static TRANSACTION_GRANT_ROLE_OP: &str = "7role_grant";
+pub static TRANSACTION_REVOKE_ROLE_OP: &str = "7role_revoke";
+pub static TRANSACTION_TRANSFER_OWNERSHIP_OP: &str = "7owner_transfer";
+pub static TRANSACTION_ACCEPT_OWNERSHIP_OP: &str = "7owner_accept";
+pub static TRANSACTION_UPDATE_COLLECTION_OP: &str = "7update_coll";
+pub static TRANSACTION_FINALIZE_OP: &str = "7finalize";
+pub static TRANSACTION_SET_USER_OP: &str = "7set_user";
+pub static TRANSACTION_USER_EXPIRED_OP: &str = "7user_expired";
+pub static TRANSACTION_EQUIP_OP: &str = "7equip";
+pub static TRANSACTION_UNEQUIP_OP: &str = "7unequip";
 
 #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
 pub enum TransactionType {
@@ -61,6 +79,80 @@ pub enum TransactionType {
         from: Account,
         to: Option<Account>,
     },
+    Pause {
+        by: Account,
+    },
+    Unpause {
+        by: Account,
+    },
+    FreezeToken {
+        tid: u128,
+        by: Account,
+    },
+    UnfreezeToken {
+        tid: u128,
+        by: Account,
+    },
+    FreezeAccount {
+        account: Account,
+        by: Account,
+    },
+    UnfreezeAccount {
+        account: Account,
+        by: Account,
+    },
+    Update {
+        tid: u128,
+        from: Account,
+        meta: Icrc7TokenMetadata,
+    },
+    GrantRole {
+        account: Account,
+        role: Role,
+        by: Account,
+    },
+    RevokeRole {
+        account: Account,
+        role: Role,
+        by: Account,
+    },
+    TransferOwnership {
+        account: Account,
+        by: Account,
+    },
+    AcceptOwnership {
+        by: Account,
+    },
+    UpdateCollection {
+        by: Account,
+        changes: Icrc7TokenMetadata,
+    },
+    Finalize {
+        by: Account,
+        supply: u128,
+        metadata_frozen: bool,
+    },
+    SetUser {
+        tid: u128,
+        from: Account,
+        user: Option<Account>,
+        expires_at: Option<u64>,
+    },
+    UserExpired {
+        tid: u128,
+        user: Account,
+    },
+    Equip {
+        tid: u128,
+        from: Account,
+        to: Account,
+        by: Account,
+    },
+    Unequip {
+        tid: u128,
+        from: Account,
+        to: Account,
+    },
 }
 
 #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
@@ -75,6 +167,9 @@ pub struct Transaction {
     pub meta: Option<Icrc7TokenMetadata>,
     pub memo: Option<Vec<u8>>,
     pub block: Option<Block>,
+    // the account targeted by a compliance or role action
+    pub account: Option<Account>,
+    pub role: Option<String>,
 }
 
 impl Transaction {
@@ -244,6 +339,129 @@ impl Transaction {
         }
     }
 
+    // Custom block recording a pause or a freeze, `from` being the account that acted
+    pub fn compliance(
+        now_sec: u64,
+        op: &str,
+        tid: u128,
+        from: Account,
+        account: Option<Account>,
+        memo: Option<Vec<u8>>,
+    ) -> Self {
+        Transaction {
+            ts: now_sec,
+            op: op.to_string(),
+            tid,
+            from: Some(from),
+            account,
+            memo,
+            ..Default::default()
+        }
+    }
+
+    // Custom block recording a rental, `account` being the user and `exp` the rental expiry
+    pub fn user_change(
+        now_sec: u64,
+        op: &str,
+        tid: u128,
+        from: Option<Account>,
+        user: Option<Account>,
+        exp: Option<u64>,
+        memo: Option<Vec<u8>>,
+    ) -> Self {
+        Transaction {
+            ts: now_sec,
+            op: op.to_string(),
+            tid,
+            from,
+            account: user,
+            exp,
+            memo,
+            ..Default::default()
+        }
+    }
+
+    // Custom block recording a token moving into or out of a parent token's account, `account`
+    // being the root owner that equipped it
+    pub fn equipment_change(
+        now_sec: u64,
+        op: &str,
+        tid: u128,
+        from: Account,
+        to: Account,
+        by: Option<Account>,
+        memo: Option<Vec<u8>>,
+    ) -> Self {
+        Transaction {
+            ts: now_sec,
+            op: op.to_string(),
+            tid,
+            from: Some(from),
+            to: Some(to),
+            account: by,
+            memo,
+            ..Default::default()
+        }
+    }
+
+    // Custom block recording a change of roles, `from` being the account that made it
+    pub fn role_change(
+        now_sec: u64,
+        op: &str,
+        from: Account,
+        account: Account,
+        role: Option<Role>,
+        memo: Option<Vec<u8>>,
+    ) -> Self {
+        Transaction {
+            ts: now_sec,
+            op: op.to_string(),
+            from: Some(from),
+            account: Some(account),
+            role: role.map(|role| role.as_str().to_string()),
+            memo,
+            ..Default::default()
+        }
+    }
+
+    // Custom block recording a change of the collection config, `meta` holding the new values
+    pub fn update_collection(
+        now_sec: u64,
+        from: Account,
+        changes: Icrc7TokenMetadata,
+        memo: Option<Vec<u8>>,
+    ) -> Self {
+        Transaction {
+            ts: now_sec,
+            op: TRANSACTION_UPDATE_COLLECTION_OP.to_string(),
+            from: Some(from),
+            meta: Some(changes),
+            memo,
+            ..Default::default()
+        }
+    }
+
+    // Custom block recording the finalization, `meta` holding the frozen supply
+    pub fn finalize(
+        now_sec: u64,
+        from: Account,
+        supply: u128,
+        metadata_frozen: bool,
+        memo: Option<Vec<u8>>,
+    ) -> Self {
+        Transaction {
+            ts: now_sec,
+            op: TRANSACTION_FINALIZE_OP.to_string(),
+            from: Some(from),
+            meta: Some(BTreeMap::from([
+                ("icrc7:supply_cap".to_string(), Value::Nat(supply.into())),
+                (METADATA_FROZEN.to_string(), Value::text(metadata_frozen)),
+            ])),
+            memo,
+            ..Default::default()
+        }
+    }
+
     pub fn new(_txn_id: u128, txn_type: TransactionType, at: u64, memo: Option<Vec<u8>>) -> Self {
         let transaction = match &txn_type {
             TransactionType::Transfer { tid, from, to } => {
@@ -281,11 +499,11 @@ impl Transaction {
             TransactionType::ApproveCollection { from, to, exp_sec } => {
                 Self::approve_collection(at, from.clone(), to.clone(), exp_sec.clone(), memo)
             }
-            TransactionType::Revoke { tid, from, to: _ } => {
-                Self::revoke(at, tid.clone(), from.clone(), None, memo)
+            TransactionType::Revoke { tid, from, to } => {
+                Self::revoke(at, *tid, *from, *to, memo)
             }
-            TransactionType::RevokeCollection { from, to: _ } => {
-                Self::revoke_collection(at, from.clone(), None, memo)
+            TransactionType::RevokeCollection { from, to } => {
+                Self::revoke_collection(at, *from, *to, memo)
             }
             TransactionType::TransferFrom {
                 tid,
@@ -300,6 +518,81 @@ impl Transaction {
                 spender.clone(),
                 memo,
             ),
+            TransactionType::Pause { by } => {
+                Self::compliance(at, TRANSACTION_PAUSE_OP, 0, *by, None, memo)
+            }
+            TransactionType::Unpause { by } => {
+                Self::compliance(at, TRANSACTION_UNPAUSE_OP, 0, *by, None, memo)
+            }
+            TransactionType::FreezeToken { tid, by } => {
+                Self::compliance(at, TRANSACTION_FREEZE_TOKEN_OP, *tid, *by, None, memo)
+            }
+            TransactionType::UnfreezeToken { tid, by } => {
+                Self::compliance(at, TRANSACTION_UNFREEZE_TOKEN_OP, *tid, *by, None, memo)
+            }
+            TransactionType::FreezeAccount { account, by } => {
+                Self::compliance(at, TRANSACTION_FREEZE_ACCOUNT_OP, 0, *by, Some(*account), memo)
+            }
+            TransactionType::UnfreezeAccount { account, by } => {
+                Self::compliance(at, TRANSACTION_UNFREEZE_ACCOUNT_OP, 0, *by, Some(*account), memo)
+            }
+            TransactionType::Update { tid, from, meta } => {
+                Self::update(at, *tid, *from, meta.clone(), memo)
+            }
+            TransactionType::GrantRole { account, role, by } => {
+                Self::role_change(at, TRANSACTION_GRANT_ROLE_OP, *by, *account, Some(*role), memo)
+            }
+            TransactionType::RevokeRole { account, role, by } => {
+                Self::role_change(at, TRANSACTION_REVOKE_ROLE_OP, *by, *account, Some(*role), memo)
+            }
+            TransactionType::TransferOwnership { account, by } => {
+                Self::role_change(at, TRANSACTION_TRANSFER_OWNERSHIP_OP, *by, *account, None, memo)
+            }
+            TransactionType::AcceptOwnership { by } => Self::role_change(
+                at,
+                TRANSACTION_ACCEPT_OWNERSHIP_OP,
+                *by,
+                *by,
+                Some(Role::Owner),
+                memo,
+            ),
+            TransactionType::UpdateCollection { by, changes } => {
+                Self::update_collection(at, *by, changes.clone(), memo)
+            }
+            TransactionType::Finalize {
+                by,
+                supply,
+                metadata_frozen,
+            } => Self::finalize(at, *by, *supply, *metadata_frozen, memo),
+            TransactionType::SetUser {
+                tid,
+                from,
+                user,
+                expires_at,
+            } => Self::user_change(
+                at,
+                TRANSACTION_SET_USER_OP,
+                *tid,
+                Some(*from),
+                *user,
+                *expires_at,
+                memo,
+            ),
+            TransactionType::UserExpired { tid, user } => Self::user_change(
+                at,
+                TRANSACTION_USER_EXPIRED_OP,
+                *tid,
+                None,
+                Some(*user),
+                None,
+                memo,
+            ),
+            TransactionType::Equip { tid, from, to, by } => {
+                Self::equipment_change(at, TRANSACTION_EQUIP_OP, *tid, *from, *to, Some(*by), memo)
+            }
+            TransactionType::Unequip { tid, from, to } => {
+                Self::equipment_change(at, TRANSACTION_UNEQUIP_OP, *tid, *from, *to, None, memo)
+            }
         };
         return transaction;
     }
@@ -337,6 +630,10 @@ pub struct MintArg {
     pub token_id: Option<u128>,
     pub memo: Option<Vec<u8>>,
     pub meta: Icrc7TokenMetadata,
+    // overrides the collection royalties for this token
+    pub royalties: Option<Vec<RoyaltyRecipient>>,
+    // mints the token already locked against transfers
+    pub locked: Option<bool>,
 }
 
 pub type MintResult = Result<(u128, u128), MintError>;
@@ -368,6 +665,15 @@ pub struct InitArg {
     pub permitted_drift: Option<u64>,
     pub approval_init: Option<InitApprovalsArg>,
     pub archive_init: Option<InitArchiveArg>,
+    pub royalties: Option<Vec<RoyaltyRecipient>>,
+    pub revenue_split: Option<Vec<Stakeholder>>,
+    pub transferable: Option<TransferPolicy>,
+    // defaults to the installer
+    pub owner: Option<Principal>,
+    // the asset canister hosting the collection media, advertised in the collection metadata
+    pub assets_canister: Option<Principal>,
+    // installs the collection in migration mode, to be filled by the `import_*` endpoints
+    pub migration: Option<bool>,
 }
 
 #[derive(CandidType)]
@@ -386,3 +692,116 @@ pub struct ApprovalArg {
 }
 
 pub type SyncReceipt = Result<u32, InsertTransactionError>;
+
+pub static METADATA_ROYALTIES: &str = "icrc7:royalties";
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoyaltyRecipient {
+    pub account: Account,
+    // share of the sale price, in basis points
+    pub bps: u16,
+}
+
+// Encodes royalties as an array of `{ account; bps }` maps, the account following the ICRC-3 encoding
+pub fn royalties_value(royalties: &[RoyaltyRecipient]) -> Value {
+    Value::Array(
+        royalties
+            .iter()
+            .map(|recipient| {
+                Value::Map(BTreeMap::from([
+                    ("account".to_string(), Value::from(recipient.account)),
+                    ("bps".to_string(), Value::Nat(Nat::from(recipient.bps))),
+                ]))
+            })
+            .collect(),
+    )
+}
+
+pub static METADATA_TRANSFERABLE: &str = "icrc7:transferable";
+pub static METADATA_ASSETS_CANISTER: &str = "icrc7:assets_canister";
+pub static METADATA_ASSETS_URL: &str = "icrc7:assets_url";
+
+// The base URL an asset canister serves its files under
+pub fn assets_url(assets_canister: Principal) -> String {
+    format!("https://{}.raw.icp0.io", assets_canister)
+}
+
+// Who may move the tokens of the collection. Burns by the owner are always allowed.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum TransferPolicy {
+    #[default]
+    Always,
+    // soulbound: tokens stay with the account they were minted to
+    Never,
+    MintingAuthorityOnly,
+}
+
+impl TransferPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransferPolicy::Always => "always",
+            TransferPolicy::Never => "never",
+            TransferPolicy::MintingAuthorityOnly => "minting_authority",
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct UpdateTokenMetadataArg {
+    pub token_id: u128,
+    pub meta: Icrc7TokenMetadata,
+    pub memo: Option<Vec<u8>>,
+}
+
+pub type UpdateTokenMetadataResult = Result<u128, String>;
+
+// Partial update of the collection config: fields left as `None` are kept. An empty
+// description or logo removes it.
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct UpdateCollectionConfigArg {
+    pub icrc7_name: Option<String>,
+    pub icrc7_description: Option<String>,
+    pub icrc7_logo: Option<String>,
+    pub icrc7_max_query_batch_size: Option<u16>,
+    pub icrc7_max_update_batch_size: Option<u16>,
+    pub icrc7_max_take_value: Option<u128>,
+    pub icrc7_default_take_value: Option<u128>,
+    pub icrc7_max_memo_size: Option<u32>,
+    pub icrc7_atomic_batch_transfers: Option<bool>,
+    pub tx_window: Option<u64>,
+    pub permitted_drift: Option<u64>,
+    pub approval_init: Option<InitApprovalsArg>,
+    // replaces the whole archive setting
+    pub archive_init: Option<InitArchiveArg>,
+    pub memo: Option<Vec<u8>>,
+}
+
+// The index of the 7update_coll block
+pub type UpdateCollectionConfigResult = Result<u128, String>;
+
+pub static METADATA_FINALIZED: &str = "icrc7:finalized";
+pub static METADATA_FINALIZED_BLOCK: &str = "icrc7:finalized_block";
+pub static METADATA_FROZEN: &str = "icrc7:metadata_frozen";
+pub static METADATA_LAUNCHPAD_REMOVED: &str = "icrc7:launchpad_removed";
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct FinalizeArg {
+    // rejects any later change of the token metadata and of the collection name, description
+    // and logo
+    pub freeze_metadata: bool,
+    // asks the launchpad that installed the collection to stop being one of its controllers
+    pub remove_launchpad: bool,
+    pub memo: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Finalization {
+    pub block_index: u128,
+    pub at: u64,
+    pub by: Account,
+    pub metadata_frozen: bool,
+    pub launchpad_removed: bool,
+}
+
+// The index of the 7finalize block
+pub type FinalizeResult = Result<u128, String>;
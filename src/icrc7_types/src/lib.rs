@@ -2,3 +2,20 @@ pub mod icrc3_types;
 pub mod icrc37_types;
 pub mod icrc7_types;
 pub mod errors;
+pub mod market_types;
+pub mod revenue_types;
+pub mod compliance_types;
+pub mod rbac_types;
+pub mod multisig_types;
+pub mod series_types;
+pub mod rental_types;
+pub mod token_account_types;
+pub mod composable_types;
+pub mod http_types;
+pub mod media_types;
+pub mod schema_types;
+pub mod trait_types;
+pub mod holder_types;
+pub mod history_types;
+pub mod replay_types;
+pub mod migration_types;
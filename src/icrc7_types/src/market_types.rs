@@ -0,0 +1,276 @@
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::{storable::Bound, Storable};
+use icrc_ledger_types::{
+    icrc::generic_value::Value,
+    icrc1::account::{Account, Subaccount},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::MarketError;
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct Listing {
+    pub token_id: u128,
+    pub seller: Account,
+    pub price: u128,
+    pub ledger: Principal,
+    pub expires_at: Option<u64>,
+    pub created_at: u64,
+}
+
+impl Storable for Listing {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Listing {
+    pub fn is_expired(&self, now: u64) -> bool {
+        match self.expires_at {
+            None => false,
+            Some(expires_at) => expires_at <= now,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ListTokenArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    pub price: u128,
+    pub ledger: Principal,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct DelistArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BuyArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    // the price the buyer agreed to, guards against a relisting between query and call
+    pub price: u128,
+    pub to: Option<Account>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MarketFee {
+    pub fee_bps: u16,
+    pub fee_account: Account,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Payout {
+    pub ledger: Principal,
+    pub from_subaccount: Option<Subaccount>,
+    pub to: Account,
+    pub amount: u128,
+}
+
+pub type ListTokenResult = Result<u128, MarketError>;
+
+pub type DelistResult = Result<u128, MarketError>;
+
+pub type BuyResult = Result<u128, MarketError>;
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub enum AuctionKind {
+    English {
+        reserve_price: u128,
+        min_increment: u128,
+        // bids placed closer than this to the end push the end back by the same amount
+        extension_window: u64,
+    },
+    Dutch {
+        start_price: u128,
+        floor_price: u128,
+        decay_amount: u128,
+        decay_interval: u64,
+    },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AuctionStatus {
+    Active,
+    Settling,
+    Settled,
+    Unsold,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct Bid {
+    pub bidder: Account,
+    pub to: Account,
+    pub amount: u128,
+    pub at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct Auction {
+    pub id: u128,
+    pub token_id: u128,
+    pub seller: Account,
+    pub ledger: Principal,
+    pub kind: AuctionKind,
+    pub start_at: u64,
+    pub end_at: u64,
+    pub highest_bid: Option<Bid>,
+    pub status: AuctionStatus,
+}
+
+impl Storable for Auction {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Auction {
+    pub fn is_open(&self, now: u64) -> bool {
+        self.status == AuctionStatus::Active && self.start_at <= now && now < self.end_at
+    }
+
+    // The lowest amount a new bid must offer, `None` when the auction takes no bids
+    pub fn min_bid(&self, now: u64) -> Option<u128> {
+        match self.kind {
+            AuctionKind::English {
+                reserve_price,
+                min_increment,
+                ..
+            } => match self.highest_bid {
+                None => Some(reserve_price),
+                Some(ref bid) => Some(bid.amount.saturating_add(min_increment.max(1))),
+            },
+            AuctionKind::Dutch {
+                start_price,
+                floor_price,
+                decay_amount,
+                decay_interval,
+            } => {
+                if self.highest_bid.is_some() {
+                    return None;
+                }
+                let steps = now
+                    .saturating_sub(self.start_at)
+                    .checked_div(decay_interval)
+                    .unwrap_or_default() as u128;
+                Some(
+                    start_price
+                        .saturating_sub(decay_amount.saturating_mul(steps))
+                        .max(floor_price),
+                )
+            }
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CreateAuctionArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    pub ledger: Principal,
+    pub kind: AuctionKind,
+    pub start_at: Option<u64>,
+    pub end_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BidArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub auction_id: u128,
+    pub amount: u128,
+    pub to: Option<Account>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CancelAuctionArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub auction_id: u128,
+}
+
+pub type CreateAuctionResult = Result<u128, MarketError>;
+
+pub type BidResult = Result<u128, MarketError>;
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub enum OfferTarget {
+    Token(u128),
+    Collection,
+    Trait { key: String, value: Value },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct Offer {
+    pub id: u128,
+    pub bidder: Account,
+    pub target: OfferTarget,
+    pub ledger: Principal,
+    pub amount: u128,
+    pub expires_at: Option<u64>,
+    pub created_at: u64,
+}
+
+impl Storable for Offer {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Offer {
+    pub fn is_expired(&self, now: u64) -> bool {
+        match self.expires_at {
+            None => false,
+            Some(expires_at) => expires_at <= now,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MakeOfferArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub target: OfferTarget,
+    pub ledger: Principal,
+    pub amount: u128,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CancelOfferArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub offer_id: u128,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AcceptOfferArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub offer_id: u128,
+    pub token_id: u128,
+    // the terms the owner agreed to, as bidders choose the ledger they escrow on
+    pub ledger: Principal,
+    pub amount: u128,
+}
+
+pub type MakeOfferResult = Result<u128, MarketError>;
+
+pub type AcceptOfferResult = Result<u128, MarketError>;
@@ -0,0 +1,92 @@
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::{storable::Bound, Storable};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+use crate::errors::MediaError;
+
+// Metadata text values of the form `sha256:<hex>` reference media stored by the collection
+pub static MEDIA_REF_PREFIX: &str = "sha256:";
+
+// Content-addressed media, stored once however many token metadata reference it
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MediaAsset {
+    pub sha256: ByteBuf,
+    pub content_type: String,
+    pub size: u64,
+    pub chunks: u32,
+    // the token and series metadata referencing it
+    pub refs: u64,
+    pub created_at: u64,
+}
+
+impl Storable for MediaAsset {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// An upload in progress, its chunks appended in order
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Upload {
+    pub id: u128,
+    pub uploader: Principal,
+    pub content_type: String,
+    pub sha256: Option<ByteBuf>,
+    pub size: u64,
+    pub chunks: u32,
+    pub created_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MediaQuota {
+    pub max_asset_size: u64,
+    // committed media and uploads in progress together
+    pub max_total_size: u64,
+    pub max_uploads: u32,
+}
+
+impl Default for MediaQuota {
+    fn default() -> Self {
+        Self {
+            max_asset_size: 2 * 1024 * 1024,
+            max_total_size: 1024 * 1024 * 1024,
+            max_uploads: 16,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CreateUploadArg {
+    pub content_type: String,
+    // checked on commit when set
+    pub sha256: Option<ByteBuf>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct UploadChunkArg {
+    pub upload_id: u128,
+    pub content: ByteBuf,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MediaUsage {
+    pub stored_size: u64,
+    pub pending_size: u64,
+    pub assets: u64,
+    pub quota: MediaQuota,
+}
+
+// The upload id
+pub type CreateUploadResult = Result<u128, MediaError>;
+
+pub type UploadChunkResult = Result<(), MediaError>;
+
+// The stored media, the existing one when the content was already stored
+pub type CommitUploadResult = Result<MediaAsset, MediaError>;
@@ -0,0 +1,90 @@
+use candid::{CandidType, Deserialize, Principal};
+use icrc_ledger_types::icrc1::account::Account;
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use crate::{
+    icrc37_types::{CollectionApprovalInfo, LedgerInfo, TokenApprovalInfo},
+    icrc3_types::{ArchiveSetting, TransactionRange},
+    icrc7_types::{Finalization, Icrc7TokenMetadata, RoyaltyRecipient, TransferPolicy},
+    market_types::MarketFee,
+    media_types::MediaQuota,
+    multisig_types::MultisigConfig,
+    rbac_types::Role,
+    revenue_types::Stakeholder,
+    schema_types::MetadataSchema,
+    rental_types::TokenUser,
+    series_types::{Edition, Series},
+};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TokenExport {
+    pub token_id: u128,
+    pub owner: Account,
+    // the token's own metadata, the edition overrides for a series print
+    pub meta: Icrc7TokenMetadata,
+    pub edition: Option<Edition>,
+    pub user: Option<TokenUser>,
+    pub royalties: Option<Vec<RoyaltyRecipient>>,
+    pub locked: bool,
+    // set for an equipped token, owned by its parent's token account
+    pub parent: Option<u128>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TokenApprovalsExport {
+    pub token_id: u128,
+    pub approvals: TokenApprovalInfo,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CollectionApprovalsExport {
+    pub owner: Account,
+    pub approvals: CollectionApprovalInfo,
+}
+
+// The configuration held by the collection, which the blocks do not record
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ConfigExport {
+    pub owner: Option<Principal>,
+    pub roles: Vec<(Principal, Vec<Role>)>,
+    pub minting_authority: Option<Account>,
+    pub symbol: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub logo: Option<String>,
+    pub supply_cap: Option<u128>,
+    pub total_supply: u128,
+    pub next_token_id: u128,
+    pub max_query_batch_size: Option<u16>,
+    pub max_update_batch_size: Option<u16>,
+    pub max_take_value: Option<u128>,
+    pub default_take_value: Option<u128>,
+    pub max_memo_size: Option<u32>,
+    pub atomic_batch_transfers: Option<bool>,
+    pub tx_window: Option<u64>,
+    pub permitted_drift: Option<u64>,
+    pub approval_ledger_info: LedgerInfo,
+    pub archive_setting: ArchiveSetting,
+    pub royalties: Vec<RoyaltyRecipient>,
+    pub revenue_split: Vec<Stakeholder>,
+    pub market_fee: Option<MarketFee>,
+    pub transfer_policy: TransferPolicy,
+    pub multisig: Option<MultisigConfig>,
+    pub finalization: Option<Finalization>,
+    pub metadata_schema: Option<MetadataSchema>,
+    pub trait_keys: Vec<String>,
+    pub media_quota: MediaQuota,
+    pub assets_canister: Option<Principal>,
+    pub series: Vec<Series>,
+    pub paused: bool,
+}
+
+// Where the block log lives: the archives hold the blocks before `first_local_block`, the
+// collection the ones up to `log_length`, the last of which hashes to `last_hash`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ArchivesExport {
+    pub archives: Vec<(Principal, TransactionRange)>,
+    pub first_local_block: u128,
+    pub log_length: u128,
+    pub last_hash: Option<ByteBuf>,
+}
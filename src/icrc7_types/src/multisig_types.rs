@@ -0,0 +1,133 @@
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::{storable::Bound, Storable};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compliance_types::{FreezeArg, PauseArg},
+    errors::MultisigError,
+    icrc7_types::{
+        FinalizeArg, MintArg, RoyaltyRecipient, UpdateCollectionConfigArg, UpdateTokenMetadataArg,
+    },
+    market_types::MarketFee,
+    media_types::MediaQuota,
+    rbac_types::RoleArg,
+    revenue_types::Stakeholder,
+    schema_types::MetadataSchema,
+    series_types::{CreateSeriesArg, MintEditionArg},
+};
+
+// While set, the privileged endpoints are closed and their calls must go through proposals
+// approved by `threshold` of the `signers`
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MultisigConfig {
+    pub signers: Vec<Principal>,
+    pub threshold: u16,
+    // nanoseconds a proposal stays open for approvals
+    pub proposal_ttl: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub enum ProposalAction {
+    Mint(Vec<MintArg>),
+    CreateSeries(CreateSeriesArg),
+    MintEditions(Vec<MintEditionArg>),
+    UpdateTokenMetadata(UpdateTokenMetadataArg),
+    UpdateCollectionConfig(Box<UpdateCollectionConfigArg>),
+    SetMarketFee(Option<MarketFee>),
+    SetRoyalties(Vec<RoyaltyRecipient>),
+    SetTokenRoyalties {
+        token_id: u128,
+        royalties: Option<Vec<RoyaltyRecipient>>,
+    },
+    SetRevenueSplit(Vec<Stakeholder>),
+    GrantRole(RoleArg),
+    RevokeRole(RoleArg),
+    TransferOwnership(Principal),
+    Pause(PauseArg),
+    Unpause(PauseArg),
+    Freeze(FreezeArg),
+    Unfreeze(FreezeArg),
+    LockTokens(Vec<u128>),
+    UnlockTokens(Vec<u128>),
+    DeleteMedia { sha256: Vec<u8> },
+    SetMediaQuota(MediaQuota),
+    SetMetadataSchema(Option<MetadataSchema>),
+    SetTraitKeys(Vec<String>),
+    // allows the launchpad to upgrade the collection to the wasm with this sha256 hash
+    AuthorizeUpgrade { wasm_hash: Vec<u8> },
+    Finalize(FinalizeArg),
+    // retries dropping the launchpad's control once the collection is finalized
+    RemoveLaunchpad,
+    // `None` leaves the multisig mode
+    SetMultisig(Option<MultisigConfig>),
+}
+
+impl ProposalAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProposalAction::Mint(_) => "mint",
+            ProposalAction::CreateSeries(_) => "create_series",
+            ProposalAction::MintEditions(_) => "mint_editions",
+            ProposalAction::UpdateTokenMetadata(_) => "update_token_metadata",
+            ProposalAction::UpdateCollectionConfig(_) => "update_collection_config",
+            ProposalAction::SetMarketFee(_) => "set_market_fee",
+            ProposalAction::SetRoyalties(_) => "set_royalties",
+            ProposalAction::SetTokenRoyalties { .. } => "set_token_royalties",
+            ProposalAction::SetRevenueSplit(_) => "set_revenue_split",
+            ProposalAction::GrantRole(_) => "grant_role",
+            ProposalAction::RevokeRole(_) => "revoke_role",
+            ProposalAction::TransferOwnership(_) => "transfer_ownership",
+            ProposalAction::Pause(_) => "pause",
+            ProposalAction::Unpause(_) => "unpause",
+            ProposalAction::Freeze(_) => "freeze",
+            ProposalAction::Unfreeze(_) => "unfreeze",
+            ProposalAction::LockTokens(_) => "lock_tokens",
+            ProposalAction::UnlockTokens(_) => "unlock_tokens",
+            ProposalAction::DeleteMedia { .. } => "delete_media",
+            ProposalAction::SetMediaQuota(_) => "set_media_quota",
+            ProposalAction::SetMetadataSchema(_) => "set_metadata_schema",
+            ProposalAction::SetTraitKeys(_) => "set_trait_keys",
+            ProposalAction::AuthorizeUpgrade { .. } => "authorize_upgrade",
+            ProposalAction::Finalize(_) => "finalize",
+            ProposalAction::RemoveLaunchpad => "remove_launchpad",
+            ProposalAction::SetMultisig(_) => "set_multisig",
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum ProposalStatus {
+    Open,
+    Executed { at: u64, result: Vec<String> },
+    Failed { at: u64, message: String },
+    Expired,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Proposal {
+    pub id: u128,
+    pub proposer: Principal,
+    pub action: ProposalAction,
+    pub approvals: Vec<Principal>,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub status: ProposalStatus,
+}
+
+impl Storable for Proposal {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// The proposal id
+pub type ProposeResult = Result<u128, MultisigError>;
+
+// The proposal status after the approval, `Executed` or `Failed` when it reached the threshold
+pub type ApproveProposalResult = Result<ProposalStatus, MultisigError>;
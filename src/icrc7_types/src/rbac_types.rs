@@ -0,0 +1,37 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::RoleError;
+
+// The owner implicitly holds every role, admins every role but the owner's
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Owner,
+    Admin,
+    Minter,
+    MetadataEditor,
+    Pauser,
+    Treasurer,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Admin => "admin",
+            Role::Minter => "minter",
+            Role::MetadataEditor => "metadata_editor",
+            Role::Pauser => "pauser",
+            Role::Treasurer => "treasurer",
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct RoleArg {
+    pub principal: Principal,
+    pub role: Role,
+}
+
+// The block index of the role change
+pub type RoleResult = Result<u128, RoleError>;
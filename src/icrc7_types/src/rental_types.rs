@@ -0,0 +1,26 @@
+use candid::CandidType;
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::SetUserError;
+
+// ERC-4907 style user of a token: it can use the token until `expires_at`, but not move it
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct TokenUser {
+    pub user: Account,
+    // nanoseconds since the epoch, like `ApprovalInfo::expires_at`
+    pub expires_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SetUserArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    // `None` ends the current rental
+    pub user: Option<Account>,
+    pub expires_at: u64,
+    pub memo: Option<Vec<u8>>,
+}
+
+// The block index of the assignment
+pub type SetUserResult = Result<u128, SetUserError>;
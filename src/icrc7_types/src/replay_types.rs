@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use candid::{CandidType, Deserialize};
+use icrc_ledger_types::{
+    icrc::generic_value::{Hash, Value},
+    icrc1::account::Account,
+};
+use serde::Serialize;
+use crate::{
+    icrc3_types::{Block, QueryBlock},
+    icrc7_types::{Icrc7TokenMetadata, Transaction},
+};
+
+// spender -> expiry
+pub type Approvals = BTreeMap<Account, Option<u64>>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReplayedToken {
+    pub owner: Account,
+    // as recorded by the blocks: merged at mint, then replaced by each update
+    pub meta: Icrc7TokenMetadata,
+    // owner -> approvals, cleared whenever the token changes hands
+    pub approvals: BTreeMap<Account, Approvals>,
+}
+
+// The tokens, owners, metadata and approvals the blocks before `next_block` lead to
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ReplayedState {
+    pub next_block: u128,
+    pub last_hash: Option<Hash>,
+    pub tokens: BTreeMap<u128, ReplayedToken>,
+    pub collection_approvals: BTreeMap<Account, Approvals>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum ReplayMismatch {
+    // minted by the blocks but missing from the live state
+    MissingToken { token_id: u128 },
+    // in the live state but never minted by the blocks
+    UnexpectedToken { token_id: u128 },
+    Owner {
+        token_id: u128,
+        live: Account,
+        replayed: Account,
+    },
+    Metadata { token_id: u128 },
+    TokenApprovals { token_id: u128 },
+    CollectionApprovals { owner: Account },
+    LastHash,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ReplayReport {
+    pub blocks: u128,
+    pub tokens: u64,
+    // the first mismatches found, `truncated` telling whether there were more
+    pub mismatches: Vec<ReplayMismatch>,
+    pub truncated: bool,
+}
+
+impl ReplayedState {
+    // Applies the next block of the log, which must link to the previous one
+    pub fn apply(&mut self, id: u128, block: &Value) -> Result<Transaction, String> {
+        if id != self.next_block {
+            return Err(format!("Expected block {}, got block {id}", self.next_block));
+        }
+        let phash = match block {
+            Value::Map(map) => map.get("phash"),
+            _ => None,
+        };
+        let phash = match phash {
+            Some(Value::Blob(phash)) => Some(
+                Hash::try_from(phash.as_slice())
+                    .map_err(|_| format!("Block {id} has an invalid phash"))?,
+            ),
+            Some(_) => return Err(format!("Block {id} has an invalid phash")),
+            None => None,
+        };
+        if phash != self.last_hash {
+            return Err(format!("The phash of block {id} breaks the chain"));
+        }
+        let txn = Block::try_from(block.clone())
+            .and_then(|block| block.transaction())
+            .map_err(|e| format!("Block {id}: {e}"))?;
+        self.apply_transaction(id, &txn)?;
+        self.last_hash = Some(block.hash());
+        self.next_block += 1;
+        Ok(txn)
+    }
+
+    fn apply_transaction(&mut self, id: u128, txn: &Transaction) -> Result<(), String> {
+        let missing = |key: &str| format!("Block {id} has no {key}");
+        let tid = txn.tid;
+        match txn.op.as_str() {
+            "7mint" => {
+                if self.tokens.contains_key(&tid) {
+                    return Err(format!("Block {id} mints the existing token {tid}"));
+                }
+                let token = ReplayedToken {
+                    owner: txn.to.ok_or_else(|| missing("to"))?,
+                    meta: txn.meta.clone().unwrap_or_default(),
+                    approvals: BTreeMap::new(),
+                };
+                self.tokens.insert(tid, token);
+            }
+            "7xfer" | "37xfer" | "7burn" | "7equip" | "7unequip" => {
+                let to = txn.to.ok_or_else(|| missing("to"))?;
+                let token = self.token_mut(id, tid)?;
+                if txn.from != Some(token.owner) {
+                    return Err(format!("Block {id} moves token {tid} from a non owner"));
+                }
+                token.owner = to;
+                token.approvals.clear();
+            }
+            "7update" => {
+                let meta = txn.meta.clone().ok_or_else(|| missing("meta"))?;
+                self.token_mut(id, tid)?.meta = meta;
+            }
+            "37appr" => {
+                let from = txn.from.ok_or_else(|| missing("from"))?;
+                let spender = txn.spender.ok_or_else(|| missing("spender"))?;
+                let token = self.token_mut(id, tid)?;
+                token.approvals.entry(from).or_default().insert(spender, txn.exp);
+            }
+            "37revoke" => {
+                let from = txn.from.ok_or_else(|| missing("from"))?;
+                let token = self.token_mut(id, tid)?;
+                revoke(&mut token.approvals, from, txn.spender);
+            }
+            "37appr_coll" => {
+                let from = txn.from.ok_or_else(|| missing("from"))?;
+                let spender = txn.spender.ok_or_else(|| missing("spender"))?;
+                self.collection_approvals
+                    .entry(from)
+                    .or_default()
+                    .insert(spender, txn.exp);
+            }
+            "37revoke_coll" => {
+                let from = txn.from.ok_or_else(|| missing("from"))?;
+                revoke(&mut self.collection_approvals, from, txn.spender);
+            }
+            // the other blocks leave the tokens and approvals as they are
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn token_mut(&mut self, id: u128, tid: u128) -> Result<&mut ReplayedToken, String> {
+        self.tokens
+            .get_mut(&tid)
+            .ok_or_else(|| format!("Block {id} refers to the unminted token {tid}"))
+    }
+}
+
+// Without a spender, all the approvals of the owner go
+fn revoke(approvals: &mut BTreeMap<Account, Approvals>, from: Account, spender: Option<Account>) {
+    match spender {
+        Some(spender) => {
+            if let Some(spenders) = approvals.get_mut(&from) {
+                spenders.remove(&spender);
+                if spenders.is_empty() {
+                    approvals.remove(&from);
+                }
+            }
+        }
+        None => {
+            approvals.remove(&from);
+        }
+    }
+}
+
+// Rebuilds the state from the whole block stream, the archived blocks followed by the
+// ledger's own, the same blocks always giving the same state
+pub fn replay(blocks: impl IntoIterator<Item = QueryBlock>) -> Result<ReplayedState, String> {
+    let mut state = ReplayedState::default();
+    for QueryBlock { id, block } in blocks {
+        state.apply(id, &block)?;
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    fn account(n: u8) -> Account {
+        Account {
+            owner: Principal::from_slice(&[n]),
+            subaccount: None,
+        }
+    }
+
+    fn chain(txns: Vec<Transaction>) -> Vec<QueryBlock> {
+        let mut phash = None;
+        let mut blocks = vec![];
+        for (id, txn) in txns.into_iter().enumerate() {
+            let block = Block::new(phash, txn).into_inner();
+            phash = Some(block.hash());
+            blocks.push(QueryBlock {
+                id: id as u128,
+                block,
+            });
+        }
+        blocks
+    }
+
+    #[test]
+    fn blocks_replay_into_tokens_and_approvals() {
+        let (alice, bob, carol) = (account(1), account(2), account(3));
+        let meta = Icrc7TokenMetadata::from([("name".to_string(), Value::text("Rose"))]);
+        let updated = Icrc7TokenMetadata::from([("name".to_string(), Value::text("Tulip"))]);
+        let blocks = chain(vec![
+            Transaction::mint(1, 1, Some(alice), alice, meta.clone(), None),
+            Transaction::mint(2, 2, Some(alice), alice, meta, None),
+            Transaction::approve(3, 1, alice, bob, Some(10), None),
+            Transaction::approve(4, 2, alice, bob, None, None),
+            Transaction::approve(5, 2, alice, carol, None, None),
+            Transaction::revoke(6, 2, alice, Some(bob), None),
+            Transaction::transfer_from(7, 1, alice, carol, bob, None),
+            Transaction::update(8, 2, alice, updated.clone(), None),
+            Transaction::approve_collection(9, carol, alice, None, None),
+        ]);
+
+        let state = replay(blocks.clone()).unwrap();
+        assert_eq!(state, replay(blocks.clone()).unwrap());
+        assert_eq!(state.next_block, 9);
+        assert_eq!(state.last_hash, Some(blocks[8].block.hash()));
+        let token = &state.tokens[&1];
+        assert_eq!(token.owner, carol);
+        assert!(token.approvals.is_empty());
+        let token = &state.tokens[&2];
+        assert_eq!(token.meta, updated);
+        assert_eq!(token.approvals[&alice], Approvals::from([(carol, None)]));
+        assert_eq!(state.collection_approvals[&carol], Approvals::from([(alice, None)]));
+
+        let block = Block::try_from(blocks[6].block.clone()).unwrap();
+        let txn = block.transaction().unwrap();
+        assert_eq!((txn.op.as_str(), txn.spender, txn.to), ("37xfer", Some(bob), Some(carol)));
+
+        let mut tampered = blocks.clone();
+        tampered.remove(4);
+        assert!(replay(tampered).is_err());
+        let stolen = chain(vec![
+            Transaction::mint(1, 1, Some(alice), alice, Default::default(), None),
+            Transaction::transfer(2, 1, bob, carol, None),
+        ]);
+        assert!(replay(stolen).is_err());
+    }
+}
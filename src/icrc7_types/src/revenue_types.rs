@@ -0,0 +1,59 @@
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::{storable::Bound, Storable};
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::RevenueError;
+
+// A party entitled to a share of the primary-sale revenue (artist, launchpad, developers...)
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Stakeholder {
+    pub name: String,
+    pub account: Account,
+    pub bps: u16,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub enum RevenueEntryKind {
+    Credit { shares: Vec<(Account, u128)> },
+    Withdrawal { account: Account, to: Account, block_index: u128 },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct RevenueEntry {
+    pub id: u128,
+    pub ledger: Principal,
+    pub amount: u128,
+    pub kind: RevenueEntryKind,
+    pub at: u64,
+}
+
+impl Storable for RevenueEntry {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct DepositRevenueArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub ledger: Principal,
+    pub amount: u128,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct WithdrawArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub ledger: Principal,
+    pub to: Option<Account>,
+}
+
+pub type DepositRevenueResult = Result<u128, RevenueError>;
+
+pub type WithdrawResult = Result<u128, RevenueError>;
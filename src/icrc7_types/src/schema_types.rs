@@ -0,0 +1,57 @@
+use candid::CandidType;
+use icrc_ledger_types::icrc::generic_value::Value;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ValueType {
+    Text,
+    // Nat64 values included
+    Nat,
+    Int,
+    Blob,
+    Map,
+    Array,
+}
+
+impl ValueType {
+    pub fn of(value: &Value) -> Self {
+        match value {
+            Value::Text(_) => ValueType::Text,
+            Value::Nat(_) | Value::Nat64(_) => ValueType::Nat,
+            Value::Int(_) => ValueType::Int,
+            Value::Blob(_) => ValueType::Blob,
+            Value::Map(_) => ValueType::Map,
+            Value::Array(_) => ValueType::Array,
+        }
+    }
+}
+
+// The constraints on the value of one metadata key
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FieldSchema {
+    pub value_type: ValueType,
+    pub required: bool,
+    // an enumeration: the only values accepted
+    pub allowed_values: Option<Vec<Value>>,
+    // bytes of a text or blob, entries of an array or map
+    pub max_size: Option<u64>,
+}
+
+// The shape the token metadata of a collection must have, checked on mint and update. For
+// editions, the series metadata with the edition overrides is what gets checked.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct MetadataSchema {
+    pub fields: BTreeMap<String, FieldSchema>,
+    // whether keys the schema does not list are accepted
+    pub allow_extra_keys: bool,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SchemaViolation {
+    MissingKey { key: String },
+    UnexpectedKey { key: String },
+    WrongType { key: String, expected: ValueType },
+    ValueNotAllowed { key: String },
+    TooLarge { key: String, max_size: u64 },
+}
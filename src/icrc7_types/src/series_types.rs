@@ -0,0 +1,62 @@
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::{storable::Bound, Storable};
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use serde::{Deserialize, Serialize};
+
+use crate::icrc7_types::Icrc7TokenMetadata;
+
+pub static METADATA_SERIES: &str = "icrc7:series";
+pub static METADATA_EDITION: &str = "icrc7:edition";
+pub static METADATA_MAX_EDITIONS: &str = "icrc7:max_editions";
+
+// A master artwork whose metadata is shared by all its numbered prints
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Series {
+    pub id: u128,
+    pub name: String,
+    pub meta: Icrc7TokenMetadata,
+    pub max_editions: Option<u32>,
+    // editions minted so far, the last edition number given out
+    pub minted: u32,
+    pub created_at: u64,
+}
+
+impl Storable for Series {
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// Attached to the tokens that are editions of a series
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Edition {
+    pub series_id: u128,
+    pub number: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CreateSeriesArg {
+    pub name: String,
+    pub meta: Icrc7TokenMetadata,
+    pub max_editions: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MintEditionArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub series_id: u128,
+    pub to: Account,
+    pub token_id: Option<u128>,
+    // per-edition values, taking precedence over the master metadata
+    pub overrides: Option<Icrc7TokenMetadata>,
+    pub memo: Option<Vec<u8>>,
+}
+
+// The series id
+pub type CreateSeriesResult = Result<u128, String>;
@@ -0,0 +1,25 @@
+use candid::{CandidType, Principal};
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use serde::Deserialize;
+
+use crate::errors::TokenAccountError;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TokenWithdrawArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    pub ledger: Principal,
+    pub to: Account,
+    // the amount received, the ledger fee being charged to the token account on top
+    pub amount: u128,
+    pub memo: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TokenBalanceArg {
+    pub token_id: u128,
+    pub ledger: Principal,
+}
+
+// The ledger block index of the withdrawal
+pub type TokenWithdrawResult = Result<u128, TokenAccountError>;
@@ -0,0 +1,42 @@
+use candid::CandidType;
+use icrc_ledger_types::icrc::generic_value::Value;
+use serde::{Deserialize, Serialize};
+
+// A metadata key and value, numbers being written in decimal
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Trait {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum TraitFilter {
+    Trait(Trait),
+    And(Vec<TraitFilter>),
+    Or(Vec<TraitFilter>),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TokensByTraitArg {
+    pub filter: TraitFilter,
+    pub prev: Option<u128>,
+    pub take: Option<u128>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct TraitCount {
+    pub key: String,
+    pub value: String,
+    pub count: u64,
+}
+
+// The indexed form of a trait value: texts and numbers only
+pub fn trait_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Text(text) => Some(text.clone()),
+        Value::Nat(nat) => Some(nat.0.to_string()),
+        Value::Nat64(nat) => Some(nat.to_string()),
+        Value::Int(int) => Some(int.0.to_string()),
+        _ => None,
+    }
+}